@@ -1,17 +1,32 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write as _;
 use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 
 use anyhow::{Context, Result};
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use humansize::{format_size, BINARY};
 use walkdir::{DirEntry, WalkDir};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::command::CommandRunner;
 use crate::config::{Rule, RuleKind};
 use crate::options::{DownloadsChoice, ScanOptions};
 
+mod containers;
+mod crashes;
+mod flatpak;
+mod journal;
+mod kernels;
+mod nix;
+mod orphans;
+mod ostree;
+mod packages;
+mod snap;
+
 #[derive(Debug, Clone)]
 pub struct RuleScan {
     pub rule: Rule,
@@ -21,6 +36,69 @@ pub struct RuleScan {
     pub dirs: Vec<PathBuf>,
     pub errors: usize,
     pub error_messages: Vec<String>,
+    /// When set, only entries owned by this uid are listed or removed.
+    pub owner: Option<u32>,
+    /// Tools to run on apply, for rules that don't delete files themselves.
+    pub commands: Vec<CommandAction>,
+    /// Packages the commands remove, listed one by one so they can be
+    /// reviewed and deselected.
+    pub packages: Vec<Package>,
+    /// Detail shown next to a listed path, e.g. the crashing executable of a
+    /// core dump.
+    pub notes: HashMap<PathBuf, String>,
+    pub runner: CommandRunner,
+}
+
+/// A cleanup command a rule runs on apply, e.g. `rpm-ostree cleanup -p -r`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandAction {
+    pub description: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Estimated space the command frees.
+    pub bytes: u64,
+}
+
+/// A package a rule's command removes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    /// Installed size.
+    pub bytes: u64,
+}
+
+impl CommandAction {
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl RuleScan {
+    pub fn new(rule: &Rule, options: &ScanOptions) -> Self {
+        Self {
+            rule: rule.clone(),
+            bytes: 0,
+            entries: 0,
+            files: Vec::new(),
+            dirs: Vec::new(),
+            errors: 0,
+            error_messages: Vec::new(),
+            owner: options.owner,
+            commands: Vec::new(),
+            packages: Vec::new(),
+            notes: HashMap::new(),
+            runner: options.runner.clone(),
+        }
+    }
+
+    fn push_command(&mut self, action: CommandAction) {
+        self.bytes += action.bytes;
+        self.entries += 1;
+        self.commands.push(action);
+    }
 }
 
 #[derive(Debug, Default)]
@@ -28,13 +106,34 @@ pub struct CleanReport {
     pub files_removed: usize,
     pub dirs_removed: usize,
     pub bytes_freed: u64,
+    pub commands_run: usize,
+    /// Duplicates replaced by a hardlink or reflink to another copy.
+    pub files_linked: usize,
+    /// Entries moved to the Trash instead of deleted.
+    pub files_trashed: usize,
+    pub bytes_trashed: u64,
     pub errors: usize,
 }
 
+impl CleanReport {
+    pub fn merge(mut self, other: CleanReport) -> CleanReport {
+        self.files_removed += other.files_removed;
+        self.dirs_removed += other.dirs_removed;
+        self.commands_run += other.commands_run;
+        self.files_linked += other.files_linked;
+        self.files_trashed += other.files_trashed;
+        self.bytes_trashed += other.bytes_trashed;
+        self.bytes_freed += other.bytes_freed;
+        self.errors += other.errors;
+        self
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct DryRunReport {
     pub files_listed: usize,
     pub dirs_listed: usize,
+    pub commands_listed: usize,
     pub bytes_listed: u64,
     pub errors: usize,
 }
@@ -53,53 +152,107 @@ pub fn scan_rules(rules: &[Rule], options: &ScanOptions) -> Vec<RuleScan> {
     rules.par_iter().map(|rule| scan_rule(rule, options)).collect()
 }
 
+/// Applies scans tier by tier so the cheapest-to-regenerate data goes first.
+/// Scans within a tier run in parallel.
 pub fn apply(scans: &[RuleScan]) -> CleanReport {
+    tiers(scans.iter())
+        .into_iter()
+        .fold(CleanReport::default(), |report, tier| {
+            report.merge(apply_tier(scans, tier))
+        })
+}
+
+/// [`apply`] for several groups of scans (one per user in multi-user mode):
+/// each tier is applied for every group before the next tier starts, so no
+/// user's expensive data goes before another's cheap data. Returns a report
+/// per group.
+pub fn apply_groups(groups: &[Vec<RuleScan>]) -> Vec<CleanReport> {
+    let mut reports = groups
+        .iter()
+        .map(|_| CleanReport::default())
+        .collect::<Vec<_>>();
+    for tier in tiers(groups.iter().flatten()) {
+        for (report, scans) in reports.iter_mut().zip(groups) {
+            *report = std::mem::take(report).merge(apply_tier(scans, tier));
+        }
+    }
+    reports
+}
+
+/// The distinct tiers of `scans`, lowest first.
+fn tiers<'a>(scans: impl Iterator<Item = &'a RuleScan>) -> Vec<u8> {
+    let mut tiers = scans.map(|scan| scan.rule.tier).collect::<Vec<_>>();
+    tiers.sort_unstable();
+    tiers.dedup();
+    tiers
+}
+
+fn apply_tier(scans: &[RuleScan], tier: u8) -> CleanReport {
     scans
         .par_iter()
-        .map(|scan| {
-            let mut report = CleanReport::default();
-            for path in &scan.files {
-                match fs::symlink_metadata(path) {
-                    Ok(meta) => {
-                        let size = meta.len();
-                        if fs::remove_file(path).is_ok() {
-                            report.bytes_freed += size;
-                            report.files_removed += 1;
-                        } else {
-                            report.errors += 1;
-                        }
-                    }
-                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                        continue;
-                    }
-                    Err(_) => {
-                        report.errors += 1;
-                    }
+        .filter(|scan| scan.rule.tier == tier)
+        .map(apply_scan)
+        .reduce(CleanReport::default, CleanReport::merge)
+}
+
+fn apply_scan(scan: &RuleScan) -> CleanReport {
+    let mut report = CleanReport::default();
+    for path in &scan.files {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if scan.owner.is_some_and(|uid| meta.uid() != uid) => {
+                report.errors += 1;
+            }
+            Ok(meta) => {
+                let size = meta.len();
+                if fs::remove_file(path).is_ok() {
+                    report.bytes_freed += size;
+                    report.files_removed += 1;
+                } else {
+                    report.errors += 1;
                 }
             }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(_) => {
+                report.errors += 1;
+            }
+        }
+    }
 
-            let mut dirs = scan.dirs.clone();
-            dirs.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
-            for dir in dirs {
-                match fs::remove_dir(&dir) {
-                    Ok(_) => report.dirs_removed += 1,
-                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                        continue;
-                    }
-                    Err(_) => {
-                        report.errors += 1;
-                    }
-                }
+    let mut dirs = scan.dirs.clone();
+    dirs.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+    for dir in dirs {
+        if let (Some(uid), Ok(meta)) = (scan.owner, fs::symlink_metadata(&dir)) {
+            if meta.uid() != uid {
+                report.errors += 1;
+                continue;
             }
-            report
-        })
-        .reduce(CleanReport::default, |mut a, b| {
-            a.files_removed += b.files_removed;
-            a.dirs_removed += b.dirs_removed;
-            a.bytes_freed += b.bytes_freed;
-            a.errors += b.errors;
-            a
-        })
+        }
+        match fs::remove_dir(&dir) {
+            Ok(_) => report.dirs_removed += 1,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                continue;
+            }
+            Err(_) => {
+                report.errors += 1;
+            }
+        }
+    }
+
+    for action in &scan.commands {
+        match scan.runner.output(&action.program, &action.args) {
+            Ok(_) => {
+                report.commands_run += 1;
+                report.bytes_freed += action.bytes;
+            }
+            Err(err) => {
+                eprintln!("{}: {err:#}", action.description);
+                report.errors += 1;
+            }
+        }
+    }
+    report
 }
 
 pub fn dry_run_output(scans: &[RuleScan]) -> DryRunOutput {
@@ -113,8 +266,27 @@ pub fn dry_run_output(scans: &[RuleScan]) -> DryRunOutput {
     );
     for scan in scans {
         let _ = writeln!(details, "Rule: {} ({})", scan.rule.label, scan.rule.id);
+        for action in &scan.commands {
+            let _ = writeln!(
+                details,
+                "  run: {} ({}, ~{})",
+                action.command_line(),
+                action.description,
+                format_size(action.bytes, BINARY)
+            );
+        }
+        for package in &scan.packages {
+            let _ = writeln!(
+                details,
+                "  package: {} ({})",
+                package.name,
+                format_size(package.bytes, BINARY)
+            );
+        }
         if scan.files.is_empty() && scan.dirs.is_empty() {
-            let _ = writeln!(details, "  (no entries)");
+            if scan.commands.is_empty() {
+                let _ = writeln!(details, "  (no entries)");
+            }
         } else if scan.rule.kind == RuleKind::Downloads {
             let summary_dirs = summarize_download_dirs(&scan.dirs);
             let mut suppressed = 0usize;
@@ -141,11 +313,17 @@ pub fn dry_run_output(scans: &[RuleScan]) -> DryRunOutput {
                 }
             }
         } else {
+            let note = |path: &PathBuf| {
+                scan.notes
+                    .get(path)
+                    .map(|note| format!(" ({note})"))
+                    .unwrap_or_default()
+            };
             for path in &scan.files {
-                let _ = writeln!(details, "  file: {}", path.display());
+                let _ = writeln!(details, "  file: {}{}", path.display(), note(path));
             }
             for path in &scan.dirs {
-                let _ = writeln!(details, "  dir: {}", path.display());
+                let _ = writeln!(details, "  dir: {}{}", path.display(), note(path));
             }
         }
         if !scan.error_messages.is_empty() {
@@ -156,6 +334,7 @@ pub fn dry_run_output(scans: &[RuleScan]) -> DryRunOutput {
         }
         report.files_listed += scan.files.len();
         report.dirs_listed += scan.dirs.len();
+        report.commands_listed += scan.commands.len();
         report.bytes_listed += scan.bytes;
         report.errors += scan.errors;
     }
@@ -183,22 +362,23 @@ pub fn remove_dry_run_report(home: &Path) {
 
 pub fn scan_rule(rule: &Rule, options: &ScanOptions) -> RuleScan {
     match rule.kind {
-        RuleKind::Paths => scan_paths_rule(rule),
-        RuleKind::Downloads => scan_downloads_rule(rule, options.downloads_choice),
-        RuleKind::Logs => scan_logs_rule(rule),
+        RuleKind::Paths => scan_paths_rule(rule, options),
+        RuleKind::Downloads => scan_downloads_rule(rule, options),
+        RuleKind::Logs => scan_logs_rule(rule, options),
+        RuleKind::RpmOstree => ostree::scan(rule, options),
+        RuleKind::Nix => nix::scan(rule, options),
+        RuleKind::Flatpak => flatpak::scan(rule, options),
+        RuleKind::Snap => snap::scan(rule, options),
+        RuleKind::Kernels => kernels::scan(rule, options),
+        RuleKind::Orphans => orphans::scan(rule, options),
+        RuleKind::Journal => journal::scan(rule, options),
+        RuleKind::Crashes => crashes::scan(rule, options),
+        RuleKind::Containers => containers::scan(rule, options),
     }
 }
 
-fn scan_logs_rule(rule: &Rule) -> RuleScan {
-    let mut scan = RuleScan {
-        rule: rule.clone(),
-        bytes: 0,
-        entries: 0,
-        files: Vec::new(),
-        dirs: Vec::new(),
-        errors: 0,
-        error_messages: Vec::new(),
-    };
+fn scan_logs_rule(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
 
     let (exclude_set, exclude_errors) = build_globset(&rule.exclude_globs);
     for message in exclude_errors {
@@ -207,7 +387,7 @@ fn scan_logs_rule(rule: &Rule) -> RuleScan {
 
     let cutoff = rule.older_than_days.and_then(cutoff_from_days);
 
-    for root in rule.expanded_paths() {
+    for root in rule_roots(rule, options, &mut scan) {
         if !root.exists() {
             continue;
         }
@@ -258,6 +438,9 @@ fn scan_logs_rule(rule: &Rule) -> RuleScan {
                     if !meta.is_file() {
                         continue;
                     }
+                    if !check_owner(&meta, entry.path(), &mut scan) {
+                        continue;
+                    }
                     if !is_older_than(&meta, cutoff, entry.path(), &mut scan) {
                         continue;
                     }
@@ -311,6 +494,9 @@ fn scan_log_path(
     if meta.file_type().is_symlink() || !meta.is_file() {
         return;
     }
+    if !check_owner(&meta, path, scan) {
+        return;
+    }
     if !is_older_than(&meta, cutoff, path, scan) {
         return;
     }
@@ -319,23 +505,15 @@ fn scan_log_path(
     scan.files.push(path.to_path_buf());
 }
 
-fn scan_paths_rule(rule: &Rule) -> RuleScan {
-    let mut scan = RuleScan {
-        rule: rule.clone(),
-        bytes: 0,
-        entries: 0,
-        files: Vec::new(),
-        dirs: Vec::new(),
-        errors: 0,
-        error_messages: Vec::new(),
-    };
+fn scan_paths_rule(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
 
     let (exclude_set, exclude_errors) = build_globset(&rule.exclude_globs);
     for message in exclude_errors {
         record_error(&mut scan, message);
     }
 
-    for root in rule.expanded_paths() {
+    for root in rule_roots(rule, options, &mut scan) {
         if !root.exists() {
             continue;
         }
@@ -347,22 +525,14 @@ fn scan_paths_rule(rule: &Rule) -> RuleScan {
     scan
 }
 
-fn scan_downloads_rule(rule: &Rule, choice: Option<DownloadsChoice>) -> RuleScan {
-    let mut scan = RuleScan {
-        rule: rule.clone(),
-        bytes: 0,
-        entries: 0,
-        files: Vec::new(),
-        dirs: Vec::new(),
-        errors: 0,
-        error_messages: Vec::new(),
-    };
+fn scan_downloads_rule(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
 
-    let Some(choice) = choice else {
+    let Some(choice) = options.downloads_choice else {
         return scan;
     };
 
-    for root in rule.expanded_paths() {
+    for root in rule_roots(rule, options, &mut scan) {
         let meta = match fs::symlink_metadata(&root) {
             Ok(meta) => meta,
             Err(err) => {
@@ -441,6 +611,7 @@ fn scan_downloads_rule(rule: &Rule, choice: Option<DownloadsChoice>) -> RuleScan
                 continue;
             };
             let size = match entry.metadata() {
+                Ok(meta) if !check_owner(&meta, &path, &mut scan) => continue,
                 Ok(meta) => meta.len(),
                 Err(err) => {
                     record_error(
@@ -466,7 +637,11 @@ fn scan_downloads_rule(rule: &Rule, choice: Option<DownloadsChoice>) -> RuleScan
                     scan.files.push(archive_path);
                 }
                 DownloadsChoice::Folders => {
-                    if seen_dirs.insert(dir_path.clone()) {
+                    let owned = match fs::symlink_metadata(dir_path) {
+                        Ok(meta) => check_owner(&meta, dir_path, &mut scan),
+                        Err(_) => false,
+                    };
+                    if owned && seen_dirs.insert(dir_path.clone()) {
                         for message in scan_root(dir_path, None, &mut scan) {
                             record_error(&mut scan, message);
                         }
@@ -497,8 +672,10 @@ fn archive_base_name(file_name: &str) -> Option<String> {
 fn scan_root(root: &Path, exclude: Option<&GlobSet>, scan: &mut RuleScan) -> Vec<String> {
     if root.is_file() || root.is_symlink() {
         if !is_excluded(root, root, exclude) {
-            if let Ok(meta) = fs::symlink_metadata(root) {
-                scan.bytes += meta.len();
+            match fs::symlink_metadata(root) {
+                Ok(meta) if !check_owner(&meta, root, scan) => return Vec::new(),
+                Ok(meta) => scan.bytes += meta.len(),
+                Err(_) => {}
             }
             scan.entries += 1;
             scan.files.push(root.to_path_buf());
@@ -507,11 +684,25 @@ fn scan_root(root: &Path, exclude: Option<&GlobSet>, scan: &mut RuleScan) -> Vec
     }
 
     let mut errors = Vec::new();
+    let mut skipped = Vec::new();
+    let owner = scan.owner;
     let iter = WalkDir::new(root)
         .follow_links(false)
         .same_file_system(true)
         .into_iter()
-        .filter_entry(|entry| filter_entry(entry, root, exclude));
+        .filter_entry(|entry| {
+            if !filter_entry(entry, root, exclude) {
+                return false;
+            }
+            // Skip whole subtrees that belong to someone else.
+            match (owner, entry.metadata()) {
+                (Some(uid), Ok(meta)) if entry.path() != root && meta.uid() != uid => {
+                    skipped.push(foreign_owner_message(entry.path(), meta.uid(), uid));
+                    false
+                }
+                _ => true,
+            }
+        });
 
     for next in iter {
         match next {
@@ -546,9 +737,53 @@ fn scan_root(root: &Path, exclude: Option<&GlobSet>, scan: &mut RuleScan) -> Vec
         }
     }
 
+    errors.extend(skipped);
     errors
 }
 
+/// Regular files under `roots`, walked the way `paths` rules are (one
+/// filesystem, symlinks not followed, `exclude_globs` relative to each root),
+/// with the errors met on the way.
+pub fn walk_files(
+    roots: &[PathBuf],
+    exclude_globs: &[String],
+) -> (Vec<(PathBuf, fs::Metadata)>, Vec<String>) {
+    let (exclude_set, mut errors) = build_globset(exclude_globs);
+    let exclude = exclude_set.as_ref();
+    let mut files = Vec::new();
+    for root in roots {
+        let iter = WalkDir::new(root)
+            .follow_links(false)
+            .same_file_system(true)
+            .into_iter()
+            .filter_entry(|entry| filter_entry(entry, root, exclude));
+        for next in iter {
+            match next {
+                Ok(entry) if entry.file_type().is_file() => match entry.metadata() {
+                    Ok(meta) => files.push((entry.into_path(), meta)),
+                    Err(err) => errors.push(format!(
+                        "Failed to read metadata for {}: {}",
+                        entry.path().display(),
+                        err
+                    )),
+                },
+                Ok(_) => {}
+                Err(err) => match err.path() {
+                    Some(path) => {
+                        errors.push(format!("Failed to read entry {}: {}", path.display(), err))
+                    }
+                    None => errors.push(format!(
+                        "Failed to read entry under {}: {}",
+                        root.display(),
+                        err
+                    )),
+                },
+            }
+        }
+    }
+    (files, errors)
+}
+
 fn filter_entry(entry: &DirEntry, root: &Path, exclude: Option<&GlobSet>) -> bool {
     if entry.path() == root {
         return true;
@@ -556,6 +791,26 @@ fn filter_entry(entry: &DirEntry, root: &Path, exclude: Option<&GlobSet>) -> boo
     !is_excluded(entry.path(), root, exclude)
 }
 
+/// Returns false (and records why) if `meta` is not owned by the scan's owner.
+fn check_owner(meta: &fs::Metadata, path: &Path, scan: &mut RuleScan) -> bool {
+    match scan.owner {
+        Some(uid) if meta.uid() != uid => {
+            record_error(scan, foreign_owner_message(path, meta.uid(), uid));
+            false
+        }
+        _ => true,
+    }
+}
+
+fn foreign_owner_message(path: &Path, actual: u32, expected: u32) -> String {
+    format!(
+        "Skipped {}: owned by uid {} instead of {}",
+        path.display(),
+        actual,
+        expected
+    )
+}
+
 fn is_excluded(path: &Path, root: &Path, exclude: Option<&GlobSet>) -> bool {
     let Some(exclude) = exclude else {
         return false;
@@ -656,6 +911,31 @@ fn build_globset(patterns: &[String]) -> (Option<GlobSet>, Vec<String>) {
     }
 }
 
+/// Total size of the regular files under `path`, staying on one filesystem.
+fn tree_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_links(false)
+        .same_file_system(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// The rule's paths to scan, recording the ones refused under `--root`.
+fn rule_roots(rule: &Rule, options: &ScanOptions, scan: &mut RuleScan) -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    for path in options.rule_paths(rule) {
+        match path {
+            Ok(path) => roots.push(path),
+            Err(err) => record_error(scan, format!("{err:#}")),
+        }
+    }
+    roots
+}
+
 fn record_error(scan: &mut RuleScan, message: String) {
     scan.errors += 1;
     scan.error_messages.push(message);
@@ -673,6 +953,96 @@ mod tests {
         assert_eq!(archive_base_name(".zip"), None);
     }
 
+    #[test]
+    fn test_apply_groups_goes_tier_by_tier() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        crate::command::testing::write_stub(
+            dir.path(),
+            "mark",
+            &format!("echo \"$1\" >> {}", log.display()),
+        );
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(dir.path()),
+            ..ScanOptions::default()
+        };
+        let scan = |name: &str, tier: u8| {
+            let mut rule = Rule::selection(name, name);
+            rule.tier = tier;
+            let mut scan = RuleScan::new(&rule, &options);
+            scan.push_command(CommandAction {
+                description: name.to_string(),
+                program: "mark".to_string(),
+                args: vec![name.to_string()],
+                bytes: 10,
+            });
+            scan
+        };
+        let groups = [
+            vec![scan("alice-dev", 3), scan("alice-trash", 1)],
+            vec![scan("bob-thumbnails", 1), scan("bob-cache", 2)],
+        ];
+
+        let reports = apply_groups(&groups);
+        let order = fs::read_to_string(&log).unwrap();
+        let order = order.lines().collect::<Vec<_>>();
+        assert_eq!(order.last(), Some(&"alice-dev"));
+        assert_eq!(order[2], "bob-cache");
+        assert_eq!(
+            reports
+                .iter()
+                .map(|report| (report.commands_run, report.bytes_freed))
+                .collect::<Vec<_>>(),
+            [(2, 20), (2, 20)]
+        );
+    }
+
+    #[test]
+    fn test_scan_paths_under_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("var/cache/apt/archives");
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(cache.join("vim.deb"), "0123456789").unwrap();
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "apt", "label": "APT", "paths": ["/var/cache/apt/archives"] }"#,
+        )
+        .unwrap();
+        let options = ScanOptions {
+            root: crate::sysroot::SysRoot::new(dir.path()).unwrap(),
+            ..ScanOptions::default()
+        };
+
+        let scan = scan_rule(&rule, &options);
+        assert_eq!(scan.bytes, 10);
+        assert_eq!(scan.files, [options.root.path().join("var/cache/apt/archives/vim.deb")]);
+    }
+
+    #[test]
+    fn test_scan_under_root_stays_inside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let host = dir.path().join("host");
+        fs::create_dir_all(host.join("apt/archives")).unwrap();
+        fs::write(host.join("apt/archives/host.deb"), "host").unwrap();
+        let image = dir.path().join("image");
+        fs::create_dir_all(image.join("var")).unwrap();
+        // An absolute link that points at the host outside a chroot.
+        std::os::unix::fs::symlink(&host, image.join("var/cache")).unwrap();
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "apt", "label": "APT", "paths": ["/var/cache/apt/archives", "/var/cache"] }"#,
+        )
+        .unwrap();
+        let options = ScanOptions {
+            root: crate::sysroot::SysRoot::new(&image).unwrap(),
+            ..ScanOptions::default()
+        };
+
+        let scan = scan_rule(&rule, &options);
+        assert!(scan.files.is_empty() && scan.dirs.is_empty());
+        assert_eq!(scan.errors, 1);
+        assert!(scan.error_messages[0].contains("is a symlink"));
+        assert!(host.join("apt/archives/host.deb").exists());
+    }
+
     #[test]
     fn test_is_log_file_name() {
         assert!(is_log_file_name(Path::new("app.log")));
@@ -0,0 +1,366 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use super::{record_error, CommandAction, RuleScan};
+use crate::command::CommandRunner;
+use crate::config::{ContainerCategory, Rule};
+use crate::options::ScanOptions;
+use crate::units::parse_size;
+
+const ENGINES: [&str; 2] = ["docker", "podman"];
+
+impl ContainerCategory {
+    /// `Type` of the category's row in `system df`.
+    fn df_type(self) -> &'static str {
+        match self {
+            ContainerCategory::DanglingImages => "Images",
+            ContainerCategory::StoppedContainers => "Containers",
+            ContainerCategory::UnusedVolumes => "Local Volumes",
+            ContainerCategory::BuildCache => "Build Cache",
+        }
+    }
+
+    fn prune_args(self) -> &'static [&'static str] {
+        match self {
+            ContainerCategory::DanglingImages => &["image", "prune", "-f"],
+            ContainerCategory::StoppedContainers => &["container", "prune", "-f"],
+            ContainerCategory::UnusedVolumes => &["volume", "prune", "-f"],
+            ContainerCategory::BuildCache => &["builder", "prune", "-f"],
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ContainerCategory::DanglingImages => "dangling images",
+            ContainerCategory::StoppedContainers => "stopped containers",
+            ContainerCategory::UnusedVolumes => "unused volumes",
+            ContainerCategory::BuildCache => "build cache",
+        }
+    }
+}
+
+/// One row of `system df`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DiskUsage {
+    kind: String,
+    total: u64,
+    active: u64,
+    reclaimable: u64,
+}
+
+/// `--format json` output: docker prints one object per line, podman a
+/// single array.
+fn parse_json_rows(output: &str, command: &str) -> Result<Vec<Value>> {
+    match serde_json::from_str::<Value>(output.trim()) {
+        Ok(Value::Array(rows)) => Ok(rows),
+        _ => output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str::<Value>)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to parse {command} output")),
+    }
+}
+
+/// `system df --format json`. Docker gives counts as strings and sizes only
+/// as text (`"1.2GB (45%)"`); podman also has the raw byte counts.
+fn parse_system_df(output: &str) -> Result<Vec<DiskUsage>> {
+    parse_json_rows(output, "system df")?
+        .iter()
+        .map(parse_row)
+        .collect()
+}
+
+/// Count and total size of the images in `images -f dangling=true --format
+/// json`. Docker gives sizes as text (`"1.2GB"`), podman in bytes.
+fn parse_dangling_images(output: &str) -> Result<(u64, u64)> {
+    let rows = parse_json_rows(output, "images")?;
+    let mut bytes = 0;
+    for row in &rows {
+        bytes += match row.get("Size") {
+            Some(Value::Number(number)) => number.as_u64().unwrap_or(0),
+            Some(Value::String(text)) => {
+                parse_size(text).with_context(|| format!("Invalid image size '{text}'"))?
+            }
+            _ => 0,
+        };
+    }
+    Ok((rows.len() as u64, bytes))
+}
+
+fn parse_row(row: &Value) -> Result<DiskUsage> {
+    let Some(kind) = row.get("Type").and_then(Value::as_str) else {
+        bail!("system df row without a Type: {row}");
+    };
+    let reclaimable = match row.get("RawReclaimable").and_then(Value::as_u64) {
+        Some(bytes) => bytes,
+        None => {
+            let text = row
+                .get("Reclaimable")
+                .and_then(Value::as_str)
+                .unwrap_or("0B");
+            // Drop the `(45%)` share.
+            let size = text.split_whitespace().next().unwrap_or("0B");
+            parse_size(size).with_context(|| format!("Invalid {kind} size '{text}'"))?
+        }
+    };
+    Ok(DiskUsage {
+        kind: kind.to_string(),
+        total: count(row.get("TotalCount")),
+        active: count(row.get("Active")),
+        reclaimable,
+    })
+}
+
+fn count(value: Option<&Value>) -> u64 {
+    match value {
+        Some(Value::Number(number)) => number.as_u64().unwrap_or(0),
+        Some(Value::String(text)) => text.parse().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Engines on `PATH`, skipping a `docker` that is podman's compatibility
+/// wrapper so the same storage isn't offered twice.
+fn engines(runner: &CommandRunner) -> Vec<&'static str> {
+    ENGINES
+        .into_iter()
+        .filter(|engine| runner.has(engine))
+        .filter(|&engine| {
+            engine != "docker"
+                || !runner.has("podman")
+                || !runner
+                    .stdout("docker", ["--version"])
+                    .is_ok_and(|version| version.to_ascii_lowercase().contains("podman"))
+        })
+        .collect()
+}
+
+/// What pruning `category` with `engine` removes: how many items and their
+/// size, or `None` when the engine doesn't have the category. Dangling
+/// images are listed directly, as `system df` counts every image without a
+/// container as reclaimable; the rest come from `system df`.
+fn prunable(
+    runner: &CommandRunner,
+    engine: &str,
+    category: ContainerCategory,
+) -> Result<Option<(u64, u64)>> {
+    if category == ContainerCategory::DanglingImages {
+        let output = runner.stdout(
+            engine,
+            ["images", "-f", "dangling=true", "--format", "json"],
+        )?;
+        return parse_dangling_images(&output).map(Some);
+    }
+    let output = runner.stdout(engine, ["system", "df", "--format", "json"])?;
+    // Podman has no separate build cache.
+    Ok(parse_system_df(&output)?
+        .into_iter()
+        .find(|row| row.kind == category.df_type())
+        .map(|usage| (usage.total.saturating_sub(usage.active), usage.reclaimable)))
+}
+
+/// Offers pruning the rule's category with each of docker and podman found
+/// on `PATH`, sized by what the engine reports.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    // Rootless podman storage belongs to the caller and the docker daemon is
+    // shared, so neither is the scanned user's; pruning them in every
+    // per-user run would repeat the same prune.
+    if options.home.is_some() {
+        return scan;
+    }
+    let category = rule.containers;
+    for engine in engines(&options.runner) {
+        let (unused, bytes) = match prunable(&options.runner, engine, category) {
+            Ok(Some(usage)) => usage,
+            Ok(None) => continue,
+            Err(err) => {
+                record_error(&mut scan, format!("{err:#}"));
+                continue;
+            }
+        };
+        if unused == 0 && bytes == 0 {
+            continue;
+        }
+        scan.push_command(CommandAction {
+            // The build cache count is of cache records, not meaningful.
+            description: match category {
+                ContainerCategory::BuildCache => {
+                    format!("Prune {engine} {}", category.label())
+                }
+                _ => format!("Prune {engine} {} ({unused})", category.label()),
+            },
+            program: engine.to_string(),
+            args: category
+                .prune_args()
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            bytes,
+        });
+    }
+    scan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+
+    /// `docker system df --format json` (Docker 24).
+    const DOCKER_DF: &str = r#"{"Active":"2","Reclaimable":"1.234GB (45%)","Size":"2.742GB","TotalCount":"5","Type":"Images"}
+{"Active":"1","Reclaimable":"12.29kB (100%)","Size":"12.29kB","TotalCount":"3","Type":"Containers"}
+{"Active":"0","Reclaimable":"300MB (100%)","Size":"300MB","TotalCount":"2","Type":"Local Volumes"}
+{"Active":"0","Reclaimable":"1.1GB","Size":"1.1GB","TotalCount":"40","Type":"Build Cache"}
+"#;
+
+    /// `podman system df --format json` (Podman 4.9).
+    const PODMAN_DF: &str = r#"[
+ {
+  "Type": "Images",
+  "Total": 4,
+  "Active": 1,
+  "RawSize": 1048576000,
+  "RawReclaimable": 524288000,
+  "TotalCount": 4,
+  "Size": "1.049GB",
+  "Reclaimable": "524.3MB (50%)"
+ },
+ {
+  "Type": "Containers",
+  "Total": 1,
+  "Active": 1,
+  "RawSize": 4096,
+  "RawReclaimable": 0,
+  "TotalCount": 1,
+  "Size": "4.096kB",
+  "Reclaimable": "0B (0%)"
+ },
+ {
+  "Type": "Local Volumes",
+  "Total": 0,
+  "Active": 0,
+  "RawSize": 0,
+  "RawReclaimable": 0,
+  "TotalCount": 0,
+  "Size": "0B",
+  "Reclaimable": "0B (0%)"
+ }
+]
+"#;
+
+    /// `docker images -f dangling=true --format json` (Docker 24).
+    const DOCKER_IMAGES: &str = r#"{"Containers":"N/A","CreatedAt":"2024-01-02 10:00:00 +0100 CET","ID":"0a1b2c3d4e5f","Repository":"\u003cnone\u003e","Size":"734MB","Tag":"\u003cnone\u003e"}
+{"Containers":"N/A","CreatedAt":"2023-11-20 09:00:00 +0100 CET","ID":"6a7b8c9d0e1f","Repository":"\u003cnone\u003e","Size":"500MB","Tag":"\u003cnone\u003e"}
+"#;
+
+    /// `podman images -f dangling=true --format json` (Podman 4.9).
+    const PODMAN_IMAGES: &str = r#"[
+ {
+  "Id": "2f3e4d5c6b7a",
+  "Size": 104857600,
+  "Dangling": true,
+  "Names": null
+ }
+]
+"#;
+
+    #[test]
+    fn test_parse_system_df() {
+        let docker = parse_system_df(DOCKER_DF).unwrap();
+        assert_eq!(docker.len(), 4);
+        assert_eq!(
+            docker[0],
+            DiskUsage {
+                kind: "Images".to_string(),
+                total: 5,
+                active: 2,
+                reclaimable: 1_234_000_000,
+            }
+        );
+        assert_eq!(docker[1].reclaimable, 12_290);
+        assert_eq!(docker[3].reclaimable, 1_100_000_000);
+
+        let podman = parse_system_df(PODMAN_DF).unwrap();
+        assert_eq!(podman.len(), 3);
+        assert_eq!((podman[0].total, podman[0].active), (4, 1));
+        assert_eq!(podman[0].reclaimable, 524_288_000);
+        assert_eq!(podman[2].kind, "Local Volumes");
+
+        assert!(parse_system_df("Cannot connect to the Docker daemon").is_err());
+
+        assert_eq!(
+            parse_dangling_images(DOCKER_IMAGES).unwrap(),
+            (2, 1_234_000_000)
+        );
+        assert_eq!(
+            parse_dangling_images(PODMAN_IMAGES).unwrap(),
+            (1, 104_857_600)
+        );
+        assert_eq!(parse_dangling_images("[]").unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_scan_prunes_category_per_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        for (engine, df, images) in [
+            ("docker", DOCKER_DF, DOCKER_IMAGES),
+            ("podman", PODMAN_DF, PODMAN_IMAGES),
+        ] {
+            let df_path = dir.path().join(format!("{engine}-df.json"));
+            let images_path = dir.path().join(format!("{engine}-images.json"));
+            std::fs::write(&df_path, df).unwrap();
+            std::fs::write(&images_path, images).unwrap();
+            write_stub(
+                dir.path(),
+                engine,
+                &format!(
+                    r#"case "$1" in --version) echo "{engine} version 24.0.7" ;; images) cat {} ;; *) cat {} ;; esac"#,
+                    images_path.display(),
+                    df_path.display()
+                ),
+            );
+        }
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(dir.path()),
+            ..ScanOptions::default()
+        };
+        let rule = |category: &str| -> Rule {
+            serde_json::from_str(&format!(
+                r#"{{ "id": "c", "label": "C", "kind": "containers", "containers": "{category}" }}"#
+            ))
+            .unwrap()
+        };
+
+        let images = scan(&rule("dangling-images"), &options);
+        assert_eq!(images.errors, 0);
+        assert_eq!(images.commands.len(), 2);
+        assert_eq!(images.commands[0].command_line(), "docker image prune -f");
+        assert_eq!(
+            images.commands[1].description,
+            "Prune podman dangling images (1)"
+        );
+        assert_eq!(images.bytes, 1_234_000_000 + 104_857_600);
+
+        // Podman has no build cache and no stopped containers here.
+        let cache = scan(&rule("build-cache"), &options);
+        assert_eq!(cache.commands.len(), 1);
+        assert_eq!(cache.commands[0].command_line(), "docker builder prune -f");
+        let containers = scan(&rule("stopped-containers"), &options);
+        assert_eq!(containers.commands.len(), 1);
+        assert_eq!(
+            containers.commands[0].description,
+            "Prune docker stopped containers (2)"
+        );
+
+        // Per-user runs leave both engines to the system run.
+        let per_user = ScanOptions {
+            home: Some(dir.path().to_path_buf()),
+            ..options
+        };
+        assert!(scan(&rule("dangling-images"), &per_user)
+            .commands
+            .is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{build_globset, cutoff_from_days, record_error, rule_roots, scan_root, RuleScan};
+use crate::config::Rule;
+use crate::options::ScanOptions;
+use crate::units::format_timestamp;
+
+/// One crash: a core dump, an apport report with its companion files, or an
+/// ABRT problem directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Crash {
+    executable: String,
+    time: SystemTime,
+    /// The dump, report or problem directory.
+    path: PathBuf,
+    /// Files that go with `path` (apport's `.upload`/`.uploaded` markers).
+    companions: Vec<PathBuf>,
+}
+
+impl Crash {
+    fn note(&self) -> String {
+        let secs = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        format!("{}, {}", self.executable, format_timestamp(secs))
+    }
+}
+
+/// systemd-coredump names dumps
+/// `core.<comm>.<uid>.<boot id>.<pid>.<usec since epoch>[.<compression>]`.
+fn parse_coredump_name(name: &str) -> Option<(String, SystemTime)> {
+    let rest = name.strip_prefix("core.")?;
+    let rest = [".zst", ".xz", ".lz4"]
+        .iter()
+        .find_map(|ext| rest.strip_suffix(ext))
+        .unwrap_or(rest);
+    // The command name may itself contain dots, so take fields from the end.
+    let mut fields = rest.rsplitn(5, '.');
+    let usec = fields.next()?.parse::<u64>().ok()?;
+    let _pid = fields.next()?.parse::<u32>().ok()?;
+    let _boot_id = fields.next()?;
+    let _uid = fields.next()?.parse::<u32>().ok()?;
+    let comm = fields.next()?;
+    Some((comm.to_string(), UNIX_EPOCH + Duration::from_micros(usec)))
+}
+
+/// Apport names reports after the executable path with `/` as `_`:
+/// `_usr_bin_firefox.1000.crash`.
+fn parse_apport_name(name: &str) -> Option<String> {
+    let (path, _uid) = name.strip_suffix(".crash")?.rsplit_once('.')?;
+    Some(path.replace('_', "/"))
+}
+
+/// Crashes in `dir`. Entries Vole doesn't recognize are left alone.
+fn crashes_in(dir: &Path) -> Vec<Crash> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut crashes = Vec::new();
+    let mut names = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        if meta.is_dir() {
+            // ABRT problem directory.
+            let Ok(executable) = fs::read_to_string(path.join("executable")) else {
+                continue;
+            };
+            let time = fs::read_to_string(path.join("time"))
+                .ok()
+                .and_then(|time| time.trim().parse::<u64>().ok())
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap_or(modified);
+            crashes.push(Crash {
+                executable: executable.trim().to_string(),
+                time,
+                path,
+                companions: Vec::new(),
+            });
+        } else if let Some((executable, time)) = parse_coredump_name(&name) {
+            crashes.push(Crash {
+                executable,
+                time,
+                path,
+                companions: Vec::new(),
+            });
+        } else if let Some(executable) = parse_apport_name(&name) {
+            crashes.push(Crash {
+                executable,
+                time: modified,
+                path,
+                companions: Vec::new(),
+            });
+        }
+        names.push(name);
+    }
+    for crash in &mut crashes {
+        let Some(stem) = crash
+            .path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".crash"))
+        else {
+            continue;
+        };
+        for ext in ["upload", "uploaded"] {
+            let companion = format!("{stem}.{ext}");
+            if names.contains(&companion) {
+                crash.companions.push(dir.join(companion));
+            }
+        }
+    }
+    crashes
+}
+
+/// All but the newest `keep_newest` crashes of each executable, and only
+/// those older than `cutoff`.
+fn removable(crashes: Vec<Crash>, keep_newest: usize, cutoff: Option<SystemTime>) -> Vec<Crash> {
+    let mut by_executable: HashMap<String, Vec<Crash>> = HashMap::new();
+    for crash in crashes {
+        by_executable
+            .entry(crash.executable.clone())
+            .or_default()
+            .push(crash);
+    }
+    let mut removable = Vec::new();
+    for mut crashes in by_executable.into_values() {
+        crashes.sort_by_key(|crash| std::cmp::Reverse(crash.time));
+        removable.extend(
+            crashes
+                .into_iter()
+                .skip(keep_newest)
+                .filter(|crash| cutoff.is_none_or(|cutoff| crash.time < cutoff)),
+        );
+    }
+    removable.sort_by(|a, b| a.path.cmp(&b.path));
+    removable
+}
+
+/// Scans crash directories (the rule's paths) for core dumps, apport
+/// reports and ABRT problem directories, keeping the newest `keep_newest` of
+/// each executable.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    let (exclude_set, exclude_errors) = build_globset(&rule.exclude_globs);
+    for message in exclude_errors {
+        record_error(&mut scan, message);
+    }
+    let crashes = rule_roots(rule, options, &mut scan)
+        .iter()
+        .flat_map(|dir| crashes_in(dir))
+        .collect::<Vec<_>>();
+    let cutoff = rule.older_than_days.and_then(cutoff_from_days);
+    for crash in removable(crashes, rule.keep_newest.unwrap_or(0), cutoff) {
+        if crash.path.is_dir() {
+            scan.dirs.push(crash.path.clone());
+        }
+        for path in std::iter::once(&crash.path).chain(&crash.companions) {
+            for message in scan_root(path, exclude_set.as_ref(), &mut scan) {
+                record_error(&mut scan, message);
+            }
+        }
+        scan.notes.insert(crash.path.clone(), crash.note());
+    }
+    scan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOT_ID: &str = "3f1c0d2e4b5a69788796a5b4c3d2e1f0";
+
+    fn coredump(dir: &Path, comm: &str, pid: u32, secs: u64) -> PathBuf {
+        let path = dir.join(format!(
+            "core.{comm}.1000.{BOOT_ID}.{pid}.{}000000.zst",
+            secs
+        ));
+        fs::write(&path, "dump").unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_names() {
+        let (comm, time) = parse_coredump_name(&format!(
+            "core.gnome-shell.1000.{BOOT_ID}.1234.1700000000123456.zst"
+        ))
+        .unwrap();
+        assert_eq!(comm, "gnome-shell");
+        assert_eq!(
+            time,
+            UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456)
+        );
+        let (comm, _) =
+            parse_coredump_name(&format!("core.python3.11.0.{BOOT_ID}.99.1700000000000000"))
+                .unwrap();
+        assert_eq!(comm, "python3.11");
+        assert_eq!(parse_coredump_name("core.1234"), None);
+        assert_eq!(
+            parse_apport_name("_usr_bin_firefox.1000.crash").as_deref(),
+            Some("/usr/bin/firefox")
+        );
+    }
+
+    #[test]
+    fn test_keeps_newest_per_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = coredump(dir.path(), "firefox", 1, 1_700_000_000);
+        let new = coredump(dir.path(), "firefox", 2, 1_700_100_000);
+        let other = coredump(dir.path(), "vim", 3, 1_600_000_000);
+        fs::write(dir.path().join("_usr_bin_gedit.1000.crash"), "report").unwrap();
+        fs::write(dir.path().join("_usr_bin_gedit.1000.upload"), "").unwrap();
+        fs::write(dir.path().join("README"), "not a crash").unwrap();
+        let abrt = dir.path().join("ccpp-2023-11-14-10:22:33.123456-4321");
+        fs::create_dir_all(&abrt).unwrap();
+        fs::write(abrt.join("executable"), "/usr/bin/evince\n").unwrap();
+        fs::write(abrt.join("time"), "1690000000\n").unwrap();
+        fs::write(abrt.join("coredump"), "dump").unwrap();
+
+        let rule: Rule = serde_json::from_str(&format!(
+            r#"{{ "id": "c", "label": "C", "kind": "crashes", "paths": ["{}"], "keep_newest": 1 }}"#,
+            dir.path().display()
+        ))
+        .unwrap();
+        let scan = scan(&rule, &ScanOptions::default());
+        assert_eq!(scan.errors, 0);
+        // The newest firefox dump and the only vim, gedit and evince crashes
+        // stay.
+        assert_eq!(scan.files, vec![old.clone()]);
+        assert!(scan.dirs.is_empty());
+        assert_eq!(scan.notes[&old], "firefox, 2023-11-14 22:13");
+
+        let rule = Rule {
+            keep_newest: None,
+            ..rule
+        };
+        let scan = super::scan(&rule, &ScanOptions::default());
+        assert_eq!(scan.dirs, vec![abrt.clone()]);
+        assert_eq!(scan.notes[&abrt], "/usr/bin/evince, 2023-07-22 04:26");
+        assert!(scan
+            .files
+            .contains(&dir.path().join("_usr_bin_gedit.1000.upload")));
+        assert!(scan.files.contains(&new) && scan.files.contains(&other));
+        assert!(!scan.files.contains(&dir.path().join("README")));
+    }
+}
@@ -0,0 +1,497 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::{
+    build_globset, record_error, rule_roots, scan_root, tree_size, CommandAction, RuleScan,
+};
+use crate::config::{FlatpakCategory, Rule};
+use crate::options::ScanOptions;
+use crate::users;
+
+const SYSTEM_INSTALLATION: &str = "/var/lib/flatpak";
+/// User installation, relative to the home.
+const USER_INSTALLATION: &str = ".local/share/flatpak";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    App,
+    Runtime,
+}
+
+impl RefKind {
+    fn dir_name(self) -> &'static str {
+        match self {
+            RefKind::App => "app",
+            RefKind::Runtime => "runtime",
+        }
+    }
+}
+
+/// An installed app or runtime, read from the installation's deploy tree.
+#[derive(Debug, Clone)]
+pub struct InstalledRef {
+    pub kind: RefKind,
+    pub id: String,
+    pub arch: String,
+    pub branch: String,
+    /// `<installation>/<kind>/<id>/<arch>/<branch>`, holding the deployed commits.
+    pub path: PathBuf,
+    metadata: HashMap<String, HashMap<String, String>>,
+}
+
+impl InstalledRef {
+    /// `app/<id>/<arch>/<branch>` or `runtime/...`, as Flatpak writes refs.
+    pub fn full_ref(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.kind.dir_name(),
+            self.id,
+            self.arch,
+            self.branch
+        )
+    }
+
+    fn metadata_value(&self, group: &str, key: &str) -> Option<&str> {
+        self.metadata.get(group)?.get(key).map(String::as_str)
+    }
+}
+
+/// Every deployed ref in the installation at `root`.
+pub fn installed_refs(root: &Path) -> Vec<InstalledRef> {
+    let mut refs = Vec::new();
+    for kind in [RefKind::App, RefKind::Runtime] {
+        for (id, arch, branch, path) in deploy_dirs(&root.join(kind.dir_name())) {
+            let metadata = fs::read_to_string(path.join("active/metadata"))
+                .map(|content| parse_keyfile(&content))
+                .unwrap_or_default();
+            refs.push(InstalledRef {
+                kind,
+                id,
+                arch,
+                branch,
+                path,
+                metadata,
+            });
+        }
+    }
+    refs
+}
+
+/// `<id>/<arch>/<branch>` directories under `base`.
+fn deploy_dirs(base: &Path) -> Vec<(String, String, String, PathBuf)> {
+    let mut dirs = Vec::new();
+    for id in subdirs(base) {
+        for arch in subdirs(&base.join(&id)) {
+            // `current` points at the default branch of an app.
+            if arch == "current" {
+                continue;
+            }
+            for branch in subdirs(&base.join(&id).join(&arch)) {
+                let path = base.join(&id).join(&arch).join(&branch);
+                dirs.push((id.clone(), arch.clone(), branch, path));
+            }
+        }
+    }
+    dirs.sort();
+    dirs
+}
+
+fn subdirs(path: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Runtimes in `refs` no installed app needs, directly or as an extension
+/// of something that is needed, and that aren't pinned. `other_apps` are
+/// apps of other installations that may use these runtimes, as user
+/// installations use the system one. Refs with unreadable metadata count as
+/// used.
+pub fn unused_runtimes<'a>(
+    refs: &'a [InstalledRef],
+    other_apps: &[InstalledRef],
+    pinned: &[String],
+) -> Vec<&'a InstalledRef> {
+    let mut used = HashSet::new();
+    for installed in refs.iter().chain(other_apps) {
+        if installed.kind == RefKind::App || installed.metadata.is_empty() {
+            used.insert(installed.full_ref());
+        }
+        if let Some(runtime) = installed.metadata_value("Application", "runtime") {
+            used.insert(format!("runtime/{runtime}"));
+        }
+    }
+    // Extensions of used refs are used too, transitively.
+    loop {
+        let before = used.len();
+        for installed in refs {
+            if let Some(parent) = installed.metadata_value("ExtensionOf", "ref") {
+                if used.contains(parent) {
+                    used.insert(installed.full_ref());
+                }
+            }
+        }
+        if used.len() == before {
+            break;
+        }
+    }
+    refs.iter()
+        .filter(|installed| installed.kind == RefKind::Runtime)
+        .filter(|installed| !used.contains(&installed.full_ref()))
+        .filter(|installed| !is_pinned(&installed.full_ref(), pinned))
+        .collect()
+}
+
+/// Patterns `flatpak pin` recorded in the installation at `root`. Flatpak
+/// also pins the runtimes that were installed explicitly.
+fn pinned_patterns(root: &Path) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(root.join("repo/config")) else {
+        return Vec::new();
+    };
+    parse_keyfile(&content)
+        .get("core")
+        .and_then(|core| core.get("xa.pinned"))
+        .map(|value| {
+            value
+                .split(';')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a pin pattern such as `runtime/org.gnome.Platform/*/46` matches
+/// `full_ref`. Patterns may leave out the `runtime/` prefix and trailing
+/// parts, and `*` matches any one part.
+fn is_pinned(full_ref: &str, patterns: &[String]) -> bool {
+    let parts = full_ref.split('/').collect::<Vec<_>>();
+    patterns.iter().any(|pattern| {
+        let mut wanted = pattern.split('/').collect::<Vec<_>>();
+        if !matches!(wanted[0], "app" | "runtime") {
+            wanted.insert(0, "runtime");
+        }
+        wanted.len() <= parts.len()
+            && wanted
+                .iter()
+                .zip(&parts)
+                .all(|(wanted, part)| *wanted == "*" || wanted == part)
+    })
+}
+
+/// Apps in every user's installation, which may use system runtimes.
+fn user_apps(options: &ScanOptions) -> Result<Vec<InstalledRef>> {
+    Ok(users::read_passwd(&options.root)?
+        .iter()
+        .flat_map(|account| installed_refs(&account.home.join(USER_INSTALLATION)))
+        .filter(|installed| installed.kind == RefKind::App)
+        .collect())
+}
+
+/// Minimal GKeyFile reader: `[group]` headers and `key=value` lines.
+fn parse_keyfile(content: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut groups: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(group) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            current = Some(group.to_string());
+            groups.entry(group.to_string()).or_default();
+        } else if let (Some(group), Some((key, value))) = (&current, line.split_once('=')) {
+            groups
+                .entry(group.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    groups
+}
+
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    match rule.flatpak {
+        FlatpakCategory::UnusedRuntimes => scan_unused(rule, options),
+        FlatpakCategory::LeftoverData => scan_app_dirs(rule, options, AppDirs::Leftover),
+        FlatpakCategory::AppCache => scan_app_dirs(rule, options, AppDirs::Cache),
+    }
+}
+
+/// Offers `flatpak uninstall` of the unused runtimes in each installation in
+/// the rule's paths.
+fn scan_unused(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    if !options.runner.has("flatpak") {
+        return scan;
+    }
+    let system_installation = options.root.join(Path::new(SYSTEM_INSTALLATION)).ok();
+    for root in rule_roots(rule, options, &mut scan) {
+        let system = Some(&root) == system_installation.as_ref();
+        // `flatpak --user` acts on the caller's installation, not the one
+        // being scanned for another user.
+        if !system && options.home.is_some() {
+            continue;
+        }
+        let other_apps = if system {
+            // A runtime only user installations use is still used.
+            match user_apps(options) {
+                Ok(apps) => apps,
+                Err(err) => {
+                    record_error(&mut scan, format!("{err:#}"));
+                    continue;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let refs = installed_refs(&root);
+        let unused = unused_runtimes(&refs, &other_apps, &pinned_patterns(&root));
+        if unused.is_empty() {
+            continue;
+        }
+        let bytes = unused
+            .iter()
+            .map(|installed| tree_size(&installed.path))
+            .sum();
+        let scope = if system { "--system" } else { "--user" };
+        let mut args = ["uninstall", scope, "--noninteractive", "-y"]
+            .map(String::from)
+            .to_vec();
+        args.extend(unused.iter().map(|installed| installed.full_ref()));
+        scan.push_command(CommandAction {
+            description: format!(
+                "Uninstall {} unused Flatpak runtimes in {}",
+                unused.len(),
+                root.display()
+            ),
+            program: "flatpak".to_string(),
+            args,
+            bytes,
+        });
+    }
+    scan
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppDirs {
+    Leftover,
+    Cache,
+}
+
+/// Scans `~/.var/app` directories (the rule's paths): whole directories of
+/// apps that aren't installed anymore, or every app's `cache`.
+fn scan_app_dirs(rule: &Rule, options: &ScanOptions, which: AppDirs) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    let (exclude_set, exclude_errors) = build_globset(&rule.exclude_globs);
+    for message in exclude_errors {
+        record_error(&mut scan, message);
+    }
+
+    for var_app in rule_roots(rule, options, &mut scan) {
+        let targets = match which {
+            AppDirs::Leftover => {
+                // `<home>/.var/app` -> `<home>`.
+                let Some(home) = var_app.parent().and_then(Path::parent) else {
+                    continue;
+                };
+                let installations = options
+                    .root
+                    .join(Path::new(SYSTEM_INSTALLATION))
+                    .into_iter()
+                    .chain([home.join(USER_INSTALLATION)])
+                    .collect::<Vec<_>>();
+                leftover_app_dirs(&var_app, &installations)
+            }
+            AppDirs::Cache => subdirs(&var_app)
+                .into_iter()
+                .map(|id| var_app.join(id).join("cache"))
+                .filter(|cache| cache.is_dir())
+                .collect(),
+        };
+        for target in targets {
+            if which == AppDirs::Leftover {
+                // The app directory itself goes too.
+                scan.dirs.push(target.clone());
+            }
+            for message in scan_root(&target, exclude_set.as_ref(), &mut scan) {
+                record_error(&mut scan, message);
+            }
+        }
+    }
+    scan
+}
+
+/// App data directories under `var_app` whose app none of `installations`
+/// has. Nothing is reported when no installation exists, since then the apps
+/// may live somewhere Vole doesn't know about.
+fn leftover_app_dirs(var_app: &Path, installations: &[PathBuf]) -> Vec<PathBuf> {
+    if !installations.iter().any(|root| root.join("app").is_dir()) {
+        return Vec::new();
+    }
+    let installed = installations
+        .iter()
+        .flat_map(|root| subdirs(&root.join("app")))
+        .collect::<HashSet<_>>();
+    let mut leftovers = subdirs(var_app)
+        .into_iter()
+        .filter(|id| !installed.contains(id))
+        .map(|id| var_app.join(id))
+        .collect::<Vec<_>>();
+    leftovers.sort();
+    leftovers
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::fs;
+    use std::path::Path;
+
+    /// Deploys a fake ref with `metadata` under the installation at `root`.
+    pub fn install(root: &Path, full_ref: &str, metadata: &str, payload: usize) {
+        let commit = root.join(full_ref).join("0123abcd");
+        fs::create_dir_all(commit.join("files")).unwrap();
+        fs::write(commit.join("metadata"), metadata).unwrap();
+        fs::write(commit.join("files/payload"), vec![0u8; payload]).unwrap();
+        std::os::unix::fs::symlink("0123abcd", root.join(full_ref).join("active")).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::install;
+    use super::*;
+
+    #[test]
+    fn test_leftover_and_cache_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home/alice");
+        let user_installation = home.join(USER_INSTALLATION);
+        install(
+            &user_installation,
+            "app/org.gnome.Maps/x86_64/stable",
+            "[Application]\nname=org.gnome.Maps\n",
+            10,
+        );
+        let var_app = home.join(".var/app");
+        for id in ["org.gnome.Maps", "com.example.Gone"] {
+            fs::create_dir_all(var_app.join(id).join("cache")).unwrap();
+            fs::write(var_app.join(id).join("cache/blob"), "cached").unwrap();
+        }
+        fs::write(var_app.join("com.example.Gone/data.db"), "data").unwrap();
+
+        let system = dir.path().join("var/lib/flatpak");
+        assert_eq!(
+            leftover_app_dirs(&var_app, &[system.clone(), user_installation]),
+            [var_app.join("com.example.Gone")]
+        );
+        assert!(leftover_app_dirs(&var_app, &[system]).is_empty());
+
+        let rule: Rule = serde_json::from_str(&format!(
+            r#"{{ "id": "c", "label": "C", "kind": "flatpak", "flatpak": "app-cache", "paths": ["{}"] }}"#,
+            var_app.display()
+        ))
+        .unwrap();
+        let scan = scan(&rule, &ScanOptions::default());
+        assert_eq!(scan.files.len(), 2);
+        assert_eq!(scan.bytes, 12);
+    }
+
+    #[test]
+    fn test_unused_runtimes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        install(
+            root,
+            "app/org.gnome.Maps/x86_64/stable",
+            "[Application]\nname=org.gnome.Maps\nruntime=org.gnome.Platform/x86_64/46\nsdk=org.gnome.Sdk/x86_64/46\n",
+            10,
+        );
+        install(
+            root,
+            "runtime/org.gnome.Platform/x86_64/46",
+            "[Runtime]\nname=org.gnome.Platform\n",
+            10,
+        );
+        install(
+            root,
+            "runtime/org.gnome.Platform.Locale/x86_64/46",
+            "[Runtime]\nname=org.gnome.Platform.Locale\n\n[ExtensionOf]\nref=runtime/org.gnome.Platform/x86_64/46\n",
+            10,
+        );
+        install(
+            root,
+            "runtime/org.gnome.Platform/x86_64/44",
+            "[Runtime]\nname=org.gnome.Platform\n",
+            10,
+        );
+        install(
+            root,
+            "runtime/org.freedesktop.Platform.GL.default/x86_64/22.08",
+            "[Runtime]\nname=org.freedesktop.Platform.GL.default\n\n[ExtensionOf]\nref=runtime/org.freedesktop.Platform/x86_64/22.08\n",
+            10,
+        );
+
+        install(
+            root,
+            "runtime/org.kde.Platform/x86_64/6.7",
+            "[Runtime]\nname=org.kde.Platform\n",
+            10,
+        );
+        install(
+            root,
+            "runtime/org.freedesktop.Sdk/x86_64/23.08",
+            "[Runtime]\nname=org.freedesktop.Sdk\n",
+            10,
+        );
+        fs::create_dir_all(root.join("repo")).unwrap();
+        fs::write(
+            root.join("repo/config"),
+            "[core]\nrepo_version=1\nxa.pinned=runtime/org.freedesktop.Sdk/*/23.08;\n",
+        )
+        .unwrap();
+
+        let user = dir.path().join("user");
+        install(
+            &user,
+            "app/org.kde.Kate/x86_64/stable",
+            "[Application]\nname=org.kde.Kate\nruntime=org.kde.Platform/x86_64/6.7\n",
+            10,
+        );
+
+        let refs = installed_refs(root);
+        assert_eq!(refs.len(), 7);
+        let unused = |other_apps: &[InstalledRef], pinned: &[String]| {
+            unused_runtimes(&refs, other_apps, pinned)
+                .into_iter()
+                .map(InstalledRef::full_ref)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            unused(&installed_refs(&user), &pinned_patterns(root)),
+            [
+                "runtime/org.freedesktop.Platform.GL.default/x86_64/22.08",
+                "runtime/org.gnome.Platform/x86_64/44",
+            ]
+        );
+        assert_eq!(
+            unused(&[], &["org.gnome.Platform".to_string()]),
+            [
+                "runtime/org.freedesktop.Platform.GL.default/x86_64/22.08",
+                "runtime/org.freedesktop.Sdk/x86_64/23.08",
+                "runtime/org.kde.Platform/x86_64/6.7",
+            ]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use humansize::{format_size, BINARY};
+
+use super::{cutoff_from_days, record_error, rule_roots, CommandAction, RuleScan};
+use crate::config::{JournalScope, Rule};
+use crate::options::ScanOptions;
+use crate::units::parse_size;
+
+/// A journal file on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct JournalFile {
+    path: PathBuf,
+    bytes: u64,
+    modified: SystemTime,
+}
+
+impl JournalFile {
+    /// Files journald still writes to (`system.journal`, `user-1000.journal`).
+    /// Archived ones are named `system@<seqnum>-<head>.journal` or end in
+    /// `.journal~`.
+    fn is_active(&self) -> bool {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .is_some_and(|name| !name.contains('@') && name.ends_with(".journal"))
+    }
+}
+
+/// Journal files in `dir` and its per-machine subdirectories whose name
+/// passes `keep`.
+fn journal_files(dir: &Path, keep: &dyn Fn(&str) -> bool) -> Vec<JournalFile> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    if let Ok(entries) = fs::read_dir(dir) {
+        dirs.extend(
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                .map(|entry| entry.path()),
+        );
+    }
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_journal = name.ends_with(".journal") || name.ends_with(".journal~");
+            if !is_journal || !keep(&name) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
+            }
+            files.push(JournalFile {
+                path: entry.path(),
+                bytes: meta.len(),
+                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+    files.sort_by_key(|file| file.modified);
+    files
+}
+
+/// What vacuuming removes: archived files older than `cutoff`, then the
+/// oldest archived files until everything fits in `max_bytes`. Active files
+/// are never picked, as journalctl never removes them either.
+fn vacuumed(
+    files: &[JournalFile],
+    cutoff: Option<SystemTime>,
+    max_bytes: Option<u64>,
+) -> Vec<&JournalFile> {
+    let mut total = files.iter().map(|file| file.bytes).sum::<u64>();
+    let mut picked = Vec::new();
+    // `files` is oldest first.
+    for file in files.iter().filter(|file| !file.is_active()) {
+        let expired = cutoff.is_some_and(|cutoff| file.modified < cutoff);
+        let over_size = max_bytes.is_some_and(|max| total > max);
+        if !expired && !over_size {
+            continue;
+        }
+        total -= file.bytes;
+        picked.push(file);
+    }
+    picked
+}
+
+/// Vacuums the journal to the rule's `older_than_days` and `vacuum_size`.
+/// The system journal goes through `journalctl --vacuum-time`/
+/// `--vacuum-size`, estimated from the journal files in the rule's paths.
+/// `journalctl --user` can't be used for the user's own: it vacuums whole
+/// directories, system files included. So the user scope picks the same
+/// archived `user-<uid>@*.journal` files itself and deletes them.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    let user = rule.journal == JournalScope::User;
+    if !user && !options.runner.has("journalctl") {
+        return scan;
+    }
+    let max_bytes = match rule.vacuum_size.as_deref().map(parse_size).transpose() {
+        Ok(max_bytes) => max_bytes,
+        Err(err) => {
+            record_error(&mut scan, format!("{err:#}"));
+            return scan;
+        }
+    };
+    if rule.older_than_days.is_none() && max_bytes.is_none() {
+        record_error(
+            &mut scan,
+            format!(
+                "Journal rule {} sets neither older_than_days nor vacuum_size",
+                rule.id
+            ),
+        );
+        return scan;
+    }
+
+    let cutoff = rule.older_than_days.and_then(cutoff_from_days);
+
+    if user {
+        let uid = options.owner.unwrap_or_else(invoking_uid);
+        let user_prefix = format!("user-{uid}");
+        let keep = |name: &str| {
+            name.strip_prefix(&user_prefix)
+                .is_some_and(|rest| rest.starts_with(['.', '@']))
+        };
+        let files = rule_roots(rule, options, &mut scan)
+            .iter()
+            .flat_map(|dir| journal_files(dir, &keep))
+            .collect::<Vec<_>>();
+        // journald keeps user journals owned by root and grants the user
+        // read access only.
+        scan.owner = None;
+        for file in vacuumed(&files, cutoff, max_bytes) {
+            scan.bytes += file.bytes;
+            scan.entries += 1;
+            scan.files.push(file.path.clone());
+        }
+        return scan;
+    }
+
+    let files = rule_roots(rule, options, &mut scan)
+        .iter()
+        .flat_map(|dir| journal_files(dir, &|_| true))
+        .collect::<Vec<_>>();
+    let usage = if files.is_empty() {
+        disk_usage(&scan)
+    } else {
+        Some(files.iter().map(|file| file.bytes).sum())
+    };
+
+    let mut args = Vec::new();
+    if let Some(days) = rule.older_than_days {
+        args.push(format!("--vacuum-time={days}d"));
+    }
+    if let Some(max_bytes) = max_bytes {
+        args.push(format!("--vacuum-size={max_bytes}"));
+    }
+    let description = match usage {
+        Some(usage) => format!(
+            "Vacuum the system journal (uses {})",
+            format_size(usage, BINARY)
+        ),
+        None => "Vacuum the system journal".to_string(),
+    };
+    scan.push_command(CommandAction {
+        description,
+        program: "journalctl".to_string(),
+        args,
+        bytes: vacuumed(&files, cutoff, max_bytes)
+            .iter()
+            .map(|file| file.bytes)
+            .sum(),
+    });
+    scan
+}
+
+/// The user whose journal the user scope cleans: the one who ran sudo, if
+/// vole runs under it.
+fn invoking_uid() -> u32 {
+    let euid = unsafe { libc::geteuid() };
+    if euid != 0 {
+        return euid;
+    }
+    std::env::var("SUDO_UID")
+        .ok()
+        .and_then(|uid| uid.parse().ok())
+        .unwrap_or(euid)
+}
+
+/// `journalctl --disk-usage`, for when the journal files can't be read.
+fn disk_usage(scan: &RuleScan) -> Option<u64> {
+    let output = scan.runner.stdout("journalctl", ["--disk-usage"]).ok()?;
+    parse_disk_usage(&output)
+}
+
+/// `Archived and active journals take up 1.2G in the file system.`
+fn parse_disk_usage(output: &str) -> Option<u64> {
+    let rest = output.split(" take up ").nth(1)?;
+    let size = rest.split_whitespace().next()?;
+    parse_size(size).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::command::CommandRunner;
+    use std::time::Duration;
+
+    fn journal(path: &str, bytes: u64, days_old: u64) -> JournalFile {
+        JournalFile {
+            path: PathBuf::from(path),
+            bytes,
+            modified: SystemTime::now() - Duration::from_secs(days_old * 86_400),
+        }
+    }
+
+    #[test]
+    fn test_vacuumed_spares_active_files() {
+        let files = [
+            journal("/j/system@0001-0002.journal", 100, 40),
+            journal("/j/user-1000@0003-0004.journal", 50, 20),
+            journal("/j/system.journal~", 30, 10),
+            journal("/j/system.journal", 500, 0),
+        ];
+        assert!(files[3].is_active() && !files[2].is_active());
+        let freed = |cutoff, max_bytes| {
+            vacuumed(&files, cutoff, max_bytes)
+                .iter()
+                .map(|file| file.bytes)
+                .sum::<u64>()
+        };
+        assert_eq!(freed(cutoff_from_days(30), None), 100);
+        assert_eq!(freed(None, Some(560)), 150);
+        // Never below the active files.
+        assert_eq!(freed(None, Some(0)), 180);
+        assert_eq!(
+            parse_disk_usage("Archived and active journals take up 1.5G in the file system.\n"),
+            Some(1_610_612_736)
+        );
+    }
+
+    #[test]
+    fn test_scan_vacuums_journals() {
+        let dir = tempfile::tempdir().unwrap();
+        let machine = dir.path().join("journal/0123abcd");
+        fs::create_dir_all(&machine).unwrap();
+        fs::write(machine.join("system.journal"), vec![0u8; 64]).unwrap();
+        fs::write(machine.join("user-1000.journal"), vec![0u8; 32]).unwrap();
+        fs::write(machine.join("user-10001@03-04.journal"), vec![0u8; 16]).unwrap();
+        fs::write(machine.join("user-1000@01-02.journal"), vec![0u8; 8]).unwrap();
+        let bin = dir.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        write_stub(&bin, "journalctl", "exit 0");
+        let rule = |journal: &str| -> Rule {
+            serde_json::from_str(&format!(
+                r#"{{ "id": "j", "label": "J", "kind": "journal", "journal": "{journal}", "paths": ["{}"], "vacuum_size": "32" }}"#,
+                dir.path().join("journal").display()
+            ))
+            .unwrap()
+        };
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(&bin),
+            owner: Some(1000),
+            ..ScanOptions::default()
+        };
+
+        // The user's archived file goes; the active one and other users'
+        // files stay.
+        let user = scan(&rule("user"), &options);
+        assert_eq!(user.errors, 0);
+        assert!(user.commands.is_empty());
+        assert_eq!(user.files, [machine.join("user-1000@01-02.journal")]);
+        assert_eq!(user.bytes, 8);
+        assert_eq!(user.owner, None);
+
+        let system = scan(&rule("system"), &options);
+        assert_eq!(system.errors, 0);
+        assert!(system.files.is_empty());
+        assert_eq!(
+            system.commands[0].command_line(),
+            "journalctl --vacuum-size=32"
+        );
+        assert_eq!(
+            system.commands[0].description,
+            "Vacuum the system journal (uses 120 B)"
+        );
+        assert_eq!(system.bytes, 24);
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::packages::PackageManager;
+use super::{record_error, rule_roots, scan_root, tree_size, CommandAction, RuleScan};
+use crate::command::CommandRunner;
+use crate::config::Rule;
+use crate::distro::compare_versions;
+use crate::options::ScanOptions;
+
+const BOOT_DIR: &str = "/boot";
+const DEFAULT_KEEP_NEWEST: usize = 2;
+
+/// A kernel version found in a modules directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Kernel {
+    version: String,
+    modules: PathBuf,
+    /// Files in `/boot` for this version (image, initramfs, System.map, ...).
+    boot_files: Vec<PathBuf>,
+}
+
+impl Kernel {
+    /// A kernel image next to the modules or in `/boot`.
+    fn has_image(&self) -> bool {
+        self.modules.join("vmlinuz").is_file()
+            || self.boot_files.iter().any(|file| {
+                file.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("vmlinuz"))
+            })
+    }
+
+    fn bytes(&self) -> u64 {
+        tree_size(&self.modules)
+            + self
+                .boot_files
+                .iter()
+                .filter_map(|file| fs::symlink_metadata(file).ok())
+                .map(|meta| meta.len())
+                .sum::<u64>()
+    }
+}
+
+/// Kernels in `modules_dir`, oldest first, with their files in `boot_dir`.
+fn installed_kernels(modules_dir: &Path, boot_dir: &Path) -> Vec<Kernel> {
+    let Ok(entries) = fs::read_dir(modules_dir) else {
+        return Vec::new();
+    };
+    let boot_entries = fs::read_dir(boot_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let mut kernels = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| {
+            let version = entry.file_name().to_string_lossy().into_owned();
+            let boot_files = boot_entries
+                .iter()
+                .filter(|file| is_boot_file_for(file, &version))
+                .cloned()
+                .collect();
+            Kernel {
+                modules: entry.path(),
+                version,
+                boot_files,
+            }
+        })
+        .collect::<Vec<_>>();
+    kernels.sort_by(|a, b| compare_versions(&a.version, &b.version));
+    kernels
+}
+
+/// `vmlinuz-<ver>`, `initramfs-<ver>.img`, `initrd.img-<ver>`,
+/// `System.map-<ver>`, `config-<ver>`, ...
+fn is_boot_file_for(file: &Path, version: &str) -> bool {
+    let Some(name) = file.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+    let stem = name.strip_suffix(".img").unwrap_or(&name);
+    stem.strip_suffix(version)
+        .is_some_and(|prefix| prefix.ends_with('-'))
+}
+
+/// Offers removing old kernels through the package manager, one command per
+/// version, and deletes module directories no package owns and no kernel
+/// image goes with. The running kernel and the newest `keep_newest` are kept.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    let Some(manager) = PackageManager::detect(&options.runner) else {
+        return scan;
+    };
+    let running = match options.runner.stdout("uname", ["-r"]) {
+        Ok(output) => output.trim().to_string(),
+        Err(err) => {
+            record_error(&mut scan, format!("{err:#}"));
+            return scan;
+        }
+    };
+    if running.is_empty() {
+        return scan;
+    }
+    let keep_newest = rule.keep_newest.unwrap_or(DEFAULT_KEEP_NEWEST);
+    let boot_dir = match options.root.join(Path::new(BOOT_DIR)) {
+        Ok(dir) => dir,
+        Err(err) => {
+            record_error(&mut scan, format!("{err:#}"));
+            return scan;
+        }
+    };
+
+    for modules_dir in rule_roots(rule, options, &mut scan) {
+        let mut bootable = Vec::new();
+        for kernel in installed_kernels(&modules_dir, &boot_dir) {
+            let packages = kernel_packages(manager, &options.runner, &kernel);
+            if packages.is_empty() && !kernel.has_image() && kernel.version != running {
+                // Leftovers of a kernel the package manager already removed.
+                scan.dirs.push(kernel.modules.clone());
+                for message in scan_root(&kernel.modules, None, &mut scan) {
+                    record_error(&mut scan, message);
+                }
+            } else {
+                bootable.push((kernel, packages));
+            }
+        }
+        let removable = bootable.len().saturating_sub(keep_newest);
+        for (kernel, packages) in &bootable[..removable] {
+            // Kernels built by hand have no package to remove them with.
+            if kernel.version == running || packages.is_empty() {
+                continue;
+            }
+            let (program, args) = manager.remove_command(packages);
+            scan.push_command(CommandAction {
+                description: format!("Remove kernel {}", kernel.version),
+                program,
+                args,
+                bytes: kernel.bytes(),
+            });
+        }
+    }
+    scan
+}
+
+/// Packages owning the kernel's modules or its files in `/boot`.
+fn kernel_packages(
+    manager: PackageManager,
+    runner: &CommandRunner,
+    kernel: &Kernel,
+) -> Vec<String> {
+    let mut packages = Vec::new();
+    for path in std::iter::once(&kernel.modules).chain(&kernel.boot_files) {
+        for package in manager.owners(runner, path) {
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::sysroot::SysRoot;
+
+    #[test]
+    fn test_old_kernels_and_orphaned_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let modules = root.join("usr/lib/modules");
+        let boot = root.join("boot");
+        fs::create_dir_all(&boot).unwrap();
+        for version in ["4.9.0-1", "5.0.0-1", "6.1.0-1", "6.10.0-1", "6.2.0-1"] {
+            fs::create_dir_all(modules.join(version).join("kernel")).unwrap();
+            fs::write(modules.join(version).join("kernel/ext4.ko"), "module").unwrap();
+            if version != "4.9.0-1" {
+                fs::write(boot.join(format!("vmlinuz-{version}")), "image").unwrap();
+                fs::write(boot.join(format!("initramfs-{version}.img")), "initramfs").unwrap();
+            }
+        }
+        let bin = dir.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        write_stub(&bin, "dnf", "exit 0");
+        write_stub(&bin, "uname", "echo 6.1.0-1");
+        write_stub(
+            &bin,
+            "rpm",
+            r#"v=$(basename "$2" .img); v=${v#vmlinuz-}; v=${v#initramfs-}
+case "$v" in 4.9*) echo "not owned by any package" >&2; exit 1;; esac
+echo "kernel-core-$v""#,
+        );
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "k", "label": "K", "kind": "kernels", "paths": ["/usr/lib/modules"], "keep_newest": 2 }"#,
+        )
+        .unwrap();
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(&bin),
+            root: SysRoot::new(&root).unwrap(),
+            ..ScanOptions::default()
+        };
+
+        let scan = scan(&rule, &options);
+        assert_eq!(scan.errors, 0);
+        // 6.2 and 6.10 are the newest, 6.1 is running.
+        assert_eq!(scan.commands.len(), 1);
+        assert_eq!(
+            scan.commands[0].command_line(),
+            "dnf remove -y --noautoremove kernel-core-5.0.0-1"
+        );
+        assert_eq!(scan.commands[0].bytes, 6 + 5 + 9);
+        assert_eq!(scan.dirs.first(), Some(&modules.join("4.9.0-1")));
+        assert_eq!(scan.files, [modules.join("4.9.0-1/kernel/ext4.ko")]);
+    }
+
+    #[test]
+    fn test_is_boot_file_for() {
+        assert!(is_boot_file_for(
+            Path::new("/boot/initrd.img-6.5.0-14-generic"),
+            "6.5.0-14-generic"
+        ));
+        assert!(!is_boot_file_for(
+            Path::new("/boot/vmlinuz-16.5.0-14-generic"),
+            "6.5.0-14-generic"
+        ));
+    }
+}
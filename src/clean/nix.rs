@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use super::{record_error, tree_size, CommandAction, RuleScan};
+use crate::config::Rule;
+use crate::options::ScanOptions;
+
+const DEFAULT_OLDER_THAN_DAYS: u64 = 30;
+
+/// Offers `nix-collect-garbage --delete-older-than <N>d`. The estimate is what
+/// the store already holds unreferenced; generations that expire free more.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    if !options.runner.has("nix-collect-garbage") {
+        return scan;
+    }
+    let days = rule.older_than_days.unwrap_or(DEFAULT_OLDER_THAN_DAYS);
+    let bytes = if options.runner.has("nix-store") {
+        match options.runner.stdout("nix-store", ["--gc", "--print-dead"]) {
+            Ok(output) => parse_dead_paths(&output)
+                .iter()
+                .map(|path| tree_size(path))
+                .sum(),
+            Err(err) => {
+                record_error(&mut scan, format!("{err:#}"));
+                0
+            }
+        }
+    } else {
+        0
+    };
+    scan.push_command(CommandAction {
+        description: format!("Delete Nix generations older than {days} days and collect garbage"),
+        program: "nix-collect-garbage".to_string(),
+        args: vec!["--delete-older-than".to_string(), format!("{days}d")],
+        bytes,
+    });
+    scan
+}
+
+/// Store paths in `nix-store --gc --print-dead` output (it also prints
+/// progress lines).
+fn parse_dead_paths(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("/nix/store/"))
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::command::CommandRunner;
+
+    #[test]
+    fn test_scan_builds_command() {
+        let dir = tempfile::tempdir().unwrap();
+        write_stub(dir.path(), "nix-collect-garbage", "exit 0");
+        write_stub(
+            dir.path(),
+            "nix-store",
+            "echo 'finding garbage collector roots...'; echo /nix/store/abc-hello-2.12",
+        );
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "nix", "label": "Nix", "kind": "nix", "older_than_days": 14 }"#,
+        )
+        .unwrap();
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(dir.path()),
+            ..ScanOptions::default()
+        };
+
+        let scan = scan(&rule, &options);
+        assert_eq!(scan.errors, 0);
+        assert_eq!(scan.commands.len(), 1);
+        assert_eq!(
+            scan.commands[0].command_line(),
+            "nix-collect-garbage --delete-older-than 14d"
+        );
+        assert_eq!(
+            parse_dead_paths("finding garbage collector roots...\n/nix/store/abc-hello\n"),
+            [PathBuf::from("/nix/store/abc-hello")]
+        );
+    }
+}
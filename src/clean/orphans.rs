@@ -0,0 +1,92 @@
+use super::packages::PackageManager;
+use super::{build_globset, record_error, CommandAction, Package, RuleScan};
+use crate::config::Rule;
+use crate::options::ScanOptions;
+
+/// Offers removing the packages the package manager reports as orphaned, in
+/// one command, skipping names matched by the rule's `exclude_globs`.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    let Some(manager) = PackageManager::detect(&options.runner) else {
+        return scan;
+    };
+    let (exclude_set, exclude_errors) = build_globset(&rule.exclude_globs);
+    for message in exclude_errors {
+        record_error(&mut scan, message);
+    }
+    let names = match manager.orphans(&options.runner) {
+        Ok(names) => names,
+        Err(err) => {
+            record_error(&mut scan, format!("{err:#}"));
+            return scan;
+        }
+    };
+    let names = names
+        .into_iter()
+        .filter(|name| !exclude_set.as_ref().is_some_and(|set| set.is_match(name)))
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return scan;
+    }
+    let sizes = manager
+        .installed_sizes(&options.runner, &names)
+        .unwrap_or_else(|err| {
+            record_error(&mut scan, format!("{err:#}"));
+            Default::default()
+        });
+    let packages = names
+        .iter()
+        .map(|name| Package {
+            name: name.clone(),
+            bytes: sizes.get(name).copied().unwrap_or(0),
+        })
+        .collect::<Vec<_>>();
+    let (program, args) = manager.remove_command(&names);
+    scan.push_command(CommandAction {
+        description: format!("Remove {} orphaned packages", names.len()),
+        program,
+        args,
+        bytes: packages.iter().map(|package| package.bytes).sum(),
+    });
+    scan.entries = packages.len();
+    scan.packages = packages;
+    scan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::command::CommandRunner;
+
+    #[test]
+    fn test_scan_skips_excluded_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        write_stub(
+            dir.path(),
+            "pacman",
+            r#"case "$1" in
+-Qdtq) printf 'libfoo\nlibbar\npython-old\n' ;;
+-Qi) shift; for name in "$@"; do printf 'Name            : %s\nInstalled Size  : 2.00 KiB\n\n' "$name"; done ;;
+esac"#,
+        );
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "o", "label": "O", "kind": "orphans", "exclude_globs": ["python-*"] }"#,
+        )
+        .unwrap();
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(dir.path()),
+            ..ScanOptions::default()
+        };
+
+        let scan = scan(&rule, &options);
+        assert_eq!(scan.errors, 0);
+        assert_eq!(scan.entries, 2);
+        assert_eq!(scan.bytes, 4096);
+        assert_eq!(
+            scan.commands[0].command_line(),
+            "pacman -Rn --noconfirm libfoo libbar"
+        );
+        assert_eq!(scan.packages[1].name, "libbar");
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use super::{record_error, CommandAction, RuleScan};
+use crate::config::Rule;
+use crate::options::ScanOptions;
+
+const DEPLOY_ROOT: &str = "/ostree/deploy";
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    deployments: Vec<Deployment>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Deployment {
+    osname: String,
+    checksum: String,
+    serial: u32,
+    #[serde(default)]
+    booted: bool,
+    #[serde(default)]
+    pinned: bool,
+}
+
+impl Deployment {
+    fn path(&self, deploy_root: &Path) -> PathBuf {
+        deploy_root
+            .join(&self.osname)
+            .join("deploy")
+            .join(format!("{}.{}", self.checksum, self.serial))
+    }
+}
+
+/// Offers `rpm-ostree cleanup -p -r` when there are pending or rollback
+/// deployments, sized by the files only those deployments use.
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    if !options.runner.has("rpm-ostree") {
+        return scan;
+    }
+    let deployments = match status(&scan) {
+        Ok(deployments) => deployments,
+        Err(err) => {
+            record_error(&mut scan, format!("{err:#}"));
+            return scan;
+        }
+    };
+    let (removable, kept): (Vec<_>, Vec<_>) = deployments
+        .iter()
+        .partition(|deployment| !deployment.booted && !deployment.pinned);
+    if removable.is_empty() {
+        return scan;
+    }
+    let deploy_root = Path::new(DEPLOY_ROOT);
+    let paths = |deployments: &[&Deployment]| {
+        deployments
+            .iter()
+            .map(|deployment| deployment.path(deploy_root))
+            .collect::<Vec<_>>()
+    };
+    let bytes = unique_bytes(&paths(&removable), &paths(&kept));
+    scan.push_command(CommandAction {
+        description: format!("Remove {} pending/rollback deployments", removable.len()),
+        program: "rpm-ostree".to_string(),
+        args: ["cleanup", "-p", "-r"].map(String::from).to_vec(),
+        bytes,
+    });
+    scan
+}
+
+fn status(scan: &RuleScan) -> Result<Vec<Deployment>> {
+    let output = scan.runner.stdout("rpm-ostree", ["status", "--json"])?;
+    parse_status(&output)
+}
+
+fn parse_status(json: &str) -> Result<Vec<Deployment>> {
+    let status: Status = serde_json::from_str(json).context("Failed to parse rpm-ostree status")?;
+    Ok(status.deployments)
+}
+
+/// Size of the files under `removable` that no `kept` tree shares. Deployments
+/// hardlink into the ostree repo, so this is what pruning gives back.
+fn unique_bytes(removable: &[PathBuf], kept: &[PathBuf]) -> u64 {
+    let mut seen = HashSet::new();
+    for root in kept {
+        for (inode, _) in files(root) {
+            seen.insert(inode);
+        }
+    }
+    removable
+        .iter()
+        .flat_map(|root| files(root))
+        .filter(|(inode, _)| seen.insert(*inode))
+        .map(|(_, size)| size)
+        .sum()
+}
+
+fn files(root: &Path) -> impl Iterator<Item = ((u64, u64), u64)> {
+    WalkDir::new(root)
+        .follow_links(false)
+        .same_file_system(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|meta| ((meta.dev(), meta.ino()), meta.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const STATUS: &str = r#"{
+        "deployments": [
+            {"id": "fedora-bbb.0", "osname": "fedora", "checksum": "bbb", "serial": 0, "booted": false, "staged": true},
+            {"id": "fedora-aaa.0", "osname": "fedora", "checksum": "aaa", "serial": 0, "booted": true},
+            {"id": "fedora-999.1", "osname": "fedora", "checksum": "999", "serial": 1, "booted": false, "pinned": true},
+            {"id": "fedora-888.0", "osname": "fedora", "checksum": "888", "serial": 0, "booted": false}
+        ],
+        "transaction": null
+    }"#;
+
+    #[test]
+    fn test_parse_status_and_unique_bytes() {
+        let deployments = parse_status(STATUS).unwrap();
+        let removable = deployments
+            .iter()
+            .filter(|deployment| !deployment.booted && !deployment.pinned)
+            .map(|deployment| deployment.path(Path::new("/ostree/deploy")))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            removable,
+            [
+                PathBuf::from("/ostree/deploy/fedora/deploy/bbb.0"),
+                PathBuf::from("/ostree/deploy/fedora/deploy/888.0"),
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let (booted, rollback) = (dir.path().join("booted"), dir.path().join("rollback"));
+        fs::create_dir_all(&booted).unwrap();
+        fs::create_dir_all(&rollback).unwrap();
+        fs::write(booted.join("shared"), "shared").unwrap();
+        fs::hard_link(booted.join("shared"), rollback.join("shared")).unwrap();
+        fs::write(rollback.join("old"), "only in rollback").unwrap();
+        assert_eq!(unique_bytes(&[rollback], &[booted]), 16);
+    }
+}
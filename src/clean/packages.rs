@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::command::CommandRunner;
+
+/// The system package manager, for rules that remove packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Dnf,
+    Apt,
+    Pacman,
+}
+
+impl PackageManager {
+    pub fn detect(runner: &CommandRunner) -> Option<Self> {
+        if runner.has("dnf") && runner.has("rpm") {
+            Some(PackageManager::Dnf)
+        } else if runner.has("apt-get") && runner.has("dpkg") {
+            Some(PackageManager::Apt)
+        } else if runner.has("pacman") {
+            Some(PackageManager::Pacman)
+        } else {
+            None
+        }
+    }
+
+    /// Packages owning `path`; empty when none does.
+    pub fn owners(self, runner: &CommandRunner, path: &Path) -> Vec<String> {
+        let path = path.as_os_str();
+        let output = match self {
+            PackageManager::Dnf => runner.stdout("rpm", ["-qf".as_ref(), path]),
+            PackageManager::Apt => runner.stdout("dpkg", ["-S".as_ref(), path]),
+            PackageManager::Pacman => runner.stdout("pacman", ["-Qoq".as_ref(), path]),
+        };
+        match (self, output) {
+            (_, Err(_)) => Vec::new(),
+            (PackageManager::Apt, Ok(output)) => parse_dpkg_search(&output),
+            (_, Ok(output)) => lines(&output),
+        }
+    }
+
+    /// Packages installed as dependencies that nothing requires anymore.
+    pub fn orphans(self, runner: &CommandRunner) -> Result<Vec<String>> {
+        Ok(match self {
+            // Exits 1 when there are none.
+            PackageManager::Pacman => lines(&unchecked_stdout(runner, "pacman", ["-Qdtq"])?),
+            // Exits 1 for the declined transaction.
+            PackageManager::Dnf => parse_dnf_autoremove(&unchecked_stdout(
+                runner,
+                "dnf",
+                ["autoremove", "--assumeno"],
+            )?),
+            PackageManager::Apt => {
+                parse_apt_autoremove(&runner.stdout("apt-get", ["autoremove", "-s"])?)
+            }
+        })
+    }
+
+    /// Installed size of each of `packages`, in bytes.
+    pub fn installed_sizes(
+        self,
+        runner: &CommandRunner,
+        packages: &[String],
+    ) -> Result<HashMap<String, u64>> {
+        if packages.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let query = |program: &str, args: &[&str]| {
+            let args = args
+                .iter()
+                .map(OsStr::new)
+                .chain(packages.iter().map(OsStr::new));
+            runner.stdout(program, args)
+        };
+        Ok(match self {
+            PackageManager::Dnf => {
+                parse_name_sizes(&query("rpm", &["-q", "--qf", "%{NAME} %{SIZE}\\n"])?, 1)
+            }
+            PackageManager::Apt => parse_name_sizes(
+                &query(
+                    "dpkg-query",
+                    &["-W", "-f", "${binary:Package} ${Installed-Size}\\n"],
+                )?,
+                1024,
+            ),
+            PackageManager::Pacman => parse_pacman_info(&query("pacman", &["-Qi"])?),
+        })
+    }
+
+    /// Command removing exactly `packages`, without cascading into their
+    /// dependencies.
+    pub fn remove_command(self, packages: &[String]) -> (String, Vec<String>) {
+        let (program, args): (&str, &[&str]) = match self {
+            PackageManager::Dnf => ("dnf", &["remove", "-y", "--noautoremove"]),
+            PackageManager::Apt => ("apt-get", &["remove", "--purge", "-y"]),
+            PackageManager::Pacman => ("pacman", &["-Rn", "--noconfirm"]),
+        };
+        let mut args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        args.extend(packages.iter().cloned());
+        (program.to_string(), args)
+    }
+}
+
+/// Stdout of a tool whose exit status doesn't tell failure from "nothing to
+/// do".
+fn unchecked_stdout<const N: usize>(
+    runner: &CommandRunner,
+    program: &str,
+    args: [&str; N],
+) -> Result<String> {
+    let output = runner
+        .command(program)?
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {program}"))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn lines(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Packages in `dpkg -S` output (`pkg1, pkg2: /path` lines).
+fn parse_dpkg_search(output: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    for line in output.lines() {
+        let Some((owners, _)) = line.split_once(": ") else {
+            continue;
+        };
+        for owner in owners.split(',').map(str::trim) {
+            if !owner.is_empty() && !packages.iter().any(|known| known == owner) {
+                packages.push(owner.to_string());
+            }
+        }
+    }
+    packages
+}
+
+/// Package names in the `Removing...:` sections of the transaction table
+/// `dnf autoremove` prints (dnf 4 and 5). Names too long for their column
+/// sit alone on a line, with the rest of the row on the next one.
+fn parse_dnf_autoremove(output: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    let mut in_section = false;
+    let mut wrapped = false;
+    for line in output.lines() {
+        if !line.starts_with(' ') {
+            in_section = line.starts_with("Removing") && line.trim_end().ends_with(':');
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        if !in_section || std::mem::take(&mut wrapped) {
+            continue;
+        }
+        if let Some(name) = fields.next() {
+            wrapped = fields.next().is_none();
+            packages.push(name.to_string());
+        }
+    }
+    packages
+}
+
+/// Package names in the `Remv <name> [<version>]` lines of
+/// `apt-get autoremove -s`.
+fn parse_apt_autoremove(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.strip_prefix("Remv "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+/// `<name> <size>` lines, with sizes in units of `unit` bytes. Sizes of
+/// packages installed for several architectures add up.
+fn parse_name_sizes(output: &str, unit: u64) -> HashMap<String, u64> {
+    let mut sizes = HashMap::new();
+    for line in output.lines() {
+        let mut fields = line.split_whitespace();
+        if let (Some(name), Some(Ok(size))) = (fields.next(), fields.next().map(str::parse::<u64>))
+        {
+            *sizes.entry(name.to_string()).or_insert(0) += size * unit;
+        }
+    }
+    sizes
+}
+
+/// `Name` and `Installed Size` of each package in `pacman -Qi` output.
+fn parse_pacman_info(output: &str) -> HashMap<String, u64> {
+    let mut sizes = HashMap::new();
+    let mut name = None;
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "Name" => name = Some(value.trim().to_string()),
+            "Installed Size" => {
+                if let (Some(name), Some(bytes)) = (name.take(), parse_human_size(value)) {
+                    sizes.insert(name, bytes);
+                }
+            }
+            _ => {}
+        }
+    }
+    sizes
+}
+
+/// `1.50 MiB`, `512.00 KiB`, `0.00 B`.
+fn parse_human_size(value: &str) -> Option<u64> {
+    let (number, unit) = value.trim().split_once(' ')?;
+    let number = number.replace(',', ".").parse::<f64>().ok()?;
+    let multiplier = match unit.trim() {
+        "B" => 1u64,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dpkg_search() {
+        assert_eq!(
+            parse_dpkg_search(
+                "linux-modules-6.5.0-14-generic, linux-modules-extra-6.5.0-14-generic: /usr/lib/modules/6.5.0-14-generic\n"
+            ),
+            [
+                "linux-modules-6.5.0-14-generic",
+                "linux-modules-extra-6.5.0-14-generic"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_orphan_listings() {
+        let dnf4 = "Dependencies resolved.
+================================================================================
+ Package                     Arch     Version            Repository       Size
+================================================================================
+Removing:
+ libfoo                      x86_64   1.2-3.fc40         @fedora         120 k
+ python3-a-very-long-package-name-indeed
+                             noarch   0.9-1.fc40         @updates         33 k
+
+Transaction Summary
+================================================================================
+Remove  2 Packages
+";
+        assert_eq!(
+            parse_dnf_autoremove(dnf4),
+            ["libfoo", "python3-a-very-long-package-name-indeed"]
+        );
+        let dnf5 = "Package        Arch    Version      Repository      Size
+Removing unused dependencies:
+ libbar        x86_64  2.0-1.fc41   fedora       1.0 MiB
+
+Transaction Summary:
+ Removing:           1 package
+";
+        assert_eq!(parse_dnf_autoremove(dnf5), ["libbar"]);
+
+        let apt = "Reading package lists...
+The following packages will be REMOVED:
+  libfoo1 libbar2:i386
+Remv libfoo1 [1.2-3]
+Remv libbar2:i386 [2.0-1]
+";
+        assert_eq!(parse_apt_autoremove(apt), ["libfoo1", "libbar2:i386"]);
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        let info = "Name            : libfoo
+Version         : 1.2-3
+Installed Size  : 1.50 MiB
+
+Name            : libbar
+Installed Size  : 512.00 KiB
+";
+        let sizes = parse_pacman_info(info);
+        assert_eq!(sizes["libfoo"], 1_572_864);
+        assert_eq!(sizes["libbar"], 524_288);
+        assert_eq!(
+            parse_name_sizes("libfoo1 120\nlibfoo1 8\n", 1024)["libfoo1"],
+            131_072
+        );
+    }
+
+    #[test]
+    fn test_remove_command_does_not_cascade() {
+        let packages = ["libfoo".to_string()];
+        let command_line = |manager: PackageManager| {
+            let (program, args) = manager.remove_command(&packages);
+            format!("{program} {}", args.join(" "))
+        };
+        assert_eq!(
+            command_line(PackageManager::Dnf),
+            "dnf remove -y --noautoremove libfoo"
+        );
+        assert_eq!(
+            command_line(PackageManager::Apt),
+            "apt-get remove --purge -y libfoo"
+        );
+        assert_eq!(
+            command_line(PackageManager::Pacman),
+            "pacman -Rn --noconfirm libfoo"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{build_globset, record_error, rule_roots, scan_root, CommandAction, RuleScan};
+use crate::config::{Rule, SnapCategory};
+use crate::options::ScanOptions;
+
+/// Where snapd keeps the `.snap` file of every installed revision.
+const SNAPS_DIR: &str = "/var/lib/snapd/snaps";
+/// Where snaps are mounted; `current` in each points at the active revision.
+/// Ubuntu uses `/snap`, Fedora and Arch `/var/lib/snapd/snap`.
+const MOUNT_DIRS: [&str; 2] = ["/snap", "/var/lib/snapd/snap"];
+
+/// An installed revision, from `<name>_<revision>.snap`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    /// Snap (or parallel instance) name, e.g. `firefox` or `firefox_beta`.
+    pub name: String,
+    /// `1234`, or `x1` for sideloaded snaps.
+    pub revision: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Every revision under `snaps_dir`, sorted by name then file name.
+pub fn installed_revisions(snaps_dir: &Path) -> Vec<Revision> {
+    let Ok(entries) = fs::read_dir(snaps_dir) else {
+        return Vec::new();
+    };
+    let mut revisions = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let (name, revision) = file_name.strip_suffix(".snap")?.rsplit_once('_')?;
+            Some(Revision {
+                name: name.to_string(),
+                revision: revision.to_string(),
+                path: entry.path(),
+                bytes: entry.metadata().map(|meta| meta.len()).unwrap_or(0),
+            })
+        })
+        .collect::<Vec<_>>();
+    revisions.sort_by(|a, b| (&a.name, &a.path).cmp(&(&b.name, &b.path)));
+    revisions
+}
+
+/// Active revision of each snap, from the `current` links in the mount dirs.
+fn current_revisions(options: &ScanOptions, revisions: &[Revision]) -> HashMap<String, String> {
+    let mut current = HashMap::new();
+    for revision in revisions {
+        if current.contains_key(&revision.name) {
+            continue;
+        }
+        let active = MOUNT_DIRS.iter().find_map(|dir| {
+            let link = options.root.join(Path::new(dir)).ok()?;
+            fs::read_link(link.join(&revision.name).join("current")).ok()
+        });
+        if let Some(active) = active {
+            current.insert(revision.name.clone(), active.to_string_lossy().into_owned());
+        }
+    }
+    current
+}
+
+/// Revisions snapd keeps around for `snap revert`. Snaps whose active
+/// revision is unknown are left alone.
+pub fn disabled_revisions(
+    revisions: &[Revision],
+    current: &HashMap<String, String>,
+) -> Vec<Revision> {
+    revisions
+        .iter()
+        .filter(|revision| {
+            current
+                .get(&revision.name)
+                .is_some_and(|active| *active != revision.revision)
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn scan(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    match rule.snap {
+        SnapCategory::DisabledRevisions => scan_disabled(rule, options),
+        SnapCategory::RevisionData => scan_revision_data(rule, options),
+    }
+}
+
+/// Offers `snap remove --revision` for each disabled revision in the snaps
+/// directories in the rule's paths.
+fn scan_disabled(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    if !options.runner.has("snap") {
+        return scan;
+    }
+    for snaps_dir in rule_roots(rule, options, &mut scan) {
+        let revisions = installed_revisions(&snaps_dir);
+        let current = current_revisions(options, &revisions);
+        for revision in disabled_revisions(&revisions, &current) {
+            scan.push_command(CommandAction {
+                description: format!(
+                    "Remove disabled revision {} of {}",
+                    revision.revision, revision.name
+                ),
+                program: "snap".to_string(),
+                args: vec![
+                    "remove".to_string(),
+                    "--revision".to_string(),
+                    revision.revision,
+                    revision.name,
+                ],
+                bytes: revision.bytes,
+            });
+        }
+    }
+    scan
+}
+
+/// Scans `~/snap` directories (the rule's paths) for the data of revisions
+/// that are no longer installed. `common` and `current` are kept.
+fn scan_revision_data(rule: &Rule, options: &ScanOptions) -> RuleScan {
+    let mut scan = RuleScan::new(rule, options);
+    let (exclude_set, exclude_errors) = build_globset(&rule.exclude_globs);
+    for message in exclude_errors {
+        record_error(&mut scan, message);
+    }
+    let Ok(snaps_dir) = options.root.join(Path::new(SNAPS_DIR)) else {
+        return scan;
+    };
+    let revisions = installed_revisions(&snaps_dir);
+    // Without snapd's own records, every revision would look removed.
+    if revisions.is_empty() {
+        return scan;
+    }
+    for snap_home in rule_roots(rule, options, &mut scan) {
+        for target in removed_revision_dirs(&snap_home, &revisions) {
+            scan.dirs.push(target.clone());
+            for message in scan_root(&target, exclude_set.as_ref(), &mut scan) {
+                record_error(&mut scan, message);
+            }
+        }
+    }
+    scan
+}
+
+/// `<snap_home>/<name>/<revision>` directories of revisions not in
+/// `revisions`.
+fn removed_revision_dirs(snap_home: &Path, revisions: &[Revision]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for name in entries(snap_home, |kind| kind.is_dir()) {
+        for revision in entries(&snap_home.join(&name), |kind| kind.is_dir()) {
+            if !is_revision(&revision) {
+                continue;
+            }
+            let installed = revisions
+                .iter()
+                .any(|installed| installed.name == name && installed.revision == revision);
+            if !installed {
+                dirs.push(snap_home.join(&name).join(revision));
+            }
+        }
+    }
+    dirs.sort();
+    dirs
+}
+
+/// `1234` or `x1`; anything else (`common`, `current`) isn't a revision.
+fn is_revision(name: &str) -> bool {
+    let digits = name.strip_prefix('x').unwrap_or(name);
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Names of the entries in `path` whose type (not followed) passes `keep`.
+fn entries(path: &Path, keep: impl Fn(fs::FileType) -> bool) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(&keep))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::command::CommandRunner;
+    use crate::sysroot::SysRoot;
+    use std::os::unix::fs::symlink;
+
+    /// Installs `revisions` of `name` under `root`, the last one active.
+    fn install(root: &Path, name: &str, revisions: &[&str]) {
+        let snaps = root.join("var/lib/snapd/snaps");
+        fs::create_dir_all(&snaps).unwrap();
+        for (size, revision) in revisions.iter().enumerate() {
+            let file = snaps.join(format!("{name}_{revision}.snap"));
+            fs::write(file, vec![0u8; 100 * (size + 1)]).unwrap();
+        }
+        let mount = root.join("snap").join(name);
+        fs::create_dir_all(&mount).unwrap();
+        symlink(revisions.last().unwrap(), mount.join("current")).unwrap();
+    }
+
+    fn rule(json: &str) -> Rule {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_disabled_revisions_become_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        install(dir.path(), "firefox", &["4793", "4848"]);
+        install(dir.path(), "core22", &["1380"]);
+        let bin = dir.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        write_stub(&bin, "snap", "exit 0");
+        let options = ScanOptions {
+            runner: CommandRunner::with_search_path(&bin),
+            root: SysRoot::new(dir.path()).unwrap(),
+            ..ScanOptions::default()
+        };
+
+        let scan = scan(
+            &rule(
+                r#"{ "id": "s", "label": "S", "kind": "snap", "paths": ["/var/lib/snapd/snaps"] }"#,
+            ),
+            &options,
+        );
+        assert_eq!(scan.errors, 0);
+        assert_eq!(scan.commands.len(), 1);
+        assert_eq!(
+            scan.commands[0].command_line(),
+            "snap remove --revision 4793 firefox"
+        );
+        assert_eq!(scan.bytes, 100);
+    }
+
+    #[test]
+    fn test_removed_revision_data() {
+        let dir = tempfile::tempdir().unwrap();
+        install(dir.path(), "firefox", &["4793", "4848"]);
+        let snap_home = dir.path().join("home/alice/snap");
+        for revision in ["4700", "4793", "4848", "common"] {
+            let data = snap_home.join("firefox").join(revision);
+            fs::create_dir_all(&data).unwrap();
+            fs::write(data.join("prefs.js"), "prefs").unwrap();
+        }
+        symlink("4848", snap_home.join("firefox/current")).unwrap();
+        fs::create_dir_all(snap_home.join("gone/x1")).unwrap();
+
+        let revisions = installed_revisions(&dir.path().join("var/lib/snapd/snaps"));
+        assert_eq!(
+            removed_revision_dirs(&snap_home, &revisions),
+            [snap_home.join("firefox/4700"), snap_home.join("gone/x1")]
+        );
+        assert!(is_revision("x2"));
+        assert!(!is_revision("x"));
+    }
+}
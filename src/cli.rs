@@ -1,8 +1,12 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
+use crate::dupes::{DupeAction, Keep};
 use crate::options::DownloadsChoice;
+use crate::space::SpaceAmount;
+use crate::units::{parse_duration, parse_size};
 
 #[derive(Parser, Debug)]
 #[command(name = "vole")]
@@ -12,6 +16,11 @@ pub struct Cli {
     #[arg(long)]
     pub config: Option<PathBuf>,
 
+    /// Clean the system under this directory (a chroot, container image or
+    /// mounted disk) instead of the live one.
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -20,6 +29,16 @@ pub struct Cli {
 pub enum Commands {
     /// Scan and clean using the CLI (or launch the clean TUI).
     Clean(CleanArgs),
+    /// Manage systemd timers that run `vole clean` on a schedule.
+    Schedule(ScheduleArgs),
+    /// List, prune and restore snapshots Vole created.
+    Snapshot(SnapshotArgs),
+    /// Restore files from an archive backup taken before cleaning.
+    Restore(RestoreArgs),
+    /// Find duplicate files and delete or relink the extra copies.
+    Dupes(DupesArgs),
+    /// Find big files that haven't been used in a while.
+    Large(LargeArgs),
 }
 
 #[derive(ValueEnum, Debug, Clone, Copy)]
@@ -63,6 +82,30 @@ pub struct CleanArgs {
     #[arg(long)]
     pub yes: bool,
 
+    /// Never read from stdin; fail instead of prompting.
+    #[arg(long)]
+    pub non_interactive: bool,
+
+    /// With --sudo: also clean user rules in every regular user's home.
+    #[arg(long)]
+    pub all_users: bool,
+
+    /// With --sudo: clean user rules in this user's home (repeatable).
+    #[arg(long = "user", value_name = "NAME")]
+    pub users: Vec<String>,
+
+    /// Uid range of regular users for --all-users (default from /etc/login.defs).
+    #[arg(long, value_name = "MIN-MAX")]
+    pub uid_range: Option<String>,
+
+    /// Only clean if an affected filesystem has less free space than this (e.g. 10%, 20GiB).
+    #[arg(long, value_name = "AMOUNT")]
+    pub when_free_below: Option<SpaceAmount>,
+
+    /// Apply rules in order and stop once this much space is free (e.g. 30GiB, 25%).
+    #[arg(long, value_name = "AMOUNT")]
+    pub until_free: Option<SpaceAmount>,
+
     /// Limit to specific rule IDs (repeatable).
     #[arg(long = "rule")]
     pub rules: Vec<String>,
@@ -72,10 +115,60 @@ pub struct CleanArgs {
     pub list_rules: bool,
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct ScheduleArgs {
+    /// Write units here instead of the systemd unit directory (skips systemctl).
+    #[arg(long, global = true)]
+    pub unit_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: ScheduleCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ScheduleCommand {
+    /// Generate and enable a .service/.timer pair (system units when run as root).
+    Install(ScheduleInstallArgs),
+    /// List installed Vole schedules.
+    List,
+    /// Disable and delete a schedule.
+    Remove(ScheduleRemoveArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ScheduleInstallArgs {
+    /// How often to run: hourly, daily, weekly, monthly or an OnCalendar= expression.
+    #[arg(long, default_value = "weekly")]
+    pub every: String,
+
+    /// Rule IDs to run (repeatable or comma-separated; defaults to the default rules).
+    #[arg(long = "rule", alias = "rules", value_delimiter = ',')]
+    pub rules: Vec<String>,
+
+    /// Schedule name, used in the unit file names.
+    #[arg(long, default_value = "default")]
+    pub name: String,
+
+    /// For Downloads cleanup, remove either archives or matching folders.
+    #[arg(long, value_enum)]
+    pub downloads_remove: Option<DownloadsRemove>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ScheduleRemoveArgs {
+    /// Schedule name to remove.
+    #[arg(default_value = "default")]
+    pub name: String,
+}
+
 impl CleanArgs {
     pub fn effective_dry_run(&self) -> bool {
         self.dry_run
     }
+
+    pub fn multi_user(&self) -> bool {
+        self.all_users || !self.users.is_empty()
+    }
 }
 
 impl From<DownloadsRemove> for DownloadsChoice {
@@ -86,3 +179,178 @@ impl From<DownloadsRemove> for DownloadsChoice {
         }
     }
 }
+
+#[derive(Args, Debug, Clone)]
+pub struct SnapshotArgs {
+    #[command(subcommand)]
+    pub command: SnapshotCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnapshotCommand {
+    /// List Vole's snapshots across all supported providers.
+    List,
+    /// Delete old Vole snapshots.
+    Prune(SnapshotPruneArgs),
+    /// Copy files back out of a snapshot (only missing files are restored).
+    Restore(SnapshotRestoreArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SnapshotPruneArgs {
+    /// Keep this many of the newest snapshots of each source.
+    #[arg(long)]
+    pub keep: Option<usize>,
+
+    /// Only delete snapshots older than this (e.g. 30d, 12h, 2w).
+    #[arg(long, value_parser = parse_duration)]
+    pub older_than: Option<Duration>,
+
+    /// Show what would be deleted without deleting.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Skip the confirmation prompt.
+    #[arg(long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SnapshotRestoreArgs {
+    /// Snapshot ID as shown by `vole snapshot list`.
+    pub id: String,
+
+    /// Path to restore (repeatable).
+    #[arg(long = "path", required_unless_present = "all")]
+    pub paths: Vec<PathBuf>,
+
+    /// Restore every file missing from the snapshotted filesystem.
+    #[arg(long, conflicts_with = "paths")]
+    pub all: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RestoreArgs {
+    /// Archive file (`vole-clean-<timestamp>.tar.zst`).
+    pub archive: PathBuf,
+
+    /// Path to restore (repeatable; defaults to everything in the archive).
+    #[arg(long = "path")]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DupesArgs {
+    /// Directories to search (defaults to the home directory).
+    pub paths: Vec<PathBuf>,
+
+    /// Skip paths matching this glob, relative to each searched directory (repeatable).
+    #[arg(long = "exclude", value_name = "GLOB")]
+    pub excludes: Vec<String>,
+
+    /// Which copy of each set to keep.
+    #[arg(long, value_enum, default_value_t = DupesKeep::Oldest)]
+    pub keep: DupesKeep,
+
+    /// What to do with the other copies.
+    #[arg(long, value_enum, default_value_t = DupesAction::Delete)]
+    pub action: DupesAction,
+
+    /// Pick the copies to remove in the interactive TUI.
+    #[arg(long)]
+    pub tui: bool,
+
+    /// Preview without changing files (writes ~/vole-dry-run.txt).
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Skip the confirmation prompt when applying.
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Never read from stdin; fail instead of prompting.
+    #[arg(long)]
+    pub non_interactive: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LargeArgs {
+    /// Directories to search (defaults to the home directory).
+    pub paths: Vec<PathBuf>,
+
+    /// Only list files at least this big (e.g. 500M, 2GiB).
+    #[arg(long, value_parser = parse_size, default_value = "100M")]
+    pub min_size: u64,
+
+    /// Only list files not read or written for this long (e.g. 180d, 26w).
+    #[arg(long, value_parser = parse_duration)]
+    pub not_accessed: Option<Duration>,
+
+    /// Skip paths matching this glob, relative to each searched directory (repeatable).
+    #[arg(long = "exclude", value_name = "GLOB")]
+    pub excludes: Vec<String>,
+
+    /// Pick the files to remove in the interactive TUI.
+    #[arg(long)]
+    pub tui: bool,
+
+    /// Delete every listed file (without --tui, files are only listed otherwise).
+    #[arg(long, conflicts_with_all = ["tui", "trash"])]
+    pub delete: bool,
+
+    /// Move files to the Trash instead of deleting them.
+    #[arg(long)]
+    pub trash: bool,
+
+    /// Create a snapshot before removing (if supported).
+    #[arg(long)]
+    pub snapshot: bool,
+
+    /// Preview without removing (writes ~/vole-dry-run.txt).
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Skip the confirmation prompt when applying.
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Never read from stdin; fail instead of prompting.
+    #[arg(long)]
+    pub non_interactive: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+#[clap(rename_all = "kebab-case")]
+pub enum DupesKeep {
+    Oldest,
+    Newest,
+    ShortestPath,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+#[clap(rename_all = "kebab-case")]
+pub enum DupesAction {
+    Delete,
+    Hardlink,
+    Reflink,
+}
+
+impl From<DupesKeep> for Keep {
+    fn from(value: DupesKeep) -> Self {
+        match value {
+            DupesKeep::Oldest => Keep::Oldest,
+            DupesKeep::Newest => Keep::Newest,
+            DupesKeep::ShortestPath => Keep::ShortestPath,
+        }
+    }
+}
+
+impl From<DupesAction> for DupeAction {
+    fn from(value: DupesAction) -> Self {
+        match value {
+            DupesAction::Delete => DupeAction::Delete,
+            DupesAction::Hardlink => DupeAction::Hardlink,
+            DupesAction::Reflink => DupeAction::Reflink,
+        }
+    }
+}
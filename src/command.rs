@@ -0,0 +1,108 @@
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use anyhow::{bail, Context, Result};
+
+/// Runs external tools (`zfs`, `lvs`, `snapper`, ...) looked up on a search
+/// path, so tests can point it at a directory of stub binaries.
+#[derive(Debug, Clone, Default)]
+pub struct CommandRunner {
+    search_path: Option<OsString>,
+}
+
+impl CommandRunner {
+    /// Looks programs up on `$PATH`.
+    pub fn system() -> Self {
+        Self::default()
+    }
+
+    /// Looks programs up only in `search_path` (a `PATH`-style list).
+    #[cfg(test)]
+    pub fn with_search_path(search_path: impl Into<OsString>) -> Self {
+        Self {
+            search_path: Some(search_path.into()),
+        }
+    }
+
+    pub fn find(&self, program: &str) -> Option<PathBuf> {
+        match &self.search_path {
+            Some(path) => which::which_in(program, Some(path), "/").ok(),
+            None => which::which(program).ok(),
+        }
+    }
+
+    pub fn has(&self, program: &str) -> bool {
+        self.find(program).is_some()
+    }
+
+    /// A [`Command`] for `program`, for callers that need to stream output.
+    pub fn command(&self, program: &str) -> Result<Command> {
+        let path = self
+            .find(program)
+            .with_context(|| format!("{program} not found"))?;
+        Ok(Command::new(path))
+    }
+
+    /// Runs `program` and returns its output, failing on a non-zero exit.
+    pub fn output<I, S>(&self, program: &str, args: I) -> Result<Output>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self
+            .command(program)?
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {program}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("{program} failed: {}", stderr.trim());
+        }
+        Ok(output)
+    }
+
+    /// Runs `program` and returns its stdout as text.
+    pub fn stdout<I, S>(&self, program: &str, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self.output(program, args)?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    /// Writes an executable shell script named `name` into `dir`.
+    pub fn write_stub(dir: &Path, name: &str, script: &str) {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::write_stub;
+    use super::*;
+
+    #[test]
+    fn test_runner_uses_search_path() {
+        let dir = tempfile::tempdir().unwrap();
+        write_stub(dir.path(), "greet", "echo \"hello $1\"");
+        write_stub(dir.path(), "broken", "echo oops >&2; exit 3");
+        let runner = CommandRunner::with_search_path(dir.path());
+
+        let output = runner.output("greet", ["vole"]).unwrap();
+        assert_eq!(output.stdout, b"hello vole\n");
+        let err = runner.output("broken", [""; 0]).unwrap_err();
+        assert_eq!(err.to_string(), "broken failed: oops");
+        assert!(!runner.has("definitely-not-installed"));
+    }
+}
@@ -5,7 +5,7 @@ use anyhow::{bail, Context, Result};
 use directories::ProjectDirs;
 use serde::Deserialize;
 
-use crate::distro::Distro;
+use crate::distro::{Distro, DistroMatcher};
 
 const DEFAULT_CONFIG: &str = include_str!("../config/default.json");
 
@@ -14,6 +14,41 @@ pub struct Config {
     pub version: u8,
     #[serde(default)]
     pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnapshotConfig {
+    /// Copy-on-write space for snapshots of classic (thick) LVM volumes, in
+    /// `lvcreate --size` form (e.g. `5G`). Thick volumes are refused without it.
+    #[serde(default)]
+    pub lvm_size: Option<String>,
+    /// Back up files no snapshot provider covers into a compressed archive.
+    #[serde(default = "default_archive_fallback")]
+    pub archive_fallback: bool,
+    /// Where archives go (default: /var/lib/vole/archives for root, the user
+    /// data directory otherwise).
+    #[serde(default)]
+    pub archive_dir: Option<PathBuf>,
+    /// Largest amount of data to archive, e.g. `4GiB`.
+    #[serde(default)]
+    pub archive_max_size: Option<String>,
+}
+
+fn default_archive_fallback() -> bool {
+    true
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            lvm_size: None,
+            archive_fallback: default_archive_fallback(),
+            archive_dir: None,
+            archive_max_size: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -31,11 +66,54 @@ pub struct Rule {
     #[serde(default)]
     pub enabled_by_default: bool,
     #[serde(default)]
-    pub distros: Vec<String>,
+    pub distros: Vec<DistroMatcher>,
     #[serde(default)]
     pub exclude_globs: Vec<String>,
+    /// Age cutoff: files older than this for `logs` rules, generations older
+    /// than this for `nix` rules.
     #[serde(default)]
     pub older_than_days: Option<u64>,
+    /// How many of the newest items to keep: kernels for `kernels` rules,
+    /// crashes of each executable for `crashes` rules.
+    #[serde(default)]
+    pub keep_newest: Option<usize>,
+    /// Size the journal is vacuumed down to (`--vacuum-size`), for `journal`
+    /// rules; `older_than_days` gives `--vacuum-time`.
+    #[serde(default)]
+    pub vacuum_size: Option<String>,
+    /// Only offer the rule on traditional, package-managed systems (for
+    /// package caches that image-based systems don't use).
+    #[serde(default)]
+    pub mutable_only: bool,
+    /// Category for `flatpak` rules.
+    #[serde(default)]
+    pub flatpak: FlatpakCategory,
+    /// Category for `snap` rules.
+    #[serde(default)]
+    pub snap: SnapCategory,
+    /// Which journal a `journal` rule vacuums.
+    #[serde(default)]
+    pub journal: JournalScope,
+    /// Category for `containers` rules.
+    #[serde(default)]
+    pub containers: ContainerCategory,
+    /// Cleanup tier: 1 is cheapest to regenerate and is applied first.
+    #[serde(default = "default_tier")]
+    pub tier: u8,
+}
+
+pub const DEFAULT_TIER: u8 = 2;
+
+fn default_tier() -> u8 {
+    DEFAULT_TIER
+}
+
+pub fn tier_label(tier: u8) -> &'static str {
+    match tier {
+        0 | 1 => "safe to discard",
+        2 => "regenerated on demand",
+        _ => "expensive to regenerate",
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
@@ -45,6 +123,83 @@ pub enum RuleKind {
     Paths,
     Downloads,
     Logs,
+    /// `rpm-ostree cleanup -p -r`: pending and rollback deployments.
+    RpmOstree,
+    /// `nix-collect-garbage --delete-older-than`.
+    Nix,
+    /// Flatpak runtimes, app data and caches; see [`FlatpakCategory`].
+    #[serde(alias = "flatpak-unused")]
+    Flatpak,
+    /// Old snap revisions and their data; see [`SnapCategory`]. Only offered
+    /// where snapd is installed.
+    Snap,
+    /// Old kernels, removed through the package manager, and module
+    /// directories no package owns.
+    Kernels,
+    /// Packages installed as dependencies that nothing requires anymore,
+    /// removed through the package manager. `exclude_globs` match package
+    /// names.
+    Orphans,
+    /// Vacuums the systemd journal to `older_than_days` and `vacuum_size`;
+    /// see [`JournalScope`].
+    Journal,
+    /// Core dumps, apport reports and ABRT problem directories in `paths`.
+    Crashes,
+    /// `docker`/`podman` prune commands; see [`ContainerCategory`].
+    Containers,
+}
+
+/// What a `flatpak` rule cleans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlatpakCategory {
+    /// Runtimes and extensions no installed app uses, in each installation
+    /// in `paths`.
+    #[default]
+    UnusedRuntimes,
+    /// `~/.var/app/<id>` of apps that are no longer installed.
+    LeftoverData,
+    /// `~/.var/app/<id>/cache` of every app.
+    AppCache,
+}
+
+/// What a `snap` rule cleans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapCategory {
+    /// Disabled revisions in the snaps directories in `paths`, removed with
+    /// `snap remove --revision`.
+    #[default]
+    DisabledRevisions,
+    /// `~/snap/<name>/<revision>` of revisions that are no longer installed.
+    RevisionData,
+}
+
+/// Which journal a `journal` rule vacuums.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum JournalScope {
+    /// Every journal on the system, with `journalctl` (needs root).
+    #[default]
+    System,
+    /// The user's archived `user-<uid>@*.journal` files, deleted directly
+    /// (needs root, as journald owns them).
+    User,
+}
+
+/// What a `containers` rule prunes, with each of docker and podman.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerCategory {
+    /// Untagged images (`image prune`).
+    #[default]
+    DanglingImages,
+    /// `container prune`.
+    StoppedContainers,
+    /// Volumes no container uses (`volume prune`).
+    UnusedVolumes,
+    /// Docker's build cache (`builder prune`).
+    BuildCache,
 }
 
 impl Config {
@@ -74,13 +229,22 @@ impl Config {
         Ok(config)
     }
 
+    /// Rules that apply to `distro`, ordered by tier (config order within a tier).
+    /// Paths on read-only mounts are dropped, and rules left without paths
+    /// are hidden.
     pub fn available_rules(&self, distro: &Distro) -> Vec<Rule> {
-        let ids = distro.identifiers();
-        self.rules
+        let mut rules = self
+            .rules
             .iter()
-            .filter(|rule| rule.matches_distro(&ids))
-            .cloned()
-            .collect()
+            .filter(|rule| rule.matches_distro(distro))
+            .filter(|rule| !(rule.mutable_only && distro.image.is_some()))
+            .filter(|rule| rule.kind != RuleKind::Snap || distro.snapd)
+            // Cleanup tools act on the live system, never on `--root`.
+            .filter(|rule| distro.root.is_host() || !rule.runs_tool())
+            .filter_map(|rule| rule.without_read_only_paths(distro))
+            .collect::<Vec<_>>();
+        rules.sort_by_key(|rule| rule.tier);
+        rules
     }
 
     fn ensure_supported(&self) -> Result<()> {
@@ -92,16 +256,64 @@ impl Config {
 }
 
 impl Rule {
-    pub fn matches_distro(&self, distro_ids: &[String]) -> bool {
-        if self.distros.is_empty() {
-            return true;
+    /// A rule for files the user picked by hand (e.g. in `vole large`)
+    /// rather than matched from the ruleset. Such files can't be
+    /// regenerated, so it's in the last tier.
+    pub fn selection(id: &str, label: &str) -> Rule {
+        Rule {
+            id: id.to_string(),
+            label: label.to_string(),
+            kind: RuleKind::Paths,
+            description: None,
+            paths: Vec::new(),
+            requires_sudo: false,
+            enabled_by_default: false,
+            distros: Vec::new(),
+            exclude_globs: Vec::new(),
+            older_than_days: None,
+            keep_newest: None,
+            vacuum_size: None,
+            mutable_only: false,
+            flatpak: FlatpakCategory::default(),
+            snap: SnapCategory::default(),
+            journal: JournalScope::default(),
+            containers: ContainerCategory::default(),
+            tier: 3,
+        }
+    }
+
+    pub fn matches_distro(&self, distro: &Distro) -> bool {
+        self.distros.is_empty() || self.distros.iter().any(|matcher| matcher.matches(distro))
+    }
+
+    /// Rules that run a cleanup tool instead of deleting files under `paths`.
+    pub fn runs_tool(&self) -> bool {
+        match self.kind {
+            RuleKind::RpmOstree
+            | RuleKind::Nix
+            | RuleKind::Kernels
+            | RuleKind::Orphans
+            | RuleKind::Containers => true,
+            RuleKind::Journal => self.journal == JournalScope::System,
+            RuleKind::Flatpak => self.flatpak == FlatpakCategory::UnusedRuntimes,
+            RuleKind::Snap => self.snap == SnapCategory::DisabledRevisions,
+            RuleKind::Paths | RuleKind::Downloads | RuleKind::Logs | RuleKind::Crashes => false,
+        }
+    }
+
+    fn without_read_only_paths(&self, distro: &Distro) -> Option<Rule> {
+        if self.runs_tool() || self.paths.is_empty() {
+            return Some(self.clone());
         }
-        let distros = self
-            .distros
+        let mut rule = self.clone();
+        rule.paths = self
+            .paths
             .iter()
-            .map(|d| d.to_lowercase())
-            .collect::<Vec<_>>();
-        distros.iter().any(|d| distro_ids.iter().any(|id| id == d))
+            .zip(self.expanded_paths())
+            .filter(|(_, expanded)| !distro.is_read_only(expanded))
+            .map(|(raw, _)| raw.clone())
+            .collect();
+        (!rule.paths.is_empty()).then_some(rule)
     }
 
     pub fn expanded_paths(&self) -> Vec<PathBuf> {
@@ -114,6 +326,26 @@ impl Rule {
             })
             .collect()
     }
+
+    /// Like [`Rule::expanded_paths`], but `~` and `$HOME` resolve to `home`.
+    pub fn expanded_paths_in(&self, home: &Path) -> Vec<PathBuf> {
+        let home_str = home.to_string_lossy();
+        self.paths
+            .iter()
+            .map(|raw| {
+                shellexpand::full_with_context(
+                    raw,
+                    || Some(home_str.as_ref()),
+                    |var| match var {
+                        "HOME" => Ok(Some(home_str.to_string())),
+                        _ => std::env::var(var).map(Some),
+                    },
+                )
+                .map(|expanded| PathBuf::from(expanded.as_ref()))
+                .unwrap_or_else(|_| PathBuf::from(raw))
+            })
+            .collect()
+    }
 }
 
 pub fn default_config_path() -> Option<PathBuf> {
@@ -123,6 +355,7 @@ pub fn default_config_path() -> Option<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::distro::SystemImage;
 
     #[test]
     fn test_rule_matches_distro() {
@@ -134,16 +367,122 @@ mod tests {
             paths: vec![],
             requires_sudo: false,
             enabled_by_default: true,
-            distros: vec!["arch".to_string(), "fedora".to_string()],
+            distros: vec![
+                DistroMatcher::Id("arch".to_string()),
+                DistroMatcher::Id("fedora".to_string()),
+            ],
             exclude_globs: vec![],
             older_than_days: None,
+            keep_newest: None,
+            vacuum_size: None,
+            mutable_only: false,
+            flatpak: FlatpakCategory::default(),
+            snap: SnapCategory::default(),
+            journal: JournalScope::default(),
+            containers: ContainerCategory::default(),
+            tier: DEFAULT_TIER,
         };
 
-        assert!(rule.matches_distro(&["arch".to_string()]));
-        assert!(rule.matches_distro(&["fedora".to_string()]));
-        assert!(!rule.matches_distro(&["ubuntu".to_string()]));
+        let distro = |id: &str| Distro {
+            id: Some(id.to_string()),
+            ..Distro::default()
+        };
+        assert!(rule.matches_distro(&distro("arch")));
+        assert!(rule.matches_distro(&distro("fedora")));
+        assert!(!rule.matches_distro(&distro("ubuntu")));
 
         rule.distros = vec![];
-        assert!(rule.matches_distro(&["ubuntu".to_string()]));
+        assert!(rule.matches_distro(&distro("ubuntu")));
+    }
+
+    #[test]
+    fn test_expanded_paths_in_home() {
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "t", "label": "T", "paths": ["~/.cache", "$HOME/x", "/var/tmp"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rule.expanded_paths_in(Path::new("/home/alice")),
+            [
+                PathBuf::from("/home/alice/.cache"),
+                PathBuf::from("/home/alice/x"),
+                PathBuf::from("/var/tmp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_available_rules_ordered_by_tier() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "version": 1,
+                "rules": [
+                    { "id": "dev", "label": "Dev", "tier": 3 },
+                    { "id": "plain", "label": "Plain" },
+                    { "id": "trash", "label": "Trash", "tier": 1 },
+                    { "id": "logs", "label": "Logs", "kind": "logs" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let ids = config
+            .available_rules(&Distro::default())
+            .into_iter()
+            .map(|rule| rule.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["trash", "plain", "logs", "dev"]);
+    }
+
+    #[test]
+    fn test_available_rules_on_image_based_system() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "version": 1,
+                "rules": [
+                    { "id": "dnf", "label": "DNF", "paths": ["/var/cache/dnf"], "mutable_only": true },
+                    { "id": "usr", "label": "Usr", "paths": ["/usr/share/doc"] },
+                    { "id": "mixed", "label": "Mixed", "paths": ["/usr/tmp", "/var/tmp"] },
+                    { "id": "ostree", "label": "Ostree", "kind": "rpm-ostree", "distros": [{ "image": "ostree" }] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let distro = Distro {
+            id: Some("fedora".to_string()),
+            image: Some(SystemImage::Ostree),
+            mounts: crate::mounts::parse_mountinfo(
+                "1 0 0:1 / / rw - ext4 /dev/sda1 rw\n2 1 0:1 /usr /usr ro - ext4 /dev/sda1 rw\n",
+            ),
+            ..Distro::default()
+        };
+        let rules = config.available_rules(&distro);
+        let ids = rules
+            .iter()
+            .map(|rule| rule.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["mixed", "ostree"]);
+        assert_eq!(rules[0].paths, ["/var/tmp"]);
+    }
+
+    #[test]
+    fn test_snap_rules_need_snapd() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "version": 1,
+                "rules": [
+                    { "id": "snaps", "label": "Snaps", "kind": "snap", "paths": ["/var/lib/snapd/snaps"] },
+                    { "id": "data", "label": "Data", "kind": "snap", "snap": "revision-data", "paths": ["~/snap"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(config.available_rules(&Distro::default()).is_empty());
+        let distro = Distro {
+            snapd: true,
+            ..Distro::default()
+        };
+        let rules = config.available_rules(&distro);
+        assert_eq!(rules.len(), 2);
+        assert!(rules[0].runs_tool() && !rules[1].runs_tool());
     }
 }
@@ -1,9 +1,38 @@
+use std::cmp::Ordering;
 use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::mounts::{self, MountEntry};
+use crate::sysroot::SysRoot;
 
 #[derive(Debug, Clone, Default)]
 pub struct Distro {
     pub id: Option<String>,
     pub id_like: Vec<String>,
+    pub version_id: Option<String>,
+    pub variant_id: Option<String>,
+    pub build_id: Option<String>,
+    /// Set on image-based systems, where the OS isn't managed file by file.
+    pub image: Option<SystemImage>,
+    /// Mount table, to tell which trees are read-only.
+    pub mounts: Vec<MountEntry>,
+    /// Whether snapd is installed, which `snap` rules need.
+    pub snapd: bool,
+    /// Where the system lives (`--root`).
+    pub root: SysRoot,
+}
+
+/// How an image-based system is updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SystemImage {
+    /// Fedora Atomic desktops (Silverblue, Kinoite, ...), Fedora CoreOS.
+    Ostree,
+    /// openSUSE MicroOS/Aeon and other `transactional-update` systems.
+    Transactional,
+    Nixos,
 }
 
 impl Distro {
@@ -17,10 +46,173 @@ impl Distro {
         }
         ids
     }
+
+    fn is(&self, id: &str) -> bool {
+        self.id
+            .as_deref()
+            .is_some_and(|own| own.eq_ignore_ascii_case(id))
+    }
+
+    /// Whether `path` (inside the root) lives on a read-only mount, like
+    /// `/usr` on ostree systems or `/nix/store`.
+    pub fn is_read_only(&self, path: &Path) -> bool {
+        let Ok(path) = self.root.join(path) else {
+            return false;
+        };
+        mounts::mount_for(&self.mounts, &path).is_some_and(|mount| mount.read_only)
+    }
+}
+
+/// An entry of a rule's `distros` list: a bare ID (`"ubuntu"`), or an object
+/// narrowing it down by version, variant, build or system image. Unknown
+/// keys are rejected, so a misspelled one doesn't widen the match.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum DistroMatcher {
+    Id(String),
+    Constrained {
+        #[serde(default)]
+        id: Option<String>,
+        /// Constraint on `VERSION_ID`, e.g. `>=22.04` or `>=39,<41`.
+        #[serde(default)]
+        version: Option<String>,
+        /// `VARIANT_ID`, e.g. `silverblue`.
+        #[serde(default)]
+        variant: Option<String>,
+        /// Constraint on `BUILD_ID`, same syntax as `version`.
+        #[serde(default)]
+        build: Option<String>,
+        /// Kind of image-based system, e.g. `ostree`.
+        #[serde(default)]
+        image: Option<SystemImage>,
+    },
+}
+
+impl DistroMatcher {
+    pub fn matches(&self, distro: &Distro) -> bool {
+        let ids = distro.identifiers();
+        match self {
+            DistroMatcher::Id(id) => ids.contains(&id.to_lowercase()),
+            DistroMatcher::Constrained {
+                id,
+                version,
+                variant,
+                build,
+                image,
+            } => {
+                if image.is_some_and(|image| distro.image != Some(image)) {
+                    return false;
+                }
+                if id
+                    .as_ref()
+                    .is_some_and(|id| !ids.contains(&id.to_lowercase()))
+                {
+                    return false;
+                }
+                // Derivatives number their releases differently (Mint 21 is
+                // based on Ubuntu 22.04), so versions only count for the
+                // distro itself.
+                let own_release = id.as_deref().is_none_or(|id| distro.is(id));
+                if let Some(version) = version {
+                    if !own_release || !satisfies(distro.version_id.as_deref(), version) {
+                        return false;
+                    }
+                }
+                if let Some(build) = build {
+                    if !own_release || !satisfies(distro.build_id.as_deref(), build) {
+                        return false;
+                    }
+                }
+                variant.as_ref().is_none_or(|variant| {
+                    distro
+                        .variant_id
+                        .as_deref()
+                        .is_some_and(|own| own.eq_ignore_ascii_case(variant))
+                })
+            }
+        }
+    }
+}
+
+/// Whether `value` meets every comma-separated clause of `constraint`
+/// (`>=`, `>`, `<=`, `<`, `=`, `!=`; no operator means `=`). An unknown
+/// value never matches.
+pub fn satisfies(value: Option<&str>, constraint: &str) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    constraint.split(',').all(|clause| {
+        let clause = clause.trim();
+        let (op, wanted) = ["!=", ">=", "<=", "=", ">", "<"]
+            .iter()
+            .find_map(|op| clause.strip_prefix(op).map(|rest| (*op, rest)))
+            .unwrap_or(("=", clause));
+        let ordering = compare_versions(value, wanted.trim());
+        match op {
+            "!=" => ordering != Ordering::Equal,
+            ">=" => ordering != Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            "<" => ordering == Ordering::Less,
+            _ => ordering == Ordering::Equal,
+        }
+    })
+}
+
+/// Compares dotted versions part by part, numerically where both parts are
+/// numbers. Missing parts count as zero, so `40` equals `40.0`.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut left = a.split(['.', '-', '_']);
+    let mut right = b.split(['.', '-', '_']);
+    loop {
+        let ordering = match (left.next(), right.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(part), None) | (None, Some(part)) if is_zero(part) => Ordering::Equal,
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (Some(l), Some(r)) => match (l.parse::<u64>(), r.parse::<u64>()) {
+                (Ok(l), Ok(r)) => l.cmp(&r),
+                _ => l.to_lowercase().cmp(&r.to_lowercase()),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn is_zero(part: &str) -> bool {
+    part.parse::<u64>() == Ok(0)
 }
 
-pub fn detect() -> Distro {
-    let content = fs::read_to_string("/etc/os-release").unwrap_or_default();
+pub fn detect(root: &SysRoot) -> Distro {
+    let content = root
+        .join(Path::new("/etc/os-release"))
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .unwrap_or_default();
+    let mut distro = parse_os_release(&content);
+    distro.image = detect_image(&distro, root.path());
+    distro.mounts = mounts::read_mountinfo().unwrap_or_default();
+    distro.snapd = root
+        .join(Path::new("/var/lib/snapd/snaps"))
+        .is_ok_and(|path| path.is_dir());
+    distro.root = root.clone();
+    distro
+}
+
+fn detect_image(distro: &Distro, root: &Path) -> Option<SystemImage> {
+    if root.join("run/ostree-booted").exists() || root.join("ostree/repo").is_dir() {
+        Some(SystemImage::Ostree)
+    } else if distro.is("nixos") || root.join("etc/NIXOS").exists() {
+        Some(SystemImage::Nixos)
+    } else if root.join("usr/sbin/transactional-update").exists() {
+        Some(SystemImage::Transactional)
+    } else {
+        None
+    }
+}
+
+fn parse_os_release(content: &str) -> Distro {
     let mut distro = Distro::default();
 
     for line in content.lines() {
@@ -31,16 +223,100 @@ pub fn detect() -> Distro {
         let mut parts = line.splitn(2, '=');
         let key = parts.next().unwrap_or("");
         let value = parts.next().unwrap_or("");
-        let value = value.trim().trim_matches('"');
+        let value = value.trim().trim_matches(['"', '\'']);
 
         match key {
             "ID" => distro.id = Some(value.to_string()),
             "ID_LIKE" => {
                 distro.id_like = value.split_whitespace().map(|s| s.to_string()).collect();
             }
+            "VERSION_ID" => distro.version_id = Some(value.to_string()),
+            "VARIANT_ID" => distro.variant_id = Some(value.to_string()),
+            "BUILD_ID" => distro.build_id = Some(value.to_string()),
             _ => {}
         }
     }
 
     distro
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SILVERBLUE: &str = r#"NAME="Fedora Linux"
+VERSION="41 (Silverblue)"
+ID=fedora
+VERSION_ID=41
+VARIANT_ID=silverblue
+"#;
+
+    const MINT: &str = r#"NAME="Linux Mint"
+ID=linuxmint
+ID_LIKE="ubuntu debian"
+VERSION_ID="21.3"
+"#;
+
+    fn matcher(json: &str) -> DistroMatcher {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let distro = parse_os_release("ID=arch\nBUILD_ID=rolling\n");
+        assert_eq!(distro.id.as_deref(), Some("arch"));
+        assert_eq!(distro.build_id.as_deref(), Some("rolling"));
+        let distro = parse_os_release(SILVERBLUE);
+        assert_eq!(distro.version_id.as_deref(), Some("41"));
+        assert_eq!(distro.variant_id.as_deref(), Some("silverblue"));
+    }
+
+    #[test]
+    fn test_detect_image() {
+        let root = tempfile::tempdir().unwrap();
+        let distro = parse_os_release(SILVERBLUE);
+        assert_eq!(detect_image(&distro, root.path()), None);
+        fs::create_dir_all(root.path().join("run")).unwrap();
+        fs::write(root.path().join("run/ostree-booted"), "").unwrap();
+        assert_eq!(
+            detect_image(&distro, root.path()),
+            Some(SystemImage::Ostree)
+        );
+
+        let nixos = parse_os_release("ID=nixos\n");
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(detect_image(&nixos, empty.path()), Some(SystemImage::Nixos));
+    }
+
+    #[test]
+    fn test_version_constraints() {
+        assert!(satisfies(Some("22.04"), ">=22.04"));
+        assert!(satisfies(Some("24.04"), ">=22.04,<25"));
+        assert!(!satisfies(Some("20.04"), ">=22.04"));
+        assert!(satisfies(Some("40"), "40.0"));
+        assert!(satisfies(Some("12.10"), ">12.9"));
+        assert!(!satisfies(None, ">=1"));
+    }
+
+    #[test]
+    fn test_matchers() {
+        let silverblue = parse_os_release(SILVERBLUE);
+        assert!(matcher(r#""fedora""#).matches(&silverblue));
+        assert!(matcher(r#"{"id": "fedora", "version": ">=41"}"#).matches(&silverblue));
+        assert!(!matcher(r#"{"id": "fedora", "version": "<41"}"#).matches(&silverblue));
+        assert!(matcher(r#"{"variant": "silverblue"}"#).matches(&silverblue));
+        assert!(!matcher(r#"{"image": "ostree"}"#).matches(&silverblue));
+        let atomic = Distro {
+            image: Some(SystemImage::Ostree),
+            ..silverblue.clone()
+        };
+        assert!(matcher(r#"{"id": "fedora", "image": "ostree"}"#).matches(&atomic));
+        assert!(!matcher(r#"{"id": "fedora", "variant": "kinoite"}"#).matches(&silverblue));
+
+        let mint = parse_os_release(MINT);
+        assert!(matcher(r#""ubuntu""#).matches(&mint));
+        assert!(!matcher(r#"{"id": "ubuntu", "version": ">=20.04"}"#).matches(&mint));
+
+        assert!(serde_json::from_str::<DistroMatcher>(r#"{"varaint": "silverblue"}"#).is_err());
+    }
+}
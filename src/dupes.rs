@@ -0,0 +1,506 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, Read};
+use std::os::unix::fs::{fchown, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use humansize::{format_size, BINARY};
+use rayon::prelude::*;
+
+use crate::clean::{walk_files, CleanReport};
+use crate::units::format_timestamp;
+
+/// Bytes hashed from the start of same-sized files before hashing them
+/// whole.
+const PARTIAL_BYTES: u64 = 16 * 1024;
+
+/// A copy in a set of identical files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DupeFile {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+}
+
+impl DupeFile {
+    pub fn modified_label(&self) -> String {
+        let secs = self
+            .modified
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        format_timestamp(secs)
+    }
+}
+
+/// Files with identical contents, sorted by path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DupeSet {
+    /// Size of each copy.
+    pub size: u64,
+    pub files: Vec<DupeFile>,
+}
+
+impl DupeSet {
+    /// Space taken by all copies but one.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+
+    /// Index of the copy `keep` picks.
+    pub fn keeper(&self, keep: Keep) -> usize {
+        let indexed = self.files.iter().enumerate();
+        let found = match keep {
+            Keep::Oldest => indexed.min_by_key(|(_, file)| file.modified),
+            Keep::Newest => indexed.max_by_key(|(_, file)| file.modified),
+            Keep::ShortestPath => indexed.min_by_key(|(_, file)| file.path.as_os_str().len()),
+        };
+        found.map(|(index, _)| index).unwrap_or(0)
+    }
+
+    /// Replacements for the copies marked in `removed`, each pointing at the
+    /// first copy that stays. Empty when every copy is marked.
+    pub fn replacements(&self, removed: &[bool]) -> Vec<Replacement> {
+        let Some(keep) = self
+            .files
+            .iter()
+            .zip(removed)
+            .find(|(_, removed)| !**removed)
+            .map(|(file, _)| file)
+        else {
+            return Vec::new();
+        };
+        self.files
+            .iter()
+            .zip(removed)
+            .filter(|(_, removed)| **removed)
+            .map(|(file, _)| Replacement {
+                path: file.path.clone(),
+                modified: file.modified,
+                keep: keep.path.clone(),
+                keep_modified: keep.modified,
+                bytes: self.size,
+            })
+            .collect()
+    }
+}
+
+/// Which copy of each set to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Oldest,
+    Newest,
+    ShortestPath,
+}
+
+/// What happens to the copies that aren't kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DupeAction {
+    Delete,
+    /// Replace with a hardlink to the kept copy (same filesystem only).
+    Hardlink,
+    /// Replace with a copy-on-write clone of the kept copy (Btrfs, XFS,
+    /// bcachefs, ...).
+    Reflink,
+}
+
+impl DupeAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DupeAction::Delete => "delete",
+            DupeAction::Hardlink => "hardlink",
+            DupeAction::Reflink => "reflink",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            DupeAction::Delete => DupeAction::Hardlink,
+            DupeAction::Hardlink => DupeAction::Reflink,
+            DupeAction::Reflink => DupeAction::Delete,
+        }
+    }
+}
+
+/// A duplicate to remove, and the copy it duplicates, with their
+/// modification times at scan time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub keep: PathBuf,
+    pub keep_modified: SystemTime,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct DupeScan {
+    /// Largest waste first.
+    pub sets: Vec<DupeSet>,
+    pub files_scanned: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Finds sets of identical files under `roots`: files are grouped by size,
+/// then by a hash of their first bytes, then by a hash of their whole
+/// contents. Empty files and extra hardlinks to a file are ignored.
+pub fn find_duplicates(roots: &[PathBuf], exclude_globs: &[String]) -> DupeScan {
+    let (files, mut errors) = walk_files(roots, exclude_globs);
+    let files_scanned = files.len();
+
+    let mut inodes = HashSet::new();
+    let mut by_size: HashMap<u64, Vec<Candidate>> = HashMap::new();
+    for (path, meta) in files {
+        if meta.len() == 0 || !inodes.insert((meta.dev(), meta.ino())) {
+            continue;
+        }
+        by_size.entry(meta.len()).or_default().push(Candidate {
+            path,
+            size: meta.len(),
+            modified: meta.modified().unwrap_or(UNIX_EPOCH),
+        });
+    }
+    let groups = by_size
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect::<Vec<_>>();
+
+    let groups = refine(
+        groups,
+        |candidate| hash_file(&candidate.path, Some(PARTIAL_BYTES)),
+        &mut errors,
+    );
+    // The partial hash already covered small files whole.
+    let (whole, partial): (Vec<_>, Vec<_>) = groups
+        .into_iter()
+        .partition(|group| group[0].size <= PARTIAL_BYTES);
+    let mut groups = refine(
+        partial,
+        |candidate| hash_file(&candidate.path, None),
+        &mut errors,
+    );
+    groups.extend(whole);
+
+    let mut sets = groups
+        .into_iter()
+        .map(|group| {
+            let size = group[0].size;
+            let mut files = group
+                .into_iter()
+                .map(|candidate| DupeFile {
+                    path: candidate.path,
+                    modified: candidate.modified,
+                })
+                .collect::<Vec<_>>();
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            DupeSet { size, files }
+        })
+        .collect::<Vec<_>>();
+    sets.sort_by(|a, b| {
+        b.wasted()
+            .cmp(&a.wasted())
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+    DupeScan {
+        sets,
+        files_scanned,
+        errors,
+    }
+}
+
+/// Splits each group by `key`, computed in parallel, dropping files that end
+/// up alone.
+fn refine<K, F>(
+    groups: Vec<Vec<Candidate>>,
+    key: F,
+    errors: &mut Vec<String>,
+) -> Vec<Vec<Candidate>>
+where
+    K: Eq + Hash + Send,
+    F: Fn(&Candidate) -> io::Result<K> + Sync,
+{
+    let keyed = groups
+        .into_par_iter()
+        .map(|group| {
+            group
+                .into_par_iter()
+                .map(|candidate| {
+                    let key = key(&candidate);
+                    (candidate, key)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut refined = Vec::new();
+    for group in keyed {
+        let mut by_key: HashMap<K, Vec<Candidate>> = HashMap::new();
+        for (candidate, key) in group {
+            match key {
+                Ok(key) => by_key.entry(key).or_default().push(candidate),
+                Err(err) => errors.push(format!(
+                    "Failed to read {}: {}",
+                    candidate.path.display(),
+                    err
+                )),
+            }
+        }
+        refined.extend(by_key.into_values().filter(|group| group.len() > 1));
+    }
+    refined
+}
+
+/// BLAKE3 of the file's contents, or of its first `limit` bytes.
+fn hash_file(path: &Path, limit: Option<u64>) -> io::Result<blake3::Hash> {
+    let file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    match limit {
+        Some(limit) => io::copy(&mut file.take(limit), &mut hasher)?,
+        None => io::copy(&mut &file, &mut hasher)?,
+    };
+    Ok(hasher.finalize())
+}
+
+/// Removes every copy but the one `keep` picks in each set.
+pub fn plan(sets: &[DupeSet], keep: Keep) -> Vec<Replacement> {
+    sets.iter()
+        .flat_map(|set| {
+            let keeper = set.keeper(keep);
+            let removed = (0..set.files.len())
+                .map(|index| index != keeper)
+                .collect::<Vec<_>>();
+            set.replacements(&removed)
+        })
+        .collect()
+}
+
+/// Dry-run listing of what `replacements` would do to each set.
+pub fn dry_run_details(
+    sets: &[DupeSet],
+    replacements: &[Replacement],
+    action: DupeAction,
+) -> String {
+    let mut details = String::new();
+    let _ = writeln!(details, "Dry-run details (no files will be changed):");
+    for set in sets {
+        let _ = writeln!(
+            details,
+            "Set: {} copies of {} (wastes {})",
+            set.files.len(),
+            format_size(set.size, BINARY),
+            format_size(set.wasted(), BINARY)
+        );
+        for file in &set.files {
+            let verb = if replacements.iter().any(|item| item.path == file.path) {
+                action.as_str()
+            } else {
+                "keep"
+            };
+            let _ = writeln!(
+                details,
+                "  {verb}: {} ({})",
+                file.path.display(),
+                file.modified_label()
+            );
+        }
+    }
+    details
+}
+
+/// Deletes or relinks each duplicate, after checking that it and the kept
+/// copy still have the scanned size and modification time.
+pub fn apply(replacements: &[Replacement], action: DupeAction) -> CleanReport {
+    let mut report = CleanReport::default();
+    for item in replacements {
+        match apply_one(item, action) {
+            Ok(freed) => {
+                match action {
+                    DupeAction::Delete => report.files_removed += 1,
+                    DupeAction::Hardlink | DupeAction::Reflink => report.files_linked += 1,
+                }
+                report.bytes_freed += freed;
+            }
+            Err(err) => {
+                eprintln!("{}: {err}", item.path.display());
+                report.errors += 1;
+            }
+        }
+    }
+    report
+}
+
+/// Returns the bytes freed: none when the duplicate has other hardlinks.
+fn apply_one(item: &Replacement, action: DupeAction) -> io::Result<u64> {
+    let meta = fs::symlink_metadata(&item.path)?;
+    let keep = fs::symlink_metadata(&item.keep)?;
+    let unchanged = |meta: &fs::Metadata, modified| {
+        meta.is_file() && meta.len() == item.bytes && meta.modified().ok() == Some(modified)
+    };
+    if !unchanged(&meta, item.modified) || !unchanged(&keep, item.keep_modified) {
+        return Err(io::Error::other("changed since the scan, skipped"));
+    }
+    if (meta.dev(), meta.ino()) == (keep.dev(), keep.ino()) {
+        return Ok(0);
+    }
+    let freed = if meta.nlink() > 1 { 0 } else { item.bytes };
+    match action {
+        DupeAction::Delete => fs::remove_file(&item.path)?,
+        DupeAction::Hardlink => replace_with(&item.path, |tmp| fs::hard_link(&item.keep, tmp))?,
+        // The clone is a new file: give it the duplicate's owner (it would
+        // be root's under sudo), mode and modification time.
+        DupeAction::Reflink => replace_with(&item.path, |tmp| {
+            let clone = reflink(&item.keep, tmp)?;
+            fchown(&clone, Some(meta.uid()), Some(meta.gid()))?;
+            clone.set_permissions(meta.permissions())?;
+            clone.set_modified(meta.modified()?)
+        })?,
+    }
+    Ok(freed)
+}
+
+/// Creates the replacement next to `path` with `create`, then renames it over
+/// `path`, so `path` never goes missing.
+fn replace_with(path: &Path, create: impl FnOnce(&Path) -> io::Result<()>) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("no file name"))?;
+    let tmp = path.with_file_name(format!(".{}.vole-tmp", name.to_string_lossy()));
+    let _ = fs::remove_file(&tmp);
+    let result = create(&tmp).and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Clones `src` into a new file at `dst` with the `FICLONE` ioctl.
+fn reflink(src: &Path, dst: &Path) -> io::Result<File> {
+    let src = File::open(src)?;
+    let dst = OpenOptions::new().write(true).create_new(true).open(dst)?;
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+        return Ok(dst);
+    }
+    let err = io::Error::last_os_error();
+    Err(match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP | libc::EINVAL | libc::EXDEV) => {
+            io::Error::other("the filesystem does not support reflinks here")
+        }
+        _ => err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("a/photo.jpg"), b"same bytes");
+        write(&root.join("b/photo (1).jpg"), b"same bytes");
+        write(&root.join("b/other.jpg"), b"diff bytes");
+        fs::hard_link(root.join("a/photo.jpg"), root.join("a/linked.jpg")).unwrap();
+        write(&root.join("skip/photo.jpg"), b"same bytes");
+        write(&root.join("empty1"), b"");
+        write(&root.join("empty2"), b"");
+        // Same first PARTIAL_BYTES, different tails.
+        let mut big = vec![7u8; PARTIAL_BYTES as usize + 10];
+        write(&root.join("big1.iso"), &big);
+        write(&root.join("big2.iso"), &big);
+        *big.last_mut().unwrap() = 8;
+        write(&root.join("big3.iso"), &big);
+
+        let scan = find_duplicates(&[root.to_path_buf()], &["skip".to_string()]);
+        assert!(scan.errors.is_empty());
+        assert_eq!(scan.files_scanned, 9);
+        assert_eq!(scan.sets.len(), 2);
+        let paths = |set: &DupeSet| {
+            set.files
+                .iter()
+                .map(|file| file.path.strip_prefix(root).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths(&scan.sets[0]),
+            [PathBuf::from("big1.iso"), PathBuf::from("big2.iso")]
+        );
+        assert_eq!(scan.sets[0].wasted(), PARTIAL_BYTES + 10);
+        let small = paths(&scan.sets[1]);
+        assert_eq!(small.len(), 2);
+        assert!(small.contains(&PathBuf::from("b/photo (1).jpg")));
+    }
+
+    #[test]
+    fn test_apply_replacements() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("keep.txt"), b"contents");
+        write(&root.join("copies/keep.txt"), b"contents");
+        write(&root.join("copies/deep/keep.txt"), b"contents");
+        let scan = find_duplicates(&[root.to_path_buf()], &[]);
+        let replacements = plan(&scan.sets, Keep::ShortestPath);
+        assert_eq!(replacements.len(), 2);
+        assert!(replacements
+            .iter()
+            .all(|item| item.keep == root.join("keep.txt")));
+
+        let details = dry_run_details(&scan.sets, &replacements, DupeAction::Hardlink);
+        assert!(details.contains(&format!("  keep: {}", root.join("keep.txt").display())));
+        assert!(details.contains(&format!(
+            "  hardlink: {}",
+            root.join("copies/keep.txt").display()
+        )));
+
+        let report = apply(&replacements[..1], DupeAction::Hardlink);
+        assert_eq!((report.files_linked, report.errors), (1, 0));
+        assert_eq!(report.bytes_freed, 8);
+        let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+        assert_eq!(inode(&replacements[0].path), inode(&root.join("keep.txt")));
+
+        let report = apply(&replacements[1..], DupeAction::Delete);
+        assert_eq!(report.files_removed, 1);
+        assert!(!replacements[1].path.exists());
+
+        // Reflinks depend on the filesystem; either way the copy survives,
+        // with its modification time.
+        write(&root.join("copies/again.txt"), b"contents");
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        let item = Replacement {
+            path: root.join("copies/again.txt"),
+            modified: modified(&root.join("copies/again.txt")),
+            keep: root.join("keep.txt"),
+            keep_modified: modified(&root.join("keep.txt")),
+            bytes: 8,
+        };
+        let report = apply(std::slice::from_ref(&item), DupeAction::Reflink);
+        assert_eq!(report.files_linked + report.errors, 1);
+        assert_eq!(fs::read(&item.path).unwrap(), b"contents");
+        assert_eq!(modified(&item.path), item.modified);
+
+        // A copy edited after the scan is left alone, even at the same size.
+        write(&root.join("copies/edited.txt"), b"contents");
+        let edited = Replacement {
+            path: root.join("copies/edited.txt"),
+            modified: SystemTime::UNIX_EPOCH,
+            ..item
+        };
+        let report = apply(&[edited], DupeAction::Delete);
+        assert_eq!((report.files_removed, report.errors), (0, 1));
+        assert!(root.join("copies/edited.txt").exists());
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Result of a command that ran to completion without an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Everything that was planned was applied (or previewed).
+    Success,
+    /// No rules were selected or the plan was empty.
+    NothingToDo,
+    /// The user declined the confirmation prompt.
+    Canceled,
+    /// Cleanup ran but some deletions failed.
+    PartialFailure,
+}
+
+/// Documented process exit codes. Keep README.md in sync when changing these.
+pub mod code {
+    pub const SUCCESS: u8 = 0;
+    pub const ERROR: u8 = 1;
+    // 2 is reserved for command-line usage errors reported by clap.
+    pub const NOTHING_TO_DO: u8 = 3;
+    pub const CANCELED: u8 = 4;
+    pub const PARTIAL_FAILURE: u8 = 5;
+    pub const CONFIG_ERROR: u8 = 6;
+    pub const SAFETY_REFUSAL: u8 = 7;
+}
+
+impl Outcome {
+    pub fn code(self) -> u8 {
+        match self {
+            Outcome::Success => code::SUCCESS,
+            Outcome::NothingToDo => code::NOTHING_TO_DO,
+            Outcome::Canceled => code::CANCELED,
+            Outcome::PartialFailure => code::PARTIAL_FAILURE,
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(value: Outcome) -> Self {
+        ExitCode::from(value.code())
+    }
+}
+
+/// The configuration could not be read, parsed or validated.
+#[derive(Debug)]
+pub struct ConfigError;
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid configuration")
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Vole refused to continue because doing so would be unsafe or would need
+/// input it is not allowed to ask for.
+#[derive(Debug)]
+pub struct Refusal(pub String);
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Refusal {}
+
+/// Shorthand for `Err(Refusal(..).into())`, mirroring `anyhow::bail!`.
+macro_rules! refuse {
+    ($($arg:tt)*) => {
+        return Err(anyhow::Error::new($crate::exit::Refusal(format!($($arg)*))))
+    };
+}
+pub(crate) use refuse;
+
+pub fn error_code(err: &anyhow::Error) -> u8 {
+    if err.downcast_ref::<Refusal>().is_some() {
+        return code::SAFETY_REFUSAL;
+    }
+    if err.downcast_ref::<ConfigError>().is_some() {
+        return code::CONFIG_ERROR;
+    }
+    code::ERROR
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn refused() -> anyhow::Result<()> {
+        refuse!("not without {}", "--yes");
+    }
+
+    #[test]
+    fn test_error_code() {
+        let err = refused().unwrap_err();
+        assert_eq!(err.to_string(), "not without --yes");
+        assert_eq!(error_code(&err), code::SAFETY_REFUSAL);
+
+        let err = Err::<(), _>(ConfigError)
+            .context("Failed to load config")
+            .unwrap_err();
+        assert_eq!(error_code(&err), code::CONFIG_ERROR);
+
+        let err = Err::<(), _>(anyhow::anyhow!("bad json"))
+            .context(ConfigError)
+            .context("while starting")
+            .unwrap_err();
+        assert_eq!(error_code(&err), code::CONFIG_ERROR);
+
+        let err = anyhow::anyhow!("boom");
+        assert_eq!(error_code(&err), code::ERROR);
+    }
+}
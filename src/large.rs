@@ -0,0 +1,219 @@
+use std::fmt::Write as _;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use humansize::{format_size, BINARY};
+
+use crate::clean::{walk_files, RuleScan};
+use crate::config::Rule;
+use crate::options::ScanOptions;
+use crate::units::format_timestamp;
+
+/// What a large file probably is, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    DiskImage,
+    VmImage,
+    Video,
+    Archive,
+}
+
+impl FileType {
+    pub fn label(self) -> &'static str {
+        match self {
+            FileType::DiskImage => "ISO",
+            FileType::VmImage => "VM image",
+            FileType::Video => "video",
+            FileType::Archive => "archive",
+        }
+    }
+
+    fn guess(path: &Path) -> Option<FileType> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        // Compound suffixes like `.tar.gz` end in a plain archive extension.
+        let ext = name.rsplit_once('.')?.1;
+        match ext {
+            "iso" | "img" | "dmg" => Some(FileType::DiskImage),
+            "qcow2" | "qcow" | "vmdk" | "vdi" | "vhd" | "vhdx" | "ova" | "raw" => {
+                Some(FileType::VmImage)
+            }
+            "mp4" | "mkv" | "avi" | "mov" | "webm" | "m4v" | "wmv" | "flv" | "mpg" | "mpeg" => {
+                Some(FileType::Video)
+            }
+            "zip" | "7z" | "rar" | "tar" | "tgz" | "gz" | "xz" | "zst" | "bz2" | "lz4" => {
+                Some(FileType::Archive)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LargeFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Later of the access and modification times: with `noatime` mounts
+    /// the access time never moves past the last write.
+    pub last_used: SystemTime,
+    pub file_type: Option<FileType>,
+}
+
+impl LargeFile {
+    pub fn last_used_label(&self) -> String {
+        let secs = self
+            .last_used
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        format_timestamp(secs)
+    }
+
+    pub fn type_label(&self) -> &'static str {
+        self.file_type.map(FileType::label).unwrap_or("")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LargeScan {
+    /// Largest first.
+    pub files: Vec<LargeFile>,
+    pub files_scanned: usize,
+    pub errors: Vec<String>,
+}
+
+/// Regular files under `roots` of at least `min_size` bytes, and when
+/// `not_accessed` is set, not read or written for that long.
+pub fn find_large(
+    roots: &[PathBuf],
+    exclude_globs: &[String],
+    min_size: u64,
+    not_accessed: Option<Duration>,
+) -> LargeScan {
+    let (walked, errors) = walk_files(roots, exclude_globs);
+    let cutoff = not_accessed.and_then(|age| SystemTime::now().checked_sub(age));
+    let files_scanned = walked.len();
+    let mut files = walked
+        .into_iter()
+        .filter(|(_, meta)| meta.len() >= min_size)
+        .map(|(path, meta)| {
+            let last_used =
+                UNIX_EPOCH + Duration::from_secs(meta.atime().max(meta.mtime()).max(0) as u64);
+            LargeFile {
+                file_type: FileType::guess(&path),
+                size: meta.len(),
+                last_used,
+                path,
+            }
+        })
+        .filter(|file| cutoff.is_none_or(|cutoff| file.last_used < cutoff))
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    LargeScan {
+        files,
+        files_scanned,
+        errors,
+    }
+}
+
+/// The picked files as a scan of an ad-hoc rule, so they go through the
+/// same plan, dry-run, snapshot and apply steps as `vole clean`.
+pub fn selection_scan(files: &[LargeFile]) -> RuleScan {
+    let rule = Rule::selection("large-files", "Large files");
+    let mut scan = RuleScan::new(&rule, &ScanOptions::default());
+    for file in files {
+        scan.bytes += file.size;
+        scan.entries += 1;
+        scan.files.push(file.path.clone());
+        let mut note = format_size(file.size, BINARY);
+        if let Some(file_type) = file.file_type {
+            note.push_str(", ");
+            note.push_str(file_type.label());
+        }
+        let _ = write!(note, ", last used {}", file.last_used_label());
+        scan.notes.insert(file.path.clone(), note);
+    }
+    scan
+}
+
+/// Lines listing `files` as `vole large` prints them.
+pub fn listing(files: &[LargeFile]) -> String {
+    let mut out = String::new();
+    for file in files {
+        let _ = writeln!(
+            out,
+            "{:>10}  {:<8}  {}  {}",
+            format_size(file.size, BINARY),
+            file.type_label(),
+            file.last_used_label(),
+            file.path.display()
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File, FileTimes};
+
+    fn write(path: &Path, size: u64, age_days: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = File::create(path).unwrap();
+        file.set_len(size).unwrap();
+        let time = SystemTime::now() - Duration::from_secs(age_days * 86_400);
+        file.set_times(FileTimes::new().set_accessed(time).set_modified(time))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_large() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join("isos/Fedora-41.ISO"), 3000, 400);
+        write(&root.join("vms/win11.qcow2"), 5000, 200);
+        write(&root.join("videos/talk.mkv"), 4000, 10);
+        write(&root.join("backup.tar.zst"), 2000, 365);
+        write(&root.join("blob.bin"), 2500, 365);
+        write(&root.join("small.iso"), 100, 365);
+        write(&root.join("skip/old.iso"), 9000, 365);
+
+        let scan = find_large(
+            &[root.to_path_buf()],
+            &["skip".to_string()],
+            1000,
+            Some(Duration::from_secs(180 * 86_400)),
+        );
+        assert!(scan.errors.is_empty());
+        assert_eq!(scan.files_scanned, 6);
+        let found = scan
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file.path.strip_prefix(root).unwrap().to_str().unwrap(),
+                    file.type_label(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("vms/win11.qcow2", "VM image"),
+                ("isos/Fedora-41.ISO", "ISO"),
+                ("blob.bin", ""),
+                ("backup.tar.zst", "archive"),
+            ]
+        );
+
+        let recent = find_large(&[root.to_path_buf()], &[], 4000, None);
+        assert_eq!(recent.files.len(), 3);
+        assert_eq!(recent.files[2].file_type, Some(FileType::Video));
+
+        let selected = selection_scan(&scan.files[..2]);
+        assert_eq!(selected.bytes, 8000);
+        assert_eq!(selected.entries, 2);
+        assert!(selected.notes[&root.join("vms/win11.qcow2")]
+            .starts_with("4.88 KiB, VM image, last used "));
+    }
+}
@@ -1,41 +1,77 @@
 mod clean;
 mod cli;
+mod command;
 mod config;
 mod distro;
+mod dupes;
+mod exit;
+mod large;
+mod mounts;
 mod options;
+mod schedule;
 mod snapshot;
+mod space;
+mod sysroot;
+mod trash;
 mod tui;
+mod units;
+mod users;
 
 use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
 use humansize::{format_size, BINARY};
 
 use crate::clean::scan_rules;
-use crate::cli::{CleanArgs, Cli, Commands};
-use crate::config::{Config, RuleKind};
+use crate::cli::{
+    CleanArgs, Cli, Commands, DupesArgs, LargeArgs, RestoreArgs, ScheduleArgs, ScheduleCommand,
+    ScheduleInstallArgs, SnapshotArgs, SnapshotCommand,
+};
+use crate::command::CommandRunner;
+use crate::config::{Config, RuleKind, SnapshotConfig};
 use crate::distro::Distro;
+use crate::exit::{refuse, ConfigError, Outcome};
 use crate::options::{DownloadsChoice, ScanOptions};
-use crate::snapshot::SnapshotSupport;
+use crate::snapshot::SnapshotOutcome;
+use crate::space::{FsUsage, SpaceAmount};
+use crate::sysroot::SysRoot;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(outcome) => outcome.into(),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(exit::error_code(&err))
+        }
+    }
+}
+
+fn run() -> Result<Outcome> {
     let cli = Cli::parse();
     let is_root = is_root();
     let user_home = match &cli.command {
         Some(Commands::Clean(args)) => args.user_home.as_deref(),
-        None => None,
+        Some(Commands::Schedule(_))
+        | Some(Commands::Snapshot(_))
+        | Some(Commands::Restore(_))
+        | Some(Commands::Dupes(_))
+        | Some(Commands::Large(_))
+        | None => None,
     };
-    let home = resolve_home(is_root, user_home).context("Failed to resolve home directory")?;
-    std::env::set_var("HOME", &home);
-    let config = Config::load(cli.config.as_deref())?;
-    let distro = distro::detect();
-    let snapshot_support = if is_root {
-        snapshot::detect(&home)
-    } else {
-        None
+    let sysroot = match &cli.root {
+        Some(root) => SysRoot::new(root)?,
+        None => SysRoot::host(),
     };
+    let home =
+        resolve_home(is_root, user_home, &sysroot).context("Failed to resolve home directory")?;
+    std::env::set_var("HOME", &home);
+    let config = Config::load(cli.config.as_deref()).context(ConfigError)?;
+    let distro = distro::detect(&sysroot);
 
     match &cli.command {
         Some(Commands::Clean(args)) => {
@@ -44,37 +80,53 @@ fn main() -> Result<()> {
                 return reexec_with_sudo(&sudo_args);
             }
             if args.tui {
+                if args.non_interactive {
+                    refuse!("--tui cannot be combined with --non-interactive");
+                }
                 let sudo_reexec = build_tui_sudo_reexec(&cli, &home)?;
                 let tui_state = load_tui_state(args.tui_state.as_deref())?;
                 return handle_tui(
                     tui::run(tui::RunConfig {
                         rules: config.available_rules(&distro),
-                        snapshot_support,
+                        snapshot_support: snapshot::detect(&home, &config.snapshot),
                         is_root,
                         start_with_sudo: args.sudo,
                         start_with_dry_run: args.dry_run,
                         sudo_reexec,
                         initial_state: tui_state,
                         home: home.clone(),
+                        root: sysroot.clone(),
                     })?,
+                    &config,
+                    &distro,
                     &home,
                 );
             }
-            run_clean_cli(&config, &distro, args, snapshot_support, is_root, &home)
+            run_clean_cli(&config, &distro, args, is_root, &home)
         }
+        Some(Commands::Schedule(args)) => {
+            run_schedule(&cli, args, &config, &distro, is_root, &home)
+        }
+        Some(Commands::Snapshot(args)) => run_snapshot(args, &config, &home),
+        Some(Commands::Restore(args)) => run_restore(args),
+        Some(Commands::Dupes(args)) => run_dupes(args, &home),
+        Some(Commands::Large(args)) => run_large(args, &config, is_root, &home),
         None => {
             let sudo_reexec = build_tui_sudo_reexec(&cli, &home)?;
             handle_tui(
                 tui::run(tui::RunConfig {
                     rules: config.available_rules(&distro),
-                    snapshot_support,
+                    snapshot_support: snapshot::detect(&home, &config.snapshot),
                     is_root,
                     start_with_sudo: false,
                     start_with_dry_run: false,
                     sudo_reexec,
                     initial_state: None,
                     home: home.clone(),
+                    root: sysroot.clone(),
                 })?,
+                &config,
+                &distro,
                 &home,
             )
         }
@@ -85,15 +137,14 @@ fn run_clean_cli(
     config: &Config,
     distro: &Distro,
     args: &CleanArgs,
-    snapshot_support: Option<SnapshotSupport>,
     is_root: bool,
     home: &Path,
-) -> Result<()> {
+) -> Result<Outcome> {
     let available_rules = config.available_rules(distro);
 
     if args.list_rules {
         print_rules(&available_rules);
-        return Ok(());
+        return Ok(Outcome::Success);
     }
 
     let mut rules = available_rules.clone();
@@ -124,57 +175,822 @@ fn run_clean_cli(
     if !args.sudo {
         rules.retain(|rule| !rule.requires_sudo);
     } else if !is_root {
-        bail!("--sudo requires running as root (try: sudo vole clean --sudo)");
+        refuse!("--sudo requires running as root (try: sudo vole clean --sudo)");
     }
 
-    if args.snapshot && !is_root && !args.effective_dry_run() {
-        bail!("--snapshot requires root (try: sudo vole clean --sudo --snapshot)");
+    if args.multi_user() && !args.sudo {
+        refuse!("--all-users and --user require --sudo");
     }
 
     if rules.is_empty() {
         println!("No rules selected.");
-        return Ok(());
+        return Ok(Outcome::NothingToDo);
     }
 
-    let downloads_choice = resolve_downloads_choice(&rules, args)?;
-    let scan_options = ScanOptions { downloads_choice };
-    let scans = scan_rules(&rules, &scan_options);
-    print_plan(&scans);
+    let mut groups = build_scan_groups(rules, args, &distro.root)?;
+
+    let mut watched = None;
+    if let Some(threshold) = args.when_free_below {
+        let low = low_space_filesystems(&groups, threshold);
+        if low.is_empty() {
+            println!("Free space is at least {threshold} on every affected filesystem.");
+            return Ok(Outcome::NothingToDo);
+        }
+        restrict_to_filesystems(&mut groups, &low);
+        watched = Some(low);
+    }
+    if let Some(goal) = args.until_free {
+        let candidates = watched.unwrap_or_else(|| group_filesystems(&groups));
+        let low = candidates
+            .into_iter()
+            .filter(|usage| usage.below(goal))
+            .collect::<Vec<_>>();
+        if low.is_empty() {
+            println!("Already at least {goal} free on every affected filesystem.");
+            return Ok(Outcome::NothingToDo);
+        }
+        restrict_to_filesystems(&mut groups, &low);
+        watched = Some(low);
+    }
+
+    groups.retain(|group| !group.rules.is_empty());
+    if groups.is_empty() {
+        println!("No selected rules live on the affected filesystems.");
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let all_rules = groups
+        .iter()
+        .flat_map(|group| group.rules.iter().cloned())
+        .collect::<Vec<_>>();
+    let downloads_choice = resolve_downloads_choice(&all_rules, args)?;
+    for group in &mut groups {
+        group.options.downloads_choice = downloads_choice;
+    }
+
+    let mut group_scans = Vec::new();
+    for group in &groups {
+        let scans = scan_rules(&group.rules, &group.options);
+        if let Some(label) = &group.label {
+            println!("{label}:");
+        }
+        print_plan(&scans);
+        group_scans.push(scans);
+    }
+
+    // Flatten in tier order so --until-free removes the cheapest data first
+    // across every user.
+    let mut indexed = groups
+        .iter()
+        .zip(&group_scans)
+        .flat_map(|(group, scans)| scans.iter().map(move |scan| (group, scan)))
+        .collect::<Vec<_>>();
+    indexed.sort_by_key(|(_, scan)| scan.rule.tier);
+    let mut scans = indexed
+        .iter()
+        .map(|(_, scan)| (*scan).clone())
+        .collect::<Vec<_>>();
+    if groups.len() > 1 {
+        print_grand_total(&scans);
+    }
+
+    let nothing_to_do = scans
+        .iter()
+        .all(|scan| scan.files.is_empty() && scan.dirs.is_empty());
 
     if args.effective_dry_run() {
+        if let (Some(goal), Some(watched)) = (args.until_free, &watched) {
+            let rule_bytes = indexed
+                .iter()
+                .map(|(group, scan)| {
+                    let paths = group
+                        .options
+                        .rule_paths(&scan.rule)
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>();
+                    (space::devices(&paths), scan.bytes)
+                })
+                .collect::<Vec<_>>();
+            let needed = space::estimate_until_free(watched, &rule_bytes, goal);
+            println!(
+                "Estimated to reach {goal} free after {} of {} rules.",
+                needed,
+                scans.len()
+            );
+            scans.truncate(needed);
+        }
         emit_dry_run(&scans, home, args.snapshot)?;
-        return Ok(());
+        if nothing_to_do {
+            return Ok(Outcome::NothingToDo);
+        }
+        return Ok(Outcome::Success);
     }
 
-    if args.snapshot {
-        let support =
-            snapshot_support.context("Snapshot requested but no supported provider detected")?;
-        let outcome = snapshot::create_snapshot(&support)?;
-        println!("{}", outcome.display());
+    if nothing_to_do {
+        println!("Nothing to clean.");
+        return Ok(Outcome::NothingToDo);
     }
 
-    if !args.yes && !confirm(args.sudo)? {
+    if !args.yes && !confirm(args.sudo, args.non_interactive)? {
         println!("Canceled.");
-        return Ok(());
+        return Ok(Outcome::Canceled);
+    }
+
+    let snapshots = if args.snapshot {
+        let paths = indexed
+            .iter()
+            .filter(|(_, scan)| !scan.files.is_empty() || !scan.dirs.is_empty())
+            .flat_map(|(group, scan)| group.options.rule_paths(&scan.rule))
+            .flatten()
+            .collect::<Vec<_>>();
+        take_snapshots(&paths, &scans, &config.snapshot)?
+    } else {
+        Vec::new()
+    };
+
+    let report = match (args.until_free, &watched) {
+        (Some(goal), Some(watched)) => apply_until_free(&scans, watched, goal),
+        _ if groups.len() > 1 => apply_per_group(&groups, &group_scans),
+        _ => clean::apply(&scans),
+    };
+    finish_snapshots(&snapshots);
+    Ok(finish_apply(&report, home))
+}
+
+/// Rules scanned with the same options: the invoking user's home, one user's
+/// home in multi-user mode, or the system-wide rules.
+struct ScanGroup {
+    label: Option<String>,
+    options: ScanOptions,
+    rules: Vec<crate::config::Rule>,
+}
+
+fn build_scan_groups(
+    rules: Vec<crate::config::Rule>,
+    args: &CleanArgs,
+    root: &SysRoot,
+) -> Result<Vec<ScanGroup>> {
+    let base_options = ScanOptions {
+        root: root.clone(),
+        ..ScanOptions::default()
+    };
+    if !args.multi_user() {
+        return Ok(vec![ScanGroup {
+            label: None,
+            options: base_options,
+            rules,
+        }]);
+    }
+
+    let accounts = users::read_passwd(root)?;
+    let range = match &args.uid_range {
+        Some(range) => users::parse_uid_range(range)?,
+        None => users::default_uid_range(root),
+    };
+    let mut selected = if args.all_users {
+        users::real_users(&accounts, &range)
+    } else {
+        Vec::new()
+    };
+    for name in &args.users {
+        let Some(account) = users::find_user(&accounts, name) else {
+            bail!("Unknown user: {name}");
+        };
+        if !range.contains(&account.uid) {
+            refuse!(
+                "User {} (uid {}) is outside the uid range {}-{}",
+                name,
+                account.uid,
+                range.start(),
+                range.end()
+            );
+        }
+        if !selected.iter().any(|user| user.uid == account.uid) {
+            selected.push(account.clone());
+        }
+    }
+    if selected.is_empty() {
+        println!(
+            "No users found in uid range {}-{}.",
+            range.start(),
+            range.end()
+        );
+    }
+
+    let (system_rules, user_rules): (Vec<_>, Vec<_>) =
+        rules.into_iter().partition(|rule| rule.requires_sudo);
+    let mut groups = selected
+        .into_iter()
+        .map(|user| ScanGroup {
+            label: Some(format!("User {} ({})", user.name, user.home.display())),
+            options: ScanOptions {
+                home: Some(user.home.clone()),
+                owner: Some(user.uid),
+                ..base_options.clone()
+            },
+            rules: user_rules.clone(),
+        })
+        .collect::<Vec<_>>();
+    groups.push(ScanGroup {
+        label: Some("System".to_string()),
+        options: base_options,
+        rules: system_rules,
+    });
+    Ok(groups)
+}
+
+fn group_filesystems(groups: &[ScanGroup]) -> Vec<FsUsage> {
+    let paths = groups
+        .iter()
+        .flat_map(|group| {
+            group
+                .rules
+                .iter()
+                .flat_map(|rule| group.options.rule_paths(rule))
+                .flatten()
+        })
+        .collect::<Vec<_>>();
+    space::filesystems(&paths)
+}
+
+fn low_space_filesystems(groups: &[ScanGroup], threshold: SpaceAmount) -> Vec<FsUsage> {
+    let low = group_filesystems(groups)
+        .into_iter()
+        .filter(|usage| usage.below(threshold))
+        .collect::<Vec<_>>();
+    for usage in &low {
+        println!(
+            "Low on space: {} free on {} (below {})",
+            format_size(usage.available, BINARY),
+            usage.path.display(),
+            threshold
+        );
     }
+    low
+}
+
+fn restrict_to_filesystems(groups: &mut [ScanGroup], usages: &[FsUsage]) {
+    for group in groups {
+        let options = &group.options;
+        group.rules.retain(|rule| {
+            space::devices(
+                &options
+                    .rule_paths(rule)
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>(),
+            )
+            .iter()
+            .any(|device| usages.iter().any(|usage| usage.device == *device))
+        });
+    }
+}
+
+/// Applies scans one at a time, stopping as soon as every watched filesystem
+/// has at least `goal` free.
+fn apply_until_free(
+    scans: &[clean::RuleScan],
+    watched: &[FsUsage],
+    goal: SpaceAmount,
+) -> clean::CleanReport {
+    let mut report = clean::CleanReport::default();
+    for (index, scan) in scans.iter().enumerate() {
+        let reached = watched.iter().all(|usage| {
+            usage
+                .refresh()
+                .map(|current| !current.below(goal))
+                .unwrap_or(false)
+        });
+        if reached {
+            println!(
+                "Reached {goal} free; skipped {} remaining rules.",
+                scans.len() - index
+            );
+            break;
+        }
+        println!("Cleaning {}...", scan.rule.label);
+        report = report.merge(clean::apply(std::slice::from_ref(scan)));
+    }
+    report
+}
 
-    let report = clean::apply(&scans);
+/// Applies every group tier by tier, then prints what each group freed.
+fn apply_per_group(
+    groups: &[ScanGroup],
+    group_scans: &[Vec<clean::RuleScan>],
+) -> clean::CleanReport {
+    let mut report = clean::CleanReport::default();
+    for (group, group_report) in groups.iter().zip(clean::apply_groups(group_scans)) {
+        println!(
+            "{}: freed {} ({} files, {} errors)",
+            group.label.as_deref().unwrap_or("Home"),
+            format_size(group_report.bytes_freed, BINARY),
+            group_report.files_removed,
+            group_report.errors
+        );
+        report = report.merge(group_report);
+    }
+    report
+}
+
+fn finish_apply(report: &clean::CleanReport, home: &Path) -> Outcome {
     println!(
         "Removed {} files and {} directories",
         report.files_removed, report.dirs_removed
     );
+    if report.commands_run > 0 {
+        println!("Ran {} cleanup commands", report.commands_run);
+    }
+    if report.files_linked > 0 {
+        println!("Replaced {} duplicates with links", report.files_linked);
+    }
+    if report.files_trashed > 0 {
+        println!(
+            "Moved {} entries ({}) to the Trash",
+            report.files_trashed,
+            format_size(report.bytes_trashed, BINARY)
+        );
+    }
     println!("Freed {}", format_size(report.bytes_freed, BINARY));
+    clean::remove_dry_run_report(home);
     if report.errors > 0 {
         println!("Errors encountered: {}", report.errors);
+        return Outcome::PartialFailure;
     }
-    clean::remove_dry_run_report(home);
+    Outcome::Success
+}
 
-    Ok(())
+fn run_snapshot(args: &SnapshotArgs, config: &Config, home: &Path) -> Result<Outcome> {
+    let runner = CommandRunner::system();
+    let mounts = mounts::read_mountinfo()?;
+    let archive_dir = snapshot::archive::archive_dir(&config.snapshot).ok();
+    let snapshots =
+        snapshot::manage::list_snapshots(&runner, &mounts, home, archive_dir.as_deref());
+
+    match &args.command {
+        SnapshotCommand::List => {
+            if snapshots.is_empty() {
+                println!("No Vole snapshots found.");
+                return Ok(Outcome::NothingToDo);
+            }
+            print_snapshots(&snapshots);
+            Ok(Outcome::Success)
+        }
+        SnapshotCommand::Prune(prune) => {
+            if prune.keep.is_none() && prune.older_than.is_none() {
+                bail!("Pass --keep and/or --older-than to choose what to prune");
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let selected =
+                snapshot::manage::select_prune(&snapshots, prune.keep, prune.older_than, now);
+            if selected.is_empty() {
+                println!("Nothing to prune.");
+                return Ok(Outcome::NothingToDo);
+            }
+            println!("Snapshots to delete:");
+            print_snapshots(&selected);
+            if prune.dry_run {
+                return Ok(Outcome::Success);
+            }
+            if !prune.yes && !confirm(false, false)? {
+                println!("Canceled.");
+                return Ok(Outcome::Canceled);
+            }
+            let mut failed = 0;
+            for snapshot in &selected {
+                match snapshot::manage::delete(&runner, snapshot) {
+                    Ok(()) => println!("Deleted {}", snapshot.id),
+                    Err(err) => {
+                        failed += 1;
+                        eprintln!("Failed to delete {}: {err:#}", snapshot.id);
+                    }
+                }
+            }
+            if failed > 0 {
+                return Ok(Outcome::PartialFailure);
+            }
+            Ok(Outcome::Success)
+        }
+        SnapshotCommand::Restore(restore) => {
+            let Some(snapshot) = snapshots.iter().find(|snapshot| snapshot.id == restore.id) else {
+                bail!(
+                    "No Vole snapshot with ID {} (see vole snapshot list)",
+                    restore.id
+                );
+            };
+            let cwd = std::env::current_dir().context("Failed to resolve current directory")?;
+            let paths = restore
+                .paths
+                .iter()
+                .map(|path| cwd.join(path))
+                .collect::<Vec<_>>();
+            let report = snapshot::manage::restore(&runner, &mounts, snapshot, &paths)?;
+            println!(
+                "Restored {} entries from {} ({} already present, left unchanged).",
+                report.restored, snapshot.id, report.skipped
+            );
+            if report.restored == 0 {
+                return Ok(Outcome::NothingToDo);
+            }
+            Ok(Outcome::Success)
+        }
+    }
+}
+
+fn run_restore(args: &RestoreArgs) -> Result<Outcome> {
+    let cwd = std::env::current_dir().context("Failed to resolve current directory")?;
+    let paths = args
+        .paths
+        .iter()
+        .map(|path| cwd.join(path))
+        .collect::<Vec<_>>();
+    let report = snapshot::archive::restore(&args.archive, &paths)?;
+    println!(
+        "Restored {} entries from {} ({} already present, left unchanged).",
+        report.restored,
+        args.archive.display(),
+        report.skipped
+    );
+    if report.restored == 0 {
+        return Ok(Outcome::NothingToDo);
+    }
+    Ok(Outcome::Success)
+}
+
+/// Directories `vole dupes` and `vole large` search: `paths` relative to the
+/// current directory, or the home directory.
+fn search_roots(paths: &[PathBuf], home: &Path) -> Result<Vec<PathBuf>> {
+    let cwd = std::env::current_dir().context("Failed to resolve current directory")?;
+    let roots = if paths.is_empty() {
+        vec![home.to_path_buf()]
+    } else {
+        paths.iter().map(|path| cwd.join(path)).collect()
+    };
+    for root in &roots {
+        if !root.exists() {
+            bail!("{} does not exist", root.display());
+        }
+    }
+    Ok(roots)
+}
+
+fn run_dupes(args: &DupesArgs, home: &Path) -> Result<Outcome> {
+    if args.tui && args.non_interactive {
+        refuse!("--tui cannot be combined with --non-interactive");
+    }
+    let roots = search_roots(&args.paths, home)?;
+
+    let scan = dupes::find_duplicates(&roots, &args.excludes);
+    let wasted = scan.sets.iter().map(|set| set.wasted()).sum::<u64>();
+    println!(
+        "Scanned {} files: {} duplicate sets wasting {}",
+        scan.files_scanned,
+        scan.sets.len(),
+        format_size(wasted, BINARY)
+    );
+    for message in &scan.errors {
+        eprintln!("Warning: {message}");
+    }
+    if scan.sets.is_empty() {
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let (replacements, action) = if args.tui {
+        match tui::dupes::run(tui::dupes::RunConfig {
+            sets: scan.sets.clone(),
+            keep: args.keep.into(),
+            action: args.action.into(),
+            dry_run: args.dry_run,
+            home: home.to_path_buf(),
+        })? {
+            tui::dupes::DupesExit::Quit => return Ok(Outcome::Success),
+            tui::dupes::DupesExit::Apply {
+                replacements,
+                action,
+            } => (replacements, action),
+        }
+    } else {
+        (
+            dupes::plan(&scan.sets, args.keep.into()),
+            args.action.into(),
+        )
+    };
+    let bytes = replacements.iter().map(|item| item.bytes).sum::<u64>();
+
+    if args.dry_run && !args.tui {
+        let details = dupes::dry_run_details(&scan.sets, &replacements, action);
+        print!("{details}");
+        match clean::write_dry_run_report(home, &details) {
+            Ok(path) => println!("Dry-run report saved to {}", path.display()),
+            Err(err) => eprintln!("Failed to write dry-run report: {err}"),
+        }
+        println!(
+            "Would {} {} duplicates",
+            action.as_str(),
+            replacements.len()
+        );
+        println!("Would free {}", format_size(bytes, BINARY));
+        return Ok(Outcome::Success);
+    }
+
+    if !args.tui {
+        println!(
+            "Plan: {} {} duplicates, freeing {}",
+            action.as_str(),
+            replacements.len(),
+            format_size(bytes, BINARY)
+        );
+        if !args.yes && !confirm(false, args.non_interactive)? {
+            println!("Canceled.");
+            return Ok(Outcome::Canceled);
+        }
+    }
+    let report = dupes::apply(&replacements, action);
+    Ok(finish_apply(&report, home))
+}
+
+fn run_large(args: &LargeArgs, config: &Config, is_root: bool, home: &Path) -> Result<Outcome> {
+    if args.tui && args.non_interactive {
+        refuse!("--tui cannot be combined with --non-interactive");
+    }
+    let roots = search_roots(&args.paths, home)?;
+
+    let scan = large::find_large(&roots, &args.excludes, args.min_size, args.not_accessed);
+    let total = scan.files.iter().map(|file| file.size).sum::<u64>();
+    println!(
+        "Scanned {} files: {} large files taking {}",
+        scan.files_scanned,
+        scan.files.len(),
+        format_size(total, BINARY)
+    );
+    for message in &scan.errors {
+        eprintln!("Warning: {message}");
+    }
+    if scan.files.is_empty() {
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let (files, trash, snapshot) = if args.tui {
+        match tui::large::run(tui::large::RunConfig {
+            files: scan.files.clone(),
+            trash: args.trash,
+            snapshot_support: snapshot::detect(home, &config.snapshot)
+                .filter(|support| is_root || !support.provider.requires_root()),
+            dry_run: args.dry_run,
+            home: home.to_path_buf(),
+        })? {
+            tui::large::LargeExit::Quit => return Ok(Outcome::Success),
+            tui::large::LargeExit::Apply {
+                files,
+                trash,
+                snapshot,
+            } => (files, trash, snapshot),
+        }
+    } else {
+        print!("{}", large::listing(&scan.files));
+        if !args.delete && !args.trash {
+            println!("Pass --delete or --trash to remove them, or --tui to pick.");
+            return Ok(Outcome::Success);
+        }
+        (scan.files.clone(), args.trash, args.snapshot)
+    };
+    // Under sudo the Trash would fill with root-owned entries the user can't
+    // restore.
+    let foreign_home =
+        std::fs::metadata(home).is_ok_and(|meta| meta.uid() != unsafe { libc::geteuid() });
+    if trash && foreign_home {
+        refuse!("Moving to the Trash only works for your own home; run vole large without sudo");
+    }
+    let scans = vec![large::selection_scan(&files)];
+
+    if args.dry_run && !args.tui {
+        emit_dry_run(&scans, home, snapshot)?;
+        if trash {
+            println!("Files would be moved to the Trash.");
+        }
+        return Ok(Outcome::Success);
+    }
+
+    if !args.tui {
+        print_plan(&scans);
+        if !args.yes && !confirm(false, args.non_interactive)? {
+            println!("Canceled.");
+            return Ok(Outcome::Canceled);
+        }
+    }
+    let snapshots = if snapshot {
+        take_snapshots(&scans[0].files, &scans, &config.snapshot)?
+    } else {
+        Vec::new()
+    };
+    let report = if trash {
+        trash::apply(&scans, home)
+    } else {
+        clean::apply(&scans)
+    };
+    finish_snapshots(&snapshots);
+    Ok(finish_apply(&report, home))
+}
+
+fn print_snapshots(snapshots: &[snapshot::manage::VoleSnapshot]) {
+    let width = snapshots
+        .iter()
+        .map(|snapshot| snapshot.id.len())
+        .max()
+        .unwrap_or(0);
+    for snapshot in snapshots {
+        let created = snapshot
+            .created
+            .map(units::format_timestamp)
+            .unwrap_or_else(|| "unknown".to_string());
+        println!(
+            "{:<width$}  {:<9}  {}  {}",
+            snapshot.id, snapshot.provider, created, snapshot.source
+        );
+    }
+}
+
+fn run_schedule(
+    cli: &Cli,
+    args: &ScheduleArgs,
+    config: &Config,
+    distro: &Distro,
+    is_root: bool,
+    home: &Path,
+) -> Result<Outcome> {
+    let scope = if is_root {
+        schedule::Scope::System
+    } else {
+        schedule::Scope::User
+    };
+    // With an explicit --unit-dir we only write files and never touch systemd.
+    let (unit_dir, manage_systemd) = match &args.unit_dir {
+        Some(dir) => (dir.clone(), false),
+        None => (schedule::default_unit_dir(scope)?, true),
+    };
+
+    match &args.command {
+        ScheduleCommand::Install(install) => {
+            let spec = build_schedule_spec(cli, install, config, distro, scope, home)?;
+            for path in schedule::write_units(&unit_dir, &spec, &CommandRunner::system())? {
+                println!("Wrote {}", path.display());
+            }
+            if manage_systemd {
+                schedule::systemctl(scope, &["daemon-reload"])?;
+                schedule::systemctl(scope, &["enable", "--now", &spec.timer_name()])?;
+                println!(
+                    "Enabled {} ({} timer, {})",
+                    spec.timer_name(),
+                    scope.as_str(),
+                    spec.on_calendar
+                );
+            }
+            Ok(Outcome::Success)
+        }
+        ScheduleCommand::List => {
+            let units = schedule::list_units(&unit_dir)?;
+            if units.is_empty() {
+                println!("No schedules installed in {}.", unit_dir.display());
+                return Ok(Outcome::NothingToDo);
+            }
+            println!(
+                "Schedules ({} units in {}):",
+                scope.as_str(),
+                unit_dir.display()
+            );
+            for unit in units {
+                let enabled = if unit.enabled { "" } else { " [disabled]" };
+                println!(
+                    "- {}: {}{}",
+                    unit.name,
+                    unit.on_calendar.as_deref().unwrap_or("?"),
+                    enabled
+                );
+                if let Some(command) = &unit.command {
+                    println!("  {}", command);
+                }
+            }
+            Ok(Outcome::Success)
+        }
+        ScheduleCommand::Remove(remove) => {
+            schedule::validate_name(&remove.name)?;
+            let timer = schedule::timer_name(&remove.name);
+            if manage_systemd && unit_dir.join(&timer).exists() {
+                if let Err(err) = schedule::systemctl(scope, &["disable", "--now", &timer]) {
+                    eprintln!("{err}");
+                }
+            }
+            if !schedule::remove_units(&unit_dir, &remove.name)? {
+                println!("No schedule named '{}'.", remove.name);
+                return Ok(Outcome::NothingToDo);
+            }
+            if manage_systemd {
+                schedule::systemctl(scope, &["daemon-reload"])?;
+            }
+            println!("Removed schedule '{}'.", remove.name);
+            Ok(Outcome::Success)
+        }
+    }
+}
+
+fn build_schedule_spec(
+    cli: &Cli,
+    args: &ScheduleInstallArgs,
+    config: &Config,
+    distro: &Distro,
+    scope: schedule::Scope,
+    home: &Path,
+) -> Result<schedule::ScheduleSpec> {
+    schedule::validate_name(&args.name)?;
+    let on_calendar = schedule::on_calendar(&args.every)?;
+    let available_rules = config.available_rules(distro);
+
+    // Resolve the selection now so the unit keeps running the same rules even
+    // if the defaults change later.
+    let rules = if args.rules.is_empty() {
+        available_rules
+            .iter()
+            .filter(|rule| rule.enabled_by_default && !rule.requires_sudo)
+            .cloned()
+            .collect::<Vec<_>>()
+    } else {
+        let mut rules = Vec::new();
+        for id in &args.rules {
+            let Some(rule) = available_rules
+                .iter()
+                .find(|rule| rule.id.eq_ignore_ascii_case(id))
+            else {
+                bail!("Unknown rule id: {id}");
+            };
+            rules.push(rule.clone());
+        }
+        rules
+    };
+    if rules.is_empty() {
+        bail!("No rules selected for the schedule");
+    }
+
+    let needs_sudo = rules.iter().any(|rule| rule.requires_sudo);
+    if needs_sudo && scope == schedule::Scope::User {
+        refuse!("Rules that require sudo need a system schedule (try: sudo vole schedule install)");
+    }
+    if args.downloads_remove.is_none() && rules.iter().any(|rule| rule.kind == RuleKind::Downloads)
+    {
+        refuse!("Scheduling Downloads cleanup requires --downloads-remove");
+    }
+
+    let exe = std::env::current_exe().context("Failed to resolve current executable path")?;
+    let mut command = vec![exe.to_string_lossy().to_string()];
+    if let Some(config) = &cli.config {
+        let config = std::fs::canonicalize(config)
+            .with_context(|| format!("Failed to resolve {}", config.display()))?;
+        command.push("--config".to_string());
+        command.push(config.to_string_lossy().to_string());
+    }
+    if let Some(root) = &cli.root {
+        let root = std::fs::canonicalize(root)
+            .with_context(|| format!("Failed to resolve {}", root.display()))?;
+        command.push("--root".to_string());
+        command.push(root.to_string_lossy().to_string());
+    }
+    command.push("clean".to_string());
+    command.push("--yes".to_string());
+    command.push("--non-interactive".to_string());
+    if scope == schedule::Scope::System {
+        if needs_sudo {
+            command.push("--sudo".to_string());
+        }
+        command.push("--user-home".to_string());
+        command.push(home.to_string_lossy().to_string());
+    }
+    if let Some(choice) = args.downloads_remove {
+        command.push("--downloads-remove".to_string());
+        command.push(DownloadsChoice::from(choice).to_string());
+    }
+    for rule in &rules {
+        command.push("--rule".to_string());
+        command.push(rule.id.clone());
+    }
+
+    Ok(schedule::ScheduleSpec {
+        name: args.name.clone(),
+        on_calendar,
+        command,
+    })
 }
 
 fn print_rules(rules: &[crate::config::Rule]) {
     println!("Available rules:");
+    let mut tier = None;
     for rule in rules {
+        if tier != Some(rule.tier) {
+            tier = Some(rule.tier);
+            print_tier_heading(rule.tier);
+        }
         let sudo = if rule.requires_sudo { " (sudo)" } else { "" };
         let enabled = if rule.enabled_by_default {
             " [default]"
@@ -192,7 +1008,12 @@ fn print_plan(scans: &[crate::clean::RuleScan]) {
     println!("Cleanup plan:");
     let mut total_bytes = 0;
     let mut total_entries = 0;
+    let mut tier = None;
     for scan in scans {
+        if tier != Some(scan.rule.tier) {
+            tier = Some(scan.rule.tier);
+            print_tier_heading(scan.rule.tier);
+        }
         total_bytes += scan.bytes;
         total_entries += scan.entries;
         println!(
@@ -209,6 +1030,20 @@ fn print_plan(scans: &[crate::clean::RuleScan]) {
     );
 }
 
+fn print_grand_total(scans: &[crate::clean::RuleScan]) {
+    let bytes = scans.iter().map(|scan| scan.bytes).sum::<u64>();
+    let entries = scans.iter().map(|scan| scan.entries).sum::<usize>();
+    println!(
+        "All users: {} across {} items",
+        format_size(bytes, BINARY),
+        entries
+    );
+}
+
+fn print_tier_heading(tier: u8) {
+    println!("Tier {} ({}):", tier, config::tier_label(tier));
+}
+
 fn resolve_downloads_choice(
     rules: &[crate::config::Rule],
     args: &CleanArgs,
@@ -221,7 +1056,10 @@ fn resolve_downloads_choice(
         return Ok(Some(choice.into()));
     }
     if args.yes {
-        bail!("Downloads cleanup requires --downloads-remove when using --yes");
+        refuse!("Downloads cleanup requires --downloads-remove when using --yes");
+    }
+    if args.non_interactive {
+        refuse!("Downloads cleanup requires --downloads-remove when using --non-interactive");
     }
     Ok(Some(prompt_downloads_choice()?))
 }
@@ -244,6 +1082,9 @@ fn emit_dry_run(
         "Dry-run listed {} files and {} directories",
         report.files_listed, report.dirs_listed
     );
+    if report.commands_listed > 0 {
+        println!("Would run {} cleanup commands", report.commands_listed);
+    }
     println!("Would free {}", format_size(report.bytes_listed, BINARY));
     if snapshot_requested {
         println!("Snapshot skipped in dry-run.");
@@ -254,7 +1095,10 @@ fn emit_dry_run(
     Ok(())
 }
 
-fn confirm(requires_sudo: bool) -> Result<bool> {
+fn confirm(requires_sudo: bool, non_interactive: bool) -> Result<bool> {
+    if non_interactive {
+        refuse!("Confirmation required; pass --yes to apply with --non-interactive");
+    }
     if requires_sudo {
         print!("Sudo mode: type DELETE to confirm: ");
         io::stdout().flush().ok();
@@ -288,36 +1132,93 @@ fn prompt_downloads_choice() -> Result<DownloadsChoice> {
     }
 }
 
-fn handle_tui(exit: tui::TuiExit, home: &Path) -> Result<()> {
+fn handle_tui(
+    exit: tui::TuiExit,
+    config: &Config,
+    distro: &Distro,
+    home: &Path,
+) -> Result<Outcome> {
     match exit {
-        tui::TuiExit::Quit => Ok(()),
+        tui::TuiExit::Quit => Ok(Outcome::Success),
         tui::TuiExit::ReexecSudo { args } => reexec_with_sudo(&args),
         tui::TuiExit::Apply {
             rules,
             snapshot,
             downloads_choice,
         } => {
-            let scan_options = ScanOptions { downloads_choice };
+            let scan_options = ScanOptions {
+                downloads_choice,
+                root: distro.root.clone(),
+                ..ScanOptions::default()
+            };
             let scans = rules
                 .iter()
                 .map(|rule| clean::scan_rule(rule, &scan_options))
                 .collect::<Vec<_>>();
-            if let Some(support) = snapshot {
-                let outcome = snapshot::create_snapshot(&support)?;
-                println!("{}", outcome.display());
-            }
+            let snapshots = if snapshot {
+                let paths = scans
+                    .iter()
+                    .filter(|scan| !scan.files.is_empty() || !scan.dirs.is_empty())
+                    .flat_map(|scan| scan_options.rule_paths(&scan.rule))
+                    .flatten()
+                    .collect::<Vec<_>>();
+                take_snapshots(&paths, &scans, &config.snapshot)?
+            } else {
+                Vec::new()
+            };
 
             let report = clean::apply(&scans);
-            println!(
-                "Removed {} files and {} directories",
-                report.files_removed, report.dirs_removed
-            );
-            println!("Freed {}", format_size(report.bytes_freed, BINARY));
-            if report.errors > 0 {
-                println!("Errors encountered: {}", report.errors);
-            }
-            clean::remove_dry_run_report(home);
-            Ok(())
+            finish_snapshots(&snapshots);
+            Ok(finish_apply(&report, home))
+        }
+    }
+}
+
+/// Snapshots every filesystem or subvolume `paths` live on, warning about the
+/// paths no provider can cover.
+fn take_snapshots(
+    paths: &[PathBuf],
+    scans: &[clean::RuleScan],
+    config: &SnapshotConfig,
+) -> Result<Vec<SnapshotOutcome>> {
+    let existing = paths
+        .iter()
+        .filter(|path| path.exists())
+        .cloned()
+        .collect::<Vec<_>>();
+    let plan = snapshot::plan(&existing, config)?;
+    if plan.targets.is_empty() {
+        if !is_root() {
+            refuse!("No snapshot provider works without root for the affected paths (try: sudo vole clean --sudo --snapshot)");
+        }
+        bail!("Snapshot requested but no supported provider covers the affected paths");
+    }
+    for path in &plan.uncovered {
+        eprintln!("Warning: no snapshot provider covers {}", path.display());
+    }
+    let deleted = scans
+        .iter()
+        .flat_map(|scan| scan.files.iter().chain(&scan.dirs).cloned())
+        .collect::<Vec<_>>();
+    let deletions = snapshot::Deletions {
+        paths: &deleted,
+        bytes: scans.iter().map(|scan| scan.bytes).sum(),
+    };
+    let outcomes = snapshot::create_snapshots(&plan, &deletions)?;
+    for outcome in &outcomes {
+        println!("{}", outcome.display());
+    }
+    Ok(outcomes)
+}
+
+/// Takes the post-cleanup half of snapshot pairs. Cleanup has already
+/// happened at this point, so failures are only reported.
+fn finish_snapshots(outcomes: &[SnapshotOutcome]) {
+    for outcome in outcomes {
+        match snapshot::finish_snapshot(outcome) {
+            Ok(Some(post)) => println!("{}", post.display()),
+            Ok(None) => {}
+            Err(err) => eprintln!("Warning: failed to create post-cleanup snapshot: {err:#}"),
         }
     }
 }
@@ -328,10 +1229,7 @@ fn build_sudo_args(cli: &Cli, args: &CleanArgs, home: &Path) -> Result<Vec<Strin
         .to_string_lossy()
         .to_string();
     let mut sudo_args = vec![exe];
-    if let Some(config) = &cli.config {
-        sudo_args.push("--config".to_string());
-        sudo_args.push(config.to_string_lossy().to_string());
-    }
+    push_global_args(cli, &mut sudo_args);
     sudo_args.push("clean".to_string());
     if args.tui {
         sudo_args.push("--tui".to_string());
@@ -352,6 +1250,28 @@ fn build_sudo_args(cli: &Cli, args: &CleanArgs, home: &Path) -> Result<Vec<Strin
     if args.yes {
         sudo_args.push("--yes".to_string());
     }
+    if args.non_interactive {
+        sudo_args.push("--non-interactive".to_string());
+    }
+    if args.all_users {
+        sudo_args.push("--all-users".to_string());
+    }
+    for user in &args.users {
+        sudo_args.push("--user".to_string());
+        sudo_args.push(user.clone());
+    }
+    if let Some(range) = &args.uid_range {
+        sudo_args.push("--uid-range".to_string());
+        sudo_args.push(range.clone());
+    }
+    if let Some(threshold) = args.when_free_below {
+        sudo_args.push("--when-free-below".to_string());
+        sudo_args.push(threshold.to_arg());
+    }
+    if let Some(goal) = args.until_free {
+        sudo_args.push("--until-free".to_string());
+        sudo_args.push(goal.to_arg());
+    }
     for rule in &args.rules {
         sudo_args.push("--rule".to_string());
         sudo_args.push(rule.clone());
@@ -368,10 +1288,7 @@ fn build_tui_sudo_reexec(cli: &Cli, home: &Path) -> Result<Option<Vec<String>>>
         .to_string_lossy()
         .to_string();
     let mut sudo_args = vec![exe];
-    if let Some(config) = &cli.config {
-        sudo_args.push("--config".to_string());
-        sudo_args.push(config.to_string_lossy().to_string());
-    }
+    push_global_args(cli, &mut sudo_args);
     sudo_args.push("clean".to_string());
     sudo_args.push("--tui".to_string());
     sudo_args.push("--sudo".to_string());
@@ -380,8 +1297,25 @@ fn build_tui_sudo_reexec(cli: &Cli, home: &Path) -> Result<Option<Vec<String>>>
     Ok(Some(sudo_args))
 }
 
-fn reexec_with_sudo(args: &[String]) -> Result<()> {
-    let status = std::process::Command::new("sudo")
+/// `--config` and `--root`, for re-running Vole under sudo.
+fn push_global_args(cli: &Cli, args: &mut Vec<String>) {
+    if let Some(config) = &cli.config {
+        args.push("--config".to_string());
+        args.push(config.to_string_lossy().to_string());
+    }
+    if let Some(root) = &cli.root {
+        args.push("--root".to_string());
+        args.push(root.to_string_lossy().to_string());
+    }
+}
+
+fn reexec_with_sudo(args: &[String]) -> Result<Outcome> {
+    let mut command = std::process::Command::new("sudo");
+    if args.iter().any(|arg| arg == "--non-interactive") {
+        // Fail instead of prompting for a password.
+        command.arg("-n");
+    }
+    let status = command
         .args(args)
         .status()
         .context("Failed to invoke sudo")?;
@@ -401,34 +1335,22 @@ fn is_root() -> bool {
     unsafe { libc::geteuid() == 0 }
 }
 
-fn resolve_home(is_root: bool, override_home: Option<&Path>) -> Option<PathBuf> {
+/// The home user rules apply to. With `--root` it is the home inside the
+/// root; `--user-home` is taken as given.
+fn resolve_home(is_root: bool, override_home: Option<&Path>, root: &SysRoot) -> Option<PathBuf> {
     if let Some(home) = override_home {
         return Some(home.to_path_buf());
     }
     if is_root {
-        if let Some(home) = home_from_sudo_user() {
+        if let Some(home) = home_from_sudo_user(root) {
             return Some(home);
         }
     }
-    std::env::var_os("HOME").map(PathBuf::from)
+    std::env::var_os("HOME").and_then(|home| root.join(Path::new(&home)).ok())
 }
 
-fn home_from_sudo_user() -> Option<PathBuf> {
+fn home_from_sudo_user(root: &SysRoot) -> Option<PathBuf> {
     let user = std::env::var("SUDO_USER").ok()?;
-    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
-    let prefix = format!("{user}:");
-    for line in passwd.lines() {
-        if !line.starts_with(&prefix) {
-            continue;
-        }
-        let mut parts = line.split(':');
-        let _name = parts.next();
-        let _password = parts.next();
-        let _uid = parts.next();
-        let _gid = parts.next();
-        let _gecos = parts.next();
-        let home = parts.next()?;
-        return Some(PathBuf::from(home));
-    }
-    None
+    let accounts = users::read_passwd(root).ok()?;
+    users::home_for(&accounts, &user)
 }
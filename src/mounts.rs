@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// One line of `/proc/self/mountinfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    /// `major:minor` of the mounted device.
+    pub device: String,
+    /// Path inside the filesystem that is mounted (a subvolume for Btrfs).
+    pub root: PathBuf,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    /// Mount source: a block device, or the dataset name for ZFS.
+    pub source: String,
+    /// Mounted read-only (`ro` in the per-mount options).
+    pub read_only: bool,
+}
+
+pub fn parse_mountinfo(content: &str) -> Vec<MountEntry> {
+    content.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<MountEntry> {
+    let (before, after) = line.split_once(" - ")?;
+    let fields = before.split(' ').collect::<Vec<_>>();
+    if fields.len() < 6 {
+        return None;
+    }
+    let mut after = after.split(' ');
+    let fs_type = after.next()?;
+    let source = after.next()?;
+    Some(MountEntry {
+        device: fields[2].to_string(),
+        root: PathBuf::from(unescape(fields[3])),
+        mount_point: PathBuf::from(unescape(fields[4])),
+        fs_type: fs_type.to_string(),
+        source: unescape(source),
+        read_only: fields[5].split(',').any(|option| option == "ro"),
+    })
+}
+
+/// Decodes the octal escapes (`\040` for space, ...) the kernel uses.
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = field
+            .get(index + 1..index + 4)
+            .filter(|_| bytes[index] == b'\\')
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        if let Some(value) = escaped {
+            out.push(value);
+            index += 4;
+        } else {
+            out.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub fn read_mountinfo() -> Result<Vec<MountEntry>> {
+    let content = fs::read_to_string(MOUNTINFO_PATH)
+        .with_context(|| format!("Failed to read {MOUNTINFO_PATH}"))?;
+    Ok(parse_mountinfo(&content))
+}
+
+/// The mount `path` lives on: the entry with the longest matching mount
+/// point, later entries winning for stacked mounts.
+pub fn mount_for<'a>(entries: &'a [MountEntry], path: &Path) -> Option<&'a MountEntry> {
+    let path = resolve(path);
+    entries
+        .iter()
+        .filter(|entry| path.starts_with(&entry.mount_point))
+        .fold(None, |best: Option<&MountEntry>, entry| match best {
+            Some(best)
+                if best.mount_point.components().count()
+                    > entry.mount_point.components().count() =>
+            {
+                Some(best)
+            }
+            _ => Some(entry),
+        })
+}
+
+/// Canonicalizes the longest existing prefix of `path`, keeping the rest.
+pub fn resolve(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(canonical) = ancestor.canonicalize() {
+            let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return canonical.join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 0:21 / / rw,relatime shared:1 - zfs rpool/ROOT/ubuntu rw,xattr
+23 22 0:22 / /proc rw,nosuid shared:12 - proc proc rw
+41 22 0:35 / /home rw,relatime shared:20 - zfs rpool/USERDATA rw,xattr
+42 41 0:36 / /home/alice rw,relatime shared:21 - zfs rpool/USERDATA/alice rw,xattr
+43 22 253:1 /@cache /var/cache rw,relatime shared:22 - btrfs /dev/mapper/vg-data rw,subvol=/@cache
+44 22 8:1 / /mnt/my\\040disk rw master:3 - ext4 /dev/sda1 rw
+45 22 253:1 /usr /usr ro,relatime shared:23 - ext4 /dev/mapper/vg-root rw
+";
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let entries = parse_mountinfo(MOUNTINFO);
+        assert_eq!(entries.len(), 7);
+        assert_eq!(entries[4].root, PathBuf::from("/@cache"));
+        assert_eq!(entries[4].fs_type, "btrfs");
+        assert_eq!(entries[4].device, "253:1");
+        assert_eq!(entries[5].mount_point, PathBuf::from("/mnt/my disk"));
+        assert!(!entries[5].read_only);
+        assert!(entries[6].read_only);
+    }
+
+    #[test]
+    fn test_mount_for_picks_longest_prefix() {
+        let entries = parse_mountinfo(MOUNTINFO);
+        let alice = mount_for(&entries, Path::new("/home/alice/.cache")).unwrap();
+        assert_eq!(alice.source, "rpool/USERDATA/alice");
+        let other = mount_for(&entries, Path::new("/home/alicex")).unwrap();
+        assert_eq!(other.source, "rpool/USERDATA");
+        let root = mount_for(&entries, Path::new("/opt")).unwrap();
+        assert_eq!(root.source, "rpool/ROOT/ubuntu");
+    }
+}
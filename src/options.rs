@@ -1,5 +1,13 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
 use serde::{Deserialize, Serialize};
 
+use crate::command::CommandRunner;
+use crate::config::Rule;
+use crate::sysroot::SysRoot;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum DownloadsChoice {
@@ -22,7 +30,30 @@ impl std::fmt::Display for DownloadsChoice {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct ScanOptions {
     pub downloads_choice: Option<DownloadsChoice>,
+    /// Expand `~` against this home instead of `$HOME` (multi-user cleanup).
+    pub home: Option<PathBuf>,
+    /// Only include entries owned by this uid (multi-user cleanup).
+    pub owner: Option<u32>,
+    /// Runs the tools behind command rules (`rpm-ostree`, `flatpak`, ...).
+    pub runner: CommandRunner,
+    /// System being cleaned; rule paths are re-based onto it.
+    pub root: SysRoot,
+}
+
+impl ScanOptions {
+    /// The rule's paths inside the root; paths that resolve to a symlink or
+    /// out of the root there are refused.
+    pub fn rule_paths(&self, rule: &Rule) -> Vec<Result<PathBuf>> {
+        let paths = match &self.home {
+            Some(home) => rule.expanded_paths_in(home),
+            None => rule.expanded_paths(),
+        };
+        paths
+            .iter()
+            .map(|path| self.root.join_scan_root(path))
+            .collect()
+    }
 }
@@ -0,0 +1,380 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use directories::BaseDirs;
+
+use crate::command::CommandRunner;
+
+const UNIT_PREFIX: &str = "vole-clean-";
+const SYSTEM_UNIT_DIR: &str = "/etc/systemd/system";
+const CALENDAR_SHORTHANDS: [&str; 6] = [
+    "hourly",
+    "daily",
+    "weekly",
+    "monthly",
+    "quarterly",
+    "yearly",
+];
+const GENERATED_HEADER: &str = "# Generated by `vole schedule install`; reinstall to change.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    User,
+    System,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::System => "system",
+        }
+    }
+}
+
+/// Everything needed to render a `.service`/`.timer` pair.
+#[derive(Debug, Clone)]
+pub struct ScheduleSpec {
+    pub name: String,
+    pub on_calendar: String,
+    /// Full command line, starting with the vole executable.
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduledUnit {
+    pub name: String,
+    pub on_calendar: Option<String>,
+    pub command: Option<String>,
+    pub enabled: bool,
+}
+
+impl ScheduleSpec {
+    pub fn service_name(&self) -> String {
+        service_name(&self.name)
+    }
+
+    pub fn timer_name(&self) -> String {
+        timer_name(&self.name)
+    }
+}
+
+pub fn service_name(name: &str) -> String {
+    format!("{UNIT_PREFIX}{name}.service")
+}
+
+pub fn timer_name(name: &str) -> String {
+    format!("{UNIT_PREFIX}{name}.timer")
+}
+
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Invalid schedule name '{name}' (use letters, digits, '-' and '_')");
+    }
+    Ok(())
+}
+
+/// Maps `--every` to a systemd `OnCalendar=` expression. Well-known shorthands
+/// are passed through as-is, anything else is treated as a calendar spec.
+pub fn on_calendar(every: &str) -> Result<String> {
+    let every = every.trim();
+    if every.is_empty() || every.contains(['\n', '\r']) {
+        bail!("Invalid schedule '{every}'");
+    }
+    let lower = every.to_ascii_lowercase();
+    if CALENDAR_SHORTHANDS.contains(&lower.as_str()) {
+        Ok(lower)
+    } else {
+        Ok(every.to_string())
+    }
+}
+
+/// Checks a calendar spec with `systemd-analyze calendar`, so a typo fails
+/// here rather than leaving a timer systemd refuses to load.
+pub fn check_calendar(runner: &CommandRunner, on_calendar: &str) -> Result<()> {
+    if CALENDAR_SHORTHANDS.contains(&on_calendar) {
+        return Ok(());
+    }
+    if !runner.has("systemd-analyze") {
+        bail!(
+            "Cannot check schedule '{on_calendar}' without systemd-analyze (use one of {})",
+            CALENDAR_SHORTHANDS.join(", ")
+        );
+    }
+    runner
+        .output("systemd-analyze", ["calendar", "--", on_calendar])
+        .with_context(|| format!("Invalid schedule '{on_calendar}'"))?;
+    Ok(())
+}
+
+pub fn default_unit_dir(scope: Scope) -> Result<PathBuf> {
+    match scope {
+        Scope::System => Ok(PathBuf::from(SYSTEM_UNIT_DIR)),
+        Scope::User => BaseDirs::new()
+            .map(|dirs| dirs.config_dir().join("systemd/user"))
+            .context("Unable to determine the user config directory"),
+    }
+}
+
+pub fn render_service(spec: &ScheduleSpec) -> String {
+    let mut unit = String::new();
+    let _ = writeln!(unit, "{GENERATED_HEADER}");
+    let _ = writeln!(unit, "[Unit]");
+    let _ = writeln!(unit, "Description=Vole scheduled cleanup ({})", spec.name);
+    let _ = writeln!(unit);
+    let _ = writeln!(unit, "[Service]");
+    let _ = writeln!(unit, "Type=oneshot");
+    let _ = writeln!(
+        unit,
+        "ExecStart={}",
+        spec.command
+            .iter()
+            .map(|arg| quote_exec_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    // "Nothing to do" is a normal result for a scheduled run.
+    let _ = writeln!(
+        unit,
+        "SuccessExitStatus={}",
+        crate::exit::code::NOTHING_TO_DO
+    );
+    let _ = writeln!(unit, "Nice=10");
+    let _ = writeln!(unit, "IOSchedulingClass=idle");
+    unit
+}
+
+pub fn render_timer(spec: &ScheduleSpec) -> String {
+    let mut unit = String::new();
+    let _ = writeln!(unit, "{GENERATED_HEADER}");
+    let _ = writeln!(unit, "[Unit]");
+    let _ = writeln!(unit, "Description=Run {}", spec.service_name());
+    let _ = writeln!(unit);
+    let _ = writeln!(unit, "[Timer]");
+    let _ = writeln!(unit, "OnCalendar={}", spec.on_calendar);
+    let _ = writeln!(unit, "Persistent=true");
+    let _ = writeln!(unit, "RandomizedDelaySec=15min");
+    let _ = writeln!(unit);
+    let _ = writeln!(unit, "[Install]");
+    let _ = writeln!(unit, "WantedBy=timers.target");
+    unit
+}
+
+/// Writes the unit pair into `dir` and returns the written paths.
+pub fn write_units(
+    dir: &Path,
+    spec: &ScheduleSpec,
+    runner: &CommandRunner,
+) -> Result<Vec<PathBuf>> {
+    validate_name(&spec.name)?;
+    check_calendar(runner, &spec.on_calendar)?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let service = dir.join(spec.service_name());
+    let timer = dir.join(spec.timer_name());
+    fs::write(&service, render_service(spec))
+        .with_context(|| format!("Failed to write {}", service.display()))?;
+    fs::write(&timer, render_timer(spec))
+        .with_context(|| format!("Failed to write {}", timer.display()))?;
+    Ok(vec![service, timer])
+}
+
+pub fn list_units(dir: &Path) -> Result<Vec<ScheduledUnit>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to list {}", dir.display()));
+        }
+    };
+
+    let mut units = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to list {}", dir.display()))?;
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        let Some(name) = file_name
+            .strip_prefix(UNIT_PREFIX)
+            .and_then(|rest| rest.strip_suffix(".timer"))
+        else {
+            continue;
+        };
+        let timer = fs::read_to_string(entry.path()).unwrap_or_default();
+        let service = fs::read_to_string(dir.join(service_name(name))).unwrap_or_default();
+        units.push(ScheduledUnit {
+            name: name.to_string(),
+            on_calendar: unit_value(&timer, "OnCalendar"),
+            command: unit_value(&service, "ExecStart"),
+            enabled: dir
+                .join("timers.target.wants")
+                .join(file_name)
+                .symlink_metadata()
+                .is_ok(),
+        });
+    }
+    units.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(units)
+}
+
+/// Deletes the unit pair from `dir`. Returns false if nothing was installed.
+pub fn remove_units(dir: &Path, name: &str) -> Result<bool> {
+    validate_name(name)?;
+    let mut removed = false;
+    for unit in [service_name(name), timer_name(name)] {
+        let path = dir.join(unit);
+        match fs::remove_file(&path) {
+            Ok(()) => removed = true,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to remove {}", path.display()));
+            }
+        }
+    }
+    Ok(removed)
+}
+
+pub fn systemctl(scope: Scope, args: &[&str]) -> Result<()> {
+    let mut command = Command::new("systemctl");
+    if scope == Scope::User {
+        command.arg("--user");
+    }
+    let status = command
+        .args(args)
+        .status()
+        .context("Failed to run systemctl")?;
+    if !status.success() {
+        bail!("systemctl {} failed", args.join(" "));
+    }
+    Ok(())
+}
+
+fn unit_value(unit: &str, key: &str) -> Option<String> {
+    unit.lines().find_map(|line| {
+        let (line_key, value) = line.split_once('=')?;
+        (line_key.trim() == key).then(|| value.trim().to_string())
+    })
+}
+
+/// Quotes a single `ExecStart=` argument following systemd's rules.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty()
+        && !escaped
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'))
+    {
+        return escaped;
+    }
+    let mut quoted = String::from("\"");
+    for c in escaped.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+
+    fn spec() -> ScheduleSpec {
+        ScheduleSpec {
+            name: "nightly".to_string(),
+            on_calendar: on_calendar("Weekly").unwrap(),
+            command: vec![
+                "/opt/my tools/vole".to_string(),
+                "clean".to_string(),
+                "--yes".to_string(),
+                "--rule".to_string(),
+                "thumbnails".to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_write_list_remove_units() {
+        let dir = tempfile::tempdir().unwrap();
+        let runner = CommandRunner::with_search_path(dir.path());
+        let written = write_units(dir.path(), &spec(), &runner).unwrap();
+        assert_eq!(written.len(), 2);
+
+        let service = fs::read_to_string(dir.path().join("vole-clean-nightly.service")).unwrap();
+        assert!(
+            service.contains("ExecStart=\"/opt/my tools/vole\" clean --yes --rule thumbnails\n")
+        );
+        assert!(service.contains("SuccessExitStatus=3\n"));
+        let timer = fs::read_to_string(dir.path().join("vole-clean-nightly.timer")).unwrap();
+        assert!(timer.contains("OnCalendar=weekly\n"));
+
+        let units = list_units(dir.path()).unwrap();
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].name, "nightly");
+        assert_eq!(units[0].on_calendar.as_deref(), Some("weekly"));
+        assert!(!units[0].enabled);
+
+        assert!(remove_units(dir.path(), "nightly").unwrap());
+        assert!(!remove_units(dir.path(), "nightly").unwrap());
+        assert!(list_units(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_calendar_writes_nothing() {
+        let bin = tempfile::tempdir().unwrap();
+        write_stub(
+            bin.path(),
+            "systemd-analyze",
+            r#"case "$3" in
+*-*-*) echo "  Original form: $3" ;;
+*) echo "Failed to parse calendar specification '$3'" >&2; exit 1 ;;
+esac"#,
+        );
+        let runner = CommandRunner::with_search_path(bin.path());
+        let dir = tempfile::tempdir().unwrap();
+        let units = dir.path().join("units");
+
+        let mut spec = spec();
+        spec.on_calendar = on_calendar("Mon *-*-* 03:00").unwrap();
+        assert_eq!(write_units(&units, &spec, &runner).unwrap().len(), 2);
+        let timer = fs::read_to_string(units.join("vole-clean-nightly.timer")).unwrap();
+        assert!(timer.contains("OnCalendar=Mon *-*-* 03:00\n"));
+        fs::remove_dir_all(&units).unwrap();
+
+        spec.on_calendar = on_calendar("fortnightly").unwrap();
+        let err = write_units(&units, &spec, &runner).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid schedule 'fortnightly'"));
+        assert!(!units.exists());
+
+        let err = write_units(&units, &spec, &CommandRunner::with_search_path(""))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("without systemd-analyze"));
+        assert!(!units.exists());
+    }
+
+    #[test]
+    fn test_quote_exec_arg() {
+        assert_eq!(quote_exec_arg("plain"), "plain");
+        assert_eq!(quote_exec_arg("50%"), "50%%");
+        assert_eq!(quote_exec_arg("a \"b\""), "\"a \\\"b\\\"\"");
+        assert_eq!(quote_exec_arg(""), "\"\"");
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("weekly-dev_caches").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../escape").is_err());
+    }
+}
@@ -1,16 +1,65 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{bail, Context, Result};
-use serde_json::Value;
-use which::which;
 
-#[derive(Debug, Clone)]
+use crate::command::CommandRunner;
+use crate::config::SnapshotConfig;
+use crate::mounts::{self, MountEntry};
+use crate::units::parse_size;
+
+pub mod archive;
+mod lvm;
+pub mod manage;
+mod snapper;
+mod timeshift;
+mod zfs;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SnapshotProvider {
-    Btrfs { source: PathBuf },
+    Btrfs {
+        source: PathBuf,
+    },
     TimeshiftBtrfs,
+    TimeshiftRsync {
+        backup_device_uuid: Option<String>,
+    },
+    Snapper {
+        config: String,
+    },
+    Zfs {
+        dataset: String,
+    },
+    Lvm {
+        volume: String,
+        thin: bool,
+        size: Option<String>,
+    },
+    /// Compressed copy of the files under `roots` that cleanup deletes.
+    Archive {
+        roots: Vec<PathBuf>,
+        dir: PathBuf,
+        max_bytes: u64,
+    },
+}
+
+impl SnapshotProvider {
+    /// Whether creating this snapshot needs root. Archives only read the
+    /// files being deleted, and Btrfs lets owners snapshot their own
+    /// subvolumes.
+    pub fn requires_root(&self) -> bool {
+        match self {
+            SnapshotProvider::Archive { .. } => false,
+            SnapshotProvider::Btrfs { source } => !owned_by_current_user(source),
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -23,87 +72,398 @@ pub struct SnapshotSupport {
 pub struct SnapshotOutcome {
     pub provider: String,
     pub location: Option<PathBuf>,
+    /// Provider-specific snapshot name, e.g. `pool/home@vole-clean-...`.
+    pub name: Option<String>,
+    /// Snapshot still to be taken once cleanup has finished.
+    pub post: Option<PostSnapshot>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PostSnapshot {
+    Snapper { config: String, pre_number: u32 },
 }
 
 impl SnapshotOutcome {
     pub fn display(&self) -> String {
         if let Some(path) = &self.location {
             format!("{} snapshot at {}", self.provider, path.display())
+        } else if let Some(name) = &self.name {
+            format!("{} snapshot {}", self.provider, name)
         } else {
             format!("{} snapshot created", self.provider)
         }
     }
 }
 
-pub fn detect(home: &Path) -> Option<SnapshotSupport> {
-    detect_btrfs(home).or_else(detect_timeshift_btrfs)
+/// Snapshots needed to cover a set of paths: one per distinct filesystem or
+/// subvolume, plus the paths no provider can cover.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotPlan {
+    pub targets: Vec<SnapshotSupport>,
+    pub uncovered: Vec<PathBuf>,
+}
+
+/// The provider that would snapshot `home`, used to offer the snapshot option.
+/// Without root, only providers that work unprivileged are considered.
+pub fn detect(home: &Path, config: &SnapshotConfig) -> Option<SnapshotSupport> {
+    plan(&[home.to_path_buf()], config)
+        .ok()?
+        .targets
+        .into_iter()
+        .next()
+}
+
+pub fn plan(paths: &[PathBuf], config: &SnapshotConfig) -> Result<SnapshotPlan> {
+    let mounts = mounts::read_mountinfo().unwrap_or_default();
+    let privileged = unsafe { libc::geteuid() } == 0;
+    Detector::new(CommandRunner::system(), mounts, config, privileged).plan(paths)
 }
 
-pub fn create_snapshot(support: &SnapshotSupport) -> Result<SnapshotOutcome> {
+/// What cleanup is about to delete, for providers that copy data.
+pub struct Deletions<'a> {
+    pub paths: &'a [PathBuf],
+    pub bytes: u64,
+}
+
+/// Creates every snapshot in `plan`.
+pub fn create_snapshots(
+    plan: &SnapshotPlan,
+    deletions: &Deletions,
+) -> Result<Vec<SnapshotOutcome>> {
+    plan.targets
+        .iter()
+        .map(|support| create_snapshot(support, deletions))
+        .collect()
+}
+
+fn create_snapshot(support: &SnapshotSupport, deletions: &Deletions) -> Result<SnapshotOutcome> {
+    let runner = CommandRunner::system();
     match &support.provider {
+        SnapshotProvider::Archive {
+            roots,
+            dir,
+            max_bytes,
+        } => {
+            let paths = deletions
+                .paths
+                .iter()
+                .filter(|path| roots.iter().any(|root| path.starts_with(root)))
+                .cloned()
+                .collect::<Vec<_>>();
+            archive::create(dir, *max_bytes, &paths)
+        }
         SnapshotProvider::Btrfs { source } => create_btrfs_snapshot(source),
-        SnapshotProvider::TimeshiftBtrfs => create_timeshift_snapshot(),
+        SnapshotProvider::TimeshiftBtrfs => timeshift::create_btrfs(&runner),
+        SnapshotProvider::TimeshiftRsync { backup_device_uuid } => {
+            let mounts = mounts::read_mountinfo()?;
+            timeshift::create_rsync(
+                &runner,
+                &mounts,
+                backup_device_uuid.as_deref(),
+                deletions.bytes,
+            )
+        }
+        SnapshotProvider::Snapper { config } => snapper::create_pre(&runner, config),
+        SnapshotProvider::Zfs { dataset } => zfs::create(&runner, dataset),
+        SnapshotProvider::Lvm { volume, thin, size } => {
+            lvm::create(&runner, volume, *thin, size.as_deref())
+        }
     }
 }
 
-fn detect_btrfs(home: &Path) -> Option<SnapshotSupport> {
-    if which("btrfs").is_err() {
-        return None;
+/// Takes the follow-up snapshot for providers that bracket cleanup (snapper's
+/// pre/post pairs). Returns `None` when there is nothing to do.
+pub fn finish_snapshot(outcome: &SnapshotOutcome) -> Result<Option<SnapshotOutcome>> {
+    match &outcome.post {
+        Some(PostSnapshot::Snapper { config, pre_number }) => {
+            snapper::create_post(&CommandRunner::system(), config, *pre_number).map(Some)
+        }
+        None => Ok(None),
     }
-    if !home.exists() {
-        return None;
+}
+
+const SNAPSHOT_PREFIX: &str = "vole-clean-";
+const TIMESHIFT_COMMENT: &str = "Vole clean";
+
+/// Name for a new snapshot: `vole-clean-<unix timestamp>`.
+fn snapshot_name() -> String {
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    format!("{SNAPSHOT_PREFIX}{ts}")
+}
+
+/// Picks a provider per filesystem/subvolume, querying each tool at most once.
+struct Detector<'a> {
+    runner: CommandRunner,
+    mounts: Vec<MountEntry>,
+    config: &'a SnapshotConfig,
+    /// Running as root; otherwise only unprivileged providers are offered.
+    privileged: bool,
+    snapper_configs: Option<Vec<snapper::SnapperConfig>>,
+    lvm_volumes: Option<Vec<lvm::LogicalVolume>>,
+    timeshift: Option<Option<timeshift::TimeshiftSettings>>,
+    by_root: HashMap<PathBuf, Option<SnapshotSupport>>,
+}
+
+impl<'a> Detector<'a> {
+    fn new(
+        runner: CommandRunner,
+        mounts: Vec<MountEntry>,
+        config: &'a SnapshotConfig,
+        privileged: bool,
+    ) -> Self {
+        Self {
+            runner,
+            mounts,
+            config,
+            privileged,
+            snapper_configs: None,
+            lvm_volumes: None,
+            timeshift: None,
+            by_root: HashMap::new(),
+        }
     }
 
-    let status = Command::new("btrfs")
-        .args(["subvolume", "show"])
-        .arg(home)
-        .status()
-        .ok()?;
+    fn plan(&mut self, paths: &[PathBuf]) -> Result<SnapshotPlan> {
+        let mut plan = SnapshotPlan::default();
+        for path in paths {
+            match self.support_for(path) {
+                Some(support) => {
+                    if !plan
+                        .targets
+                        .iter()
+                        .any(|known| known.provider == support.provider)
+                    {
+                        plan.targets.push(support);
+                    }
+                }
+                None => plan.uncovered.push(path.clone()),
+            }
+        }
+        // Whatever no provider covers is backed up file by file instead.
+        if !plan.uncovered.is_empty() && self.config.archive_fallback {
+            let dir = archive::archive_dir(self.config)?;
+            let max_bytes = match &self.config.archive_max_size {
+                Some(size) => parse_size(size).context("Invalid snapshot.archive_max_size")?,
+                None => archive::DEFAULT_MAX_BYTES,
+            };
+            plan.targets.push(SnapshotSupport {
+                label: format!("Archive ({})", dir.display()),
+                provider: SnapshotProvider::Archive {
+                    roots: std::mem::take(&mut plan.uncovered),
+                    dir,
+                    max_bytes,
+                },
+            });
+        }
+        Ok(plan)
+    }
 
-    if !status.success() {
-        return None;
+    fn support_for(&mut self, path: &Path) -> Option<SnapshotSupport> {
+        let mount = mounts::mount_for(&self.mounts, path)?.clone();
+        // Btrfs snapshots don't descend into nested subvolumes, so snapshot
+        // the subvolume the path actually lives in.
+        let root = if mount.fs_type == "btrfs" {
+            subvolume_root(path, &mount.mount_point)
+        } else {
+            mount.mount_point.clone()
+        };
+        let support = match self.by_root.get(&root) {
+            Some(known) => known.clone(),
+            None => {
+                let support = self.detect_root(&root, &mount);
+                self.by_root.insert(root, support.clone());
+                support
+            }
+        };
+        // Rsync mode covers the root filesystem minus Timeshift's excludes.
+        if let Some(SnapshotProvider::TimeshiftRsync { .. }) =
+            support.as_ref().map(|support| &support.provider)
+        {
+            let settings = self.timeshift_settings()?;
+            if !settings.covers(path) {
+                return None;
+            }
+        }
+        support
     }
 
-    Some(SnapshotSupport {
-        label: "Btrfs (home)".to_string(),
-        provider: SnapshotProvider::Btrfs {
-            source: home.to_path_buf(),
-        },
-    })
-}
+    fn detect_root(&mut self, root: &Path, mount: &MountEntry) -> Option<SnapshotSupport> {
+        if !self.privileged {
+            return match mount.fs_type.as_str() {
+                "btrfs" => self.detect_owned_btrfs(root),
+                _ => None,
+            };
+        }
+        if let Some(config) = self.snapper_config(root) {
+            return Some(SnapshotSupport {
+                label: format!("Snapper ({})", config.name),
+                provider: SnapshotProvider::Snapper {
+                    config: config.name,
+                },
+            });
+        }
+        let native = match mount.fs_type.as_str() {
+            "btrfs" => self.detect_btrfs(root),
+            "zfs" if self.runner.has("zfs") => {
+                zfs::dataset_for(&self.mounts, root).map(|dataset| SnapshotSupport {
+                    label: format!("ZFS ({dataset})"),
+                    provider: SnapshotProvider::Zfs { dataset },
+                })
+            }
+            "zfs" => None,
+            _ => self.detect_lvm(root),
+        };
+        native.or_else(|| self.detect_timeshift(root, &mount.fs_type))
+    }
 
-fn detect_timeshift_btrfs() -> Option<SnapshotSupport> {
-    if which("timeshift").is_err() {
-        return None;
+    fn timeshift_settings(&mut self) -> Option<&timeshift::TimeshiftSettings> {
+        if self.timeshift.is_none() {
+            self.timeshift = Some(timeshift::read_settings(&self.runner));
+        }
+        self.timeshift.as_ref()?.as_ref()
     }
 
-    let config_paths = ["/etc/timeshift/timeshift.json", "/etc/timeshift.json"];
-    for path in config_paths {
-        if let Ok(data) = fs::read_to_string(path) {
-            if timeshift_btrfs_enabled(&data) {
-                return Some(SnapshotSupport {
+    fn detect_timeshift(&mut self, root: &Path, fs_type: &str) -> Option<SnapshotSupport> {
+        let settings = self.timeshift_settings()?;
+        match settings.mode {
+            // Btrfs mode snapshots the `@` and `@home` subvolumes.
+            timeshift::TimeshiftMode::Btrfs => {
+                let covered =
+                    fs_type == "btrfs" && (root == Path::new("/") || root == Path::new("/home"));
+                covered.then(|| SnapshotSupport {
                     label: "Timeshift (Btrfs)".to_string(),
                     provider: SnapshotProvider::TimeshiftBtrfs,
-                });
+                })
             }
+            timeshift::TimeshiftMode::Rsync => (root == Path::new("/")).then(|| SnapshotSupport {
+                label: "Timeshift (rsync)".to_string(),
+                provider: SnapshotProvider::TimeshiftRsync {
+                    backup_device_uuid: settings.backup_device_uuid.clone(),
+                },
+            }),
         }
     }
 
-    None
-}
+    fn snapper_config(&mut self, root: &Path) -> Option<snapper::SnapperConfig> {
+        if self.snapper_configs.is_none() {
+            let configs = if self.runner.has("snapper") {
+                snapper::list_configs(&self.runner).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            self.snapper_configs = Some(configs);
+        }
+        self.snapper_configs
+            .as_deref()?
+            .iter()
+            .find(|config| config.subvolume == root)
+            .cloned()
+    }
 
-fn timeshift_btrfs_enabled(data: &str) -> bool {
-    if let Ok(json) = serde_json::from_str::<Value>(data) {
-        for key in ["snapshot_type", "backup_type", "mode"] {
-            if let Some(value) = json.get(key).and_then(|val| val.as_str()) {
-                if value.to_lowercase().contains("btrfs") {
-                    return true;
-                }
+    fn detect_btrfs(&self, root: &Path) -> Option<SnapshotSupport> {
+        // Snapshots go next to the subvolume, so the top level can't be used.
+        if root.parent().is_some() && self.runner.has("btrfs") {
+            let is_subvolume = self
+                .runner
+                .output(
+                    "btrfs",
+                    [
+                        OsStr::new("subvolume"),
+                        OsStr::new("show"),
+                        root.as_os_str(),
+                    ],
+                )
+                .is_ok();
+            if is_subvolume {
+                return Some(SnapshotSupport {
+                    label: format!("Btrfs ({})", root.display()),
+                    provider: SnapshotProvider::Btrfs {
+                        source: root.to_path_buf(),
+                    },
+                });
             }
         }
+        None
     }
 
-    data.to_lowercase().contains("btrfs")
+    /// Unprivileged Btrfs snapshots: `btrfs subvolume show` needs root, so
+    /// rely on the inode number, and require that the user owns the subvolume
+    /// and can write where the snapshot goes.
+    fn detect_owned_btrfs(&self, root: &Path) -> Option<SnapshotSupport> {
+        let is_subvolume = fs::symlink_metadata(root)
+            .is_ok_and(|meta| meta.is_dir() && meta.ino() == BTRFS_SUBVOLUME_INO);
+        if !is_subvolume || !owned_by_current_user(root) || !self.runner.has("btrfs") {
+            return None;
+        }
+        let base = snapshot_base_dir(root).ok()?;
+        let writable = base
+            .ancestors()
+            .find(|ancestor| ancestor.exists())
+            .is_some_and(is_writable);
+        writable.then(|| SnapshotSupport {
+            label: format!("Btrfs ({})", root.display()),
+            provider: SnapshotProvider::Btrfs {
+                source: root.to_path_buf(),
+            },
+        })
+    }
+
+    fn detect_lvm(&mut self, root: &Path) -> Option<SnapshotSupport> {
+        if self.lvm_volumes.is_none() {
+            let volumes = if self.runner.has("lvs") && self.runner.has("lvcreate") {
+                lvm::list_volumes(&self.runner).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            self.lvm_volumes = Some(volumes);
+        }
+        let volume = lvm::volume_for(self.lvm_volumes.as_deref()?, &self.mounts, root)?;
+        let thin = volume.is_thin();
+        // Thick volumes need copy-on-write space reserved up front.
+        if !thin && self.config.lvm_size.is_none() {
+            return None;
+        }
+        let label = if thin { "LVM thin" } else { "LVM" };
+        Some(SnapshotSupport {
+            label: format!("{label} ({})", volume.full_name()),
+            provider: SnapshotProvider::Lvm {
+                volume: volume.full_name(),
+                thin,
+                size: self.config.lvm_size.clone(),
+            },
+        })
+    }
+}
+
+fn owned_by_current_user(path: &Path) -> bool {
+    let uid = unsafe { libc::geteuid() };
+    fs::metadata(path).is_ok_and(|meta| meta.uid() == uid)
+}
+
+fn is_writable(path: &Path) -> bool {
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Btrfs subvolume roots always have inode number 256.
+const BTRFS_SUBVOLUME_INO: u64 = 256;
+
+/// The nearest ancestor of `path` (up to `mount_point`) that is a subvolume root.
+fn subvolume_root(path: &Path, mount_point: &Path) -> PathBuf {
+    path.ancestors()
+        .take_while(|ancestor| ancestor.starts_with(mount_point))
+        .find(|ancestor| {
+            fs::symlink_metadata(ancestor)
+                .map(|meta| meta.is_dir() && meta.ino() == BTRFS_SUBVOLUME_INO)
+                .unwrap_or(false)
+        })
+        .unwrap_or(mount_point)
+        .to_path_buf()
 }
 
 fn create_btrfs_snapshot(source: &Path) -> Result<SnapshotOutcome> {
@@ -116,7 +476,7 @@ fn create_btrfs_snapshot(source: &Path) -> Result<SnapshotOutcome> {
         .unwrap_or_default()
         .as_secs();
     let pid = std::process::id();
-    let name = format!("vole-clean-{}-{}", ts, pid);
+    let name = format!("{}{}-{}", SNAPSHOT_PREFIX, ts, pid);
     let dest = snapshot_dir.join(name);
     if dest.starts_with(source) {
         bail!("Snapshot destination must be outside the source subvolume");
@@ -132,10 +492,14 @@ fn create_btrfs_snapshot(source: &Path) -> Result<SnapshotOutcome> {
     if !status.success() {
         bail!("btrfs snapshot command failed");
     }
+    // Remember the source so `vole snapshot restore` knows where files go back.
+    fs::write(manage::source_marker(&dest), source.as_os_str().as_bytes()).ok();
 
     Ok(SnapshotOutcome {
         provider: "Btrfs".to_string(),
         location: Some(dest),
+        name: None,
+        post: None,
     })
 }
 
@@ -150,18 +514,119 @@ fn snapshot_base_dir(source: &Path) -> Result<PathBuf> {
     Ok(snapshot_dir)
 }
 
-fn create_timeshift_snapshot() -> Result<SnapshotOutcome> {
-    let status = Command::new("timeshift")
-        .args(["--create", "--comments", "Vole clean", "--tags", "O"])
-        .status()
-        .context("Failed to run timeshift")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::mounts::parse_mountinfo;
 
-    if !status.success() {
-        bail!("timeshift snapshot command failed");
+    const MOUNTINFO: &str = "\
+22 1 253:1 / / rw,relatime shared:1 - ext4 /dev/mapper/vg0-root rw
+41 22 0:35 / /home rw,relatime shared:20 - zfs tank/home rw,xattr
+42 22 8:1 / /boot rw,relatime shared:21 - ext4 /dev/sda1 rw
+";
+
+    const LVS_JSON: &str = r#"{"report":[{"lv":[{"vg_name":"vg0","lv_name":"root","lv_attr":"-wi-ao----","pool_lv":"","lv_path":"/dev/vg0/root","lv_dm_path":"/dev/mapper/vg0-root"}]}]}"#;
+
+    #[test]
+    fn test_plan_per_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("lvs.json");
+        fs::write(&json, LVS_JSON).unwrap();
+        write_stub(dir.path(), "lvs", &format!("cat {}", json.display()));
+        write_stub(dir.path(), "lvcreate", "exit 0");
+        write_stub(dir.path(), "zfs", "exit 0");
+        let config = SnapshotConfig {
+            lvm_size: Some("2G".to_string()),
+            archive_fallback: false,
+            ..SnapshotConfig::default()
+        };
+        let mut detector = Detector::new(
+            CommandRunner::with_search_path(dir.path()),
+            parse_mountinfo(MOUNTINFO),
+            &config,
+            true,
+        );
+
+        let paths = [
+            "/home/alice/.cache/thumbnails",
+            "/home/bob/.cache/thumbnails",
+            "/var/cache/apt/archives",
+            "/boot/vmlinuz.old",
+        ]
+        .map(PathBuf::from);
+        let plan = detector.plan(&paths).unwrap();
+
+        let providers = plan
+            .targets
+            .iter()
+            .map(|support| support.provider.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            providers,
+            vec![
+                SnapshotProvider::Zfs {
+                    dataset: "tank/home".to_string()
+                },
+                SnapshotProvider::Lvm {
+                    volume: "vg0/root".to_string(),
+                    thin: false,
+                    size: Some("2G".to_string()),
+                },
+            ]
+        );
+        assert_eq!(plan.uncovered, vec![PathBuf::from("/boot/vmlinuz.old")]);
+
+        // Without `lvm_size` the thick root volume isn't offered.
+        let config = SnapshotConfig {
+            archive_fallback: false,
+            ..SnapshotConfig::default()
+        };
+        let mut detector = Detector::new(
+            CommandRunner::with_search_path(dir.path()),
+            parse_mountinfo(MOUNTINFO),
+            &config,
+            true,
+        );
+        let plan = detector.plan(&paths[2..3]).unwrap();
+        assert!(plan.targets.is_empty());
+        assert_eq!(plan.uncovered, &paths[2..3]);
     }
 
-    Ok(SnapshotOutcome {
-        provider: "Timeshift".to_string(),
-        location: None,
-    })
+    #[test]
+    fn test_plan_unprivileged_falls_back_to_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("lvs.json");
+        fs::write(&json, LVS_JSON).unwrap();
+        write_stub(dir.path(), "lvs", &format!("cat {}", json.display()));
+        write_stub(dir.path(), "lvcreate", "exit 0");
+        write_stub(dir.path(), "zfs", "exit 0");
+        let config = SnapshotConfig {
+            archive_dir: Some(dir.path().join("archives")),
+            archive_max_size: Some("1G".to_string()),
+            ..SnapshotConfig::default()
+        };
+        let mut detector = Detector::new(
+            CommandRunner::with_search_path(dir.path()),
+            parse_mountinfo(MOUNTINFO),
+            &config,
+            false,
+        );
+
+        let paths = ["/home/alice/.cache/thumbnails", "/var/cache/apt/archives"].map(PathBuf::from);
+        let plan = detector.plan(&paths).unwrap();
+
+        assert!(plan.uncovered.is_empty());
+        assert_eq!(plan.targets.len(), 1);
+        let provider = &plan.targets[0].provider;
+        assert_eq!(
+            provider,
+            &SnapshotProvider::Archive {
+                roots: paths.to_vec(),
+                dir: dir.path().join("archives"),
+                max_bytes: 1 << 30,
+            }
+        );
+        assert!(!provider.requires_root());
+    }
 }
@@ -0,0 +1,413 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use directories::ProjectDirs;
+use humansize::{format_size, BINARY};
+use ruzstd::decoding::StreamingDecoder;
+use ruzstd::encoding::{compress, CompressionLevel};
+use serde::{Deserialize, Serialize};
+
+use crate::config::SnapshotConfig;
+use crate::exit::refuse;
+use crate::mounts;
+
+use super::manage::RestoreReport;
+use super::{snapshot_name, SnapshotOutcome, SNAPSHOT_PREFIX};
+
+pub const ARCHIVE_EXTENSION: &str = ".tar.zst";
+pub const DEFAULT_MAX_BYTES: u64 = 4 << 30;
+const MANIFEST_NAME: &str = "MANIFEST.json";
+const SYSTEM_DIR: &str = "/var/lib/vole/archives";
+const MANIFEST_VERSION: u8 = 1;
+
+/// First entry of every archive: what was backed up and when.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u8,
+    pub created: u64,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub dir: bool,
+}
+
+/// Where archives go: `snapshot.archive_dir`, else `/var/lib/vole/archives`
+/// for root and the user's data directory otherwise.
+pub fn archive_dir(config: &SnapshotConfig) -> Result<PathBuf> {
+    if let Some(dir) = &config.archive_dir {
+        return Ok(dir.clone());
+    }
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(PathBuf::from(SYSTEM_DIR));
+    }
+    ProjectDirs::from("dev", "vole", "vole")
+        .map(|dirs| dirs.data_dir().join("archives"))
+        .context("Unable to determine the data directory for archives")
+}
+
+/// The finished archives in `dir` with the time they were taken, if the
+/// name carries it.
+pub fn list(dir: &Path) -> Vec<(PathBuf, Option<u64>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let stem = name
+                .strip_prefix(SNAPSHOT_PREFIX)?
+                .strip_suffix(ARCHIVE_EXTENSION)?;
+            Some((entry.path(), stem.parse().ok()))
+        })
+        .collect()
+}
+
+/// Archives `paths` (the files and directories cleanup will delete) into a
+/// new `vole-clean-<ts>.tar.zst` under `dir`, refusing if they add up to more
+/// than `max_bytes`.
+pub fn create(dir: &Path, max_bytes: u64, paths: &[PathBuf]) -> Result<SnapshotOutcome> {
+    let mut entries = paths
+        .iter()
+        .filter_map(|path| {
+            let meta = fs::symlink_metadata(path).ok()?;
+            // Recorded without symlinks in the parents, which restore refuses.
+            let parent = path.parent()?.canonicalize().ok()?;
+            Some(ManifestEntry {
+                path: parent.join(path.file_name()?),
+                size: if meta.is_file() { meta.len() } else { 0 },
+                dir: meta.is_dir(),
+            })
+        })
+        .collect::<Vec<_>>();
+    // Parents before children, so restoring recreates directories first.
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+    if total > max_bytes {
+        refuse!(
+            "Archive backup would hold {} but the cap is {} (raise snapshot.archive_max_size)",
+            format_size(total, BINARY),
+            format_size(max_bytes, BINARY)
+        );
+    }
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let archive = dir.join(format!("{}{ARCHIVE_EXTENSION}", snapshot_name()));
+    let partial = archive.with_extension("zst.partial");
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        entries,
+    };
+
+    let written = write_archive(&partial, &manifest);
+    if let Err(err) = written {
+        fs::remove_file(&partial).ok();
+        return Err(err);
+    }
+    fs::rename(&partial, &archive)
+        .with_context(|| format!("Failed to finish {}", archive.display()))?;
+
+    Ok(SnapshotOutcome {
+        provider: "Archive".to_string(),
+        location: Some(archive),
+        name: None,
+        post: None,
+    })
+}
+
+/// Streams a tar of the manifest entries through a pipe into the zstd encoder.
+fn write_archive(target: &Path, manifest: &Manifest) -> Result<()> {
+    let manifest_json = serde_json::to_vec_pretty(manifest)?;
+    let paths = manifest
+        .entries
+        .iter()
+        .map(|entry| entry.path.clone())
+        .collect::<Vec<_>>();
+    let (reader, writer) = io::pipe().context("Failed to create pipe")?;
+
+    let tar = thread::spawn(move || -> Result<()> {
+        let mut builder = tar::Builder::new(writer);
+        builder.follow_symlinks(false);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_json.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+        for path in &paths {
+            let name = path.strip_prefix("/").unwrap_or(path);
+            // Files can vanish between scan and backup; they're gone either way.
+            if let Err(err) = builder.append_path_with_name(path, name) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err)
+                        .with_context(|| format!("Failed to archive {}", path.display()));
+                }
+            }
+        }
+        builder.into_inner()?.flush()?;
+        Ok(())
+    });
+
+    let file =
+        File::create(target).with_context(|| format!("Failed to create {}", target.display()))?;
+    let mut drain = CapturingWriter::new(BufWriter::new(file));
+    compress(reader, &mut drain, CompressionLevel::Fastest);
+    let tar_result = tar.join().map_err(|_| anyhow!("Archive writer panicked"))?;
+    tar_result?;
+    drain
+        .finish()
+        .with_context(|| format!("Failed to write {}", target.display()))
+}
+
+/// The zstd encoder panics on write errors, so swallow them here and report
+/// the first one once encoding is done.
+struct CapturingWriter<W: Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> CapturingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for CapturingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.error.is_none() {
+            if let Err(err) = self.inner.write_all(buf) {
+                self.error = Some(err);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn open(
+    archive: &Path,
+) -> Result<tar::Archive<StreamingDecoder<File, ruzstd::decoding::FrameDecoder>>> {
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    let decoder = StreamingDecoder::new(file)
+        .map_err(|err| anyhow!("{} is not a zstd archive: {err}", archive.display()))?;
+    Ok(tar::Archive::new(decoder))
+}
+
+pub fn read_manifest(archive: &Path) -> Result<Manifest> {
+    let mut tar = open(archive)?;
+    let mut entries = tar.entries()?;
+    let Some(first) = entries.next() else {
+        bail!("{} is empty", archive.display());
+    };
+    let first = first?;
+    if first.path()?.as_ref() != Path::new(MANIFEST_NAME) {
+        bail!("{} is not a Vole archive (no manifest)", archive.display());
+    }
+    let manifest: Manifest = serde_json::from_reader(first).context("Failed to read manifest")?;
+    if manifest.version != MANIFEST_VERSION {
+        bail!("Unsupported archive manifest version {}", manifest.version);
+    }
+    Ok(manifest)
+}
+
+/// Puts archived files back where they were. With `only`, just the entries
+/// under those paths. Existing files are left alone, and nothing is written
+/// through a symlink, so a crafted archive can't plant a link and then a file
+/// behind it.
+pub fn restore(archive: &Path, only: &[PathBuf]) -> Result<RestoreReport> {
+    read_manifest(archive)?;
+    let only = only
+        .iter()
+        .map(|path| mounts::resolve(path))
+        .collect::<Vec<_>>();
+    let mut tar = open(archive)?;
+    tar.set_preserve_permissions(true);
+    tar.set_preserve_mtime(true);
+    // chown only works for root; everyone else restores files as themselves.
+    tar.set_preserve_ownerships(unsafe { libc::geteuid() } == 0);
+
+    let mut report = RestoreReport::default();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        if name == Path::new(MANIFEST_NAME) {
+            continue;
+        }
+        if name
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_)))
+        {
+            bail!("Refusing unsafe archive entry {}", name.display());
+        }
+        let dest = Path::new("/").join(&name);
+        if !only.is_empty() && !only.iter().any(|path| dest.starts_with(path)) {
+            continue;
+        }
+        let is_dir = entry.header().entry_type().is_dir();
+        if fs::symlink_metadata(&dest).is_ok() {
+            if !is_dir {
+                report.skipped += 1;
+            }
+            continue;
+        }
+        if let Some(link) = dest
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| ancestor.is_symlink())
+        {
+            bail!(
+                "Refusing to restore {} through the symlink {}",
+                dest.display(),
+                link.display()
+            );
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        entry
+            .unpack(&dest)
+            .with_context(|| format!("Failed to restore {}", dest.display()))?;
+        report.restored += 1;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let data = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        let cache = data.path().join("cache");
+        fs::create_dir_all(cache.join("nested")).unwrap();
+        fs::write(cache.join("a.bin"), vec![7u8; 4096]).unwrap();
+        fs::write(cache.join("nested/b.txt"), "hello").unwrap();
+        let paths = vec![
+            cache.join("a.bin"),
+            cache.join("nested/b.txt"),
+            cache.join("nested"),
+            cache.join("vanished"),
+        ];
+
+        let err = create(backups.path(), 100, &paths).unwrap_err();
+        assert_eq!(
+            crate::exit::error_code(&err),
+            crate::exit::code::SAFETY_REFUSAL
+        );
+
+        let outcome = create(backups.path(), 1 << 20, &paths).unwrap();
+        let archive = outcome.location.unwrap();
+        assert!(archive.to_string_lossy().ends_with(ARCHIVE_EXTENSION));
+        let manifest = read_manifest(&archive).unwrap();
+        assert_eq!(manifest.entries.len(), 3);
+        assert_eq!(
+            manifest.entries.iter().map(|entry| entry.size).sum::<u64>(),
+            4101
+        );
+
+        fs::remove_dir_all(&cache).unwrap();
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(cache.join("a.bin"), "regenerated").unwrap();
+
+        let report = restore(&archive, &[cache.join("nested")]).unwrap();
+        assert_eq!(report.restored, 2);
+        assert_eq!(
+            fs::read_to_string(cache.join("nested/b.txt")).unwrap(),
+            "hello"
+        );
+
+        let report = restore(&archive, &[]).unwrap();
+        assert_eq!(report.restored, 0);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(
+            fs::read_to_string(cache.join("a.bin")).unwrap(),
+            "regenerated"
+        );
+    }
+
+    #[test]
+    fn test_restore_refuses_writing_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let outside = base.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::create_dir_all(base.join("restore")).unwrap();
+        let name = |path: &Path| path.strip_prefix("/").unwrap().to_path_buf();
+        let link = base.join("restore/link");
+
+        // A symlink entry, then a file entry behind it.
+        let mut builder = tar::Builder::new(Vec::new());
+        let manifest = serde_json::to_vec(&Manifest {
+            version: MANIFEST_VERSION,
+            created: 0,
+            entries: Vec::new(),
+        })
+        .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, MANIFEST_NAME, manifest.as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        builder
+            .append_link(&mut header, name(&link), &outside)
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name(&link.join("evil")), &b"evil"[..])
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+        let archive = base.join("crafted.tar.zst");
+        let mut file = File::create(&archive).unwrap();
+        compress(tar.as_slice(), &mut file, CompressionLevel::Fastest);
+        drop(file);
+
+        let err = restore(&archive, &[]).unwrap_err();
+        assert!(format!("{err:#}").contains("through the symlink"));
+        assert!(!outside.join("evil").exists());
+    }
+}
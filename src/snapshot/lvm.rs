@@ -0,0 +1,334 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::command::CommandRunner;
+use crate::exit::refuse;
+use crate::mounts::{self, MountEntry};
+
+use super::{snapshot_name, SnapshotOutcome, SNAPSHOT_PREFIX};
+
+const LVS_FIELDS: &str = "vg_name,lv_name,lv_attr,pool_lv,origin,lv_path,lv_dm_path";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LogicalVolume {
+    pub vg_name: String,
+    pub lv_name: String,
+    pub lv_attr: String,
+    #[serde(default)]
+    pub pool_lv: String,
+    /// The volume this one is a snapshot of, if any.
+    #[serde(default)]
+    pub origin: String,
+    #[serde(default)]
+    pub lv_path: String,
+    #[serde(default)]
+    pub lv_dm_path: String,
+}
+
+impl LogicalVolume {
+    /// `vg/lv`, the form `lvcreate` expects.
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.vg_name, self.lv_name)
+    }
+
+    /// Thin volumes have a `V` volume type and live in a thin pool.
+    pub fn is_thin(&self) -> bool {
+        self.lv_attr.starts_with('V') || !self.pool_lv.is_empty()
+    }
+
+    /// The fifth attribute character is `a` for active volumes.
+    pub fn is_active(&self) -> bool {
+        self.lv_attr.chars().nth(4) == Some('a')
+    }
+
+    /// Snapshots Vole created, by name.
+    pub fn is_vole_snapshot(&self) -> bool {
+        !self.origin.is_empty() && self.lv_name.starts_with(SNAPSHOT_PREFIX)
+    }
+
+    /// Creation time of a Vole snapshot, from its name:
+    /// `vole-clean-<origin lv>-<timestamp>-<pid>`, or `vole-clean-<timestamp>`
+    /// for ones made before the origin was part of the name.
+    pub fn snapshot_created(&self) -> Option<u64> {
+        let rest = self.lv_name.strip_prefix(SNAPSHOT_PREFIX)?;
+        let parts = rest.rsplit('-').collect::<Vec<_>>();
+        let ts = if parts.len() >= 3 { parts[1] } else { parts[0] };
+        ts.parse().ok()
+    }
+
+    pub fn matches_device(&self, source: &str) -> bool {
+        [&self.lv_path, &self.lv_dm_path]
+            .into_iter()
+            .filter(|path| !path.is_empty())
+            .any(|path| path == source || same_file(path, source))
+    }
+}
+
+#[derive(Deserialize)]
+struct LvsOutput {
+    report: Vec<LvsReport>,
+}
+
+#[derive(Deserialize)]
+struct LvsReport {
+    #[serde(default)]
+    lv: Vec<LogicalVolume>,
+}
+
+pub fn parse_lvs(json: &str) -> Result<Vec<LogicalVolume>> {
+    let output: LvsOutput = serde_json::from_str(json).context("Failed to parse lvs output")?;
+    Ok(output
+        .report
+        .into_iter()
+        .flat_map(|report| report.lv)
+        .collect())
+}
+
+pub fn list_volumes(runner: &CommandRunner) -> Result<Vec<LogicalVolume>> {
+    let json = runner.stdout("lvs", ["--reportformat", "json", "-o", LVS_FIELDS])?;
+    parse_lvs(&json)
+}
+
+/// The logical volume `path` is mounted from, if any.
+pub fn volume_for(
+    volumes: &[LogicalVolume],
+    mounts: &[MountEntry],
+    path: &Path,
+) -> Option<LogicalVolume> {
+    let entry = mounts::mount_for(mounts, path)?;
+    volumes
+        .iter()
+        .find(|volume| volume.matches_device(&entry.source))
+        .cloned()
+}
+
+/// Name for a new snapshot of `volume`. Logical volume names are unique per
+/// volume group, so it includes the origin's name (several volumes of one
+/// group can be snapshotted in the same second) and the process id.
+fn snapshot_lv_name(volume: &str) -> String {
+    let lv = volume.rsplit('/').next().unwrap_or(volume);
+    let name = snapshot_name();
+    let ts = name.strip_prefix(SNAPSHOT_PREFIX).unwrap_or(&name);
+    format!("{SNAPSHOT_PREFIX}{lv}-{ts}-{}", std::process::id())
+}
+
+/// Creates a snapshot of `volume` (`vg/lv`). Thin volumes need no size; thick
+/// volumes are refused unless `size` is configured.
+pub fn create(
+    runner: &CommandRunner,
+    volume: &str,
+    thin: bool,
+    size: Option<&str>,
+) -> Result<SnapshotOutcome> {
+    let name = snapshot_lv_name(volume);
+    let mut args = vec!["--snapshot", "--name", name.as_str()];
+    if !thin {
+        let Some(size) = size else {
+            refuse!(
+                "{volume} is a classic (thick) logical volume; set \"snapshot\": {{\"lvm_size\": \"<size>\"}} in the config to allow LVM snapshots"
+            );
+        };
+        args.extend(["--size", size]);
+    }
+    args.push(volume);
+    runner
+        .output("lvcreate", &args)
+        .context("Failed to create LVM snapshot")?;
+    let vg = volume.split('/').next().unwrap_or(volume);
+    Ok(SnapshotOutcome {
+        provider: if thin { "LVM thin" } else { "LVM" }.to_string(),
+        location: None,
+        name: Some(format!("{vg}/{name}")),
+        post: None,
+    })
+}
+
+pub fn remove(runner: &CommandRunner, volume: &str) -> Result<()> {
+    runner.output("lvremove", ["--yes", volume])?;
+    Ok(())
+}
+
+/// Mounts snapshot `volume` read-only at `target`, activating it first if
+/// needed (thin snapshots are created with activation skipped). Returns
+/// whether it was activated, so [`unmount`] can deactivate it again.
+pub fn mount_read_only(
+    runner: &CommandRunner,
+    volume: &LogicalVolume,
+    fs_type: &str,
+    target: &Path,
+) -> Result<bool> {
+    let activate = !volume.is_active();
+    if activate {
+        runner.output(
+            "lvchange",
+            [
+                "--activate",
+                "y",
+                "--ignoreactivationskip",
+                &volume.full_name(),
+            ],
+        )?;
+    }
+    // XFS refuses to mount a snapshot next to its origin without nouuid.
+    let options = if fs_type == "xfs" { "ro,nouuid" } else { "ro" };
+    let device = format!("/dev/{}", volume.full_name());
+    let mounted = runner.output(
+        "mount",
+        [
+            OsStr::new("-o"),
+            OsStr::new(options),
+            OsStr::new(&device),
+            target.as_os_str(),
+        ],
+    );
+    if let Err(err) = mounted {
+        if activate {
+            runner
+                .output("lvchange", ["--activate", "n", &volume.full_name()])
+                .ok();
+        }
+        return Err(err);
+    }
+    Ok(activate)
+}
+
+pub fn unmount(
+    runner: &CommandRunner,
+    volume: &LogicalVolume,
+    target: &Path,
+    deactivate: bool,
+) -> Result<()> {
+    runner.output("umount", [target])?;
+    if deactivate {
+        runner.output("lvchange", ["--activate", "n", &volume.full_name()])?;
+    }
+    Ok(())
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::exit::{code, error_code};
+    use crate::mounts::parse_mountinfo;
+
+    const LVS_JSON: &str = r#"{
+  "report": [
+    {
+      "lv": [
+        {"vg_name":"vg0", "lv_name":"pool", "lv_attr":"twi-aotz--", "pool_lv":"", "lv_path":"", "lv_dm_path":"/dev/mapper/vg0-pool"},
+        {"vg_name":"vg0", "lv_name":"home", "lv_attr":"Vwi-aotz--", "pool_lv":"pool", "lv_path":"/dev/vg0/home", "lv_dm_path":"/dev/mapper/vg0-home"},
+        {"vg_name":"vg0", "lv_name":"root", "lv_attr":"-wi-ao----", "pool_lv":"", "lv_path":"/dev/vg0/root", "lv_dm_path":"/dev/mapper/vg0-root"}
+      ]
+    }
+  ]
+}"#;
+
+    const MOUNTINFO: &str = "\
+22 1 253:1 / / rw,relatime shared:1 - ext4 /dev/mapper/vg0-root rw
+41 22 253:3 / /home rw,relatime shared:20 - xfs /dev/mapper/vg0-home rw
+";
+
+    #[test]
+    fn test_volume_for_paths() {
+        let volumes = parse_lvs(LVS_JSON).unwrap();
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let home = volume_for(&volumes, &mounts, Path::new("/home/alice")).unwrap();
+        assert_eq!(home.full_name(), "vg0/home");
+        assert!(home.is_thin());
+        let root = volume_for(&volumes, &mounts, Path::new("/var/cache")).unwrap();
+        assert_eq!(root.full_name(), "vg0/root");
+        assert!(!root.is_thin());
+    }
+
+    #[test]
+    fn test_lvm_snapshot_with_stub() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        write_stub(
+            dir.path(),
+            "lvcreate",
+            &format!("echo \"$@\" >> {}", log.display()),
+        );
+        let runner = CommandRunner::with_search_path(dir.path());
+
+        let outcome = create(&runner, "vg0/home", true, None).unwrap();
+        let name = outcome.name.unwrap();
+        assert!(name.starts_with("vg0/vole-clean-"));
+        let snapshot = name.trim_start_matches("vg0/");
+        let calls = fs::read_to_string(&log).unwrap();
+        assert_eq!(
+            calls.trim(),
+            format!("--snapshot --name {snapshot} vg0/home")
+        );
+
+        let err = create(&runner, "vg0/root", false, None).unwrap_err();
+        assert_eq!(error_code(&err), code::SAFETY_REFUSAL);
+        create(&runner, "vg0/root", false, Some("5G")).unwrap();
+        let calls = fs::read_to_string(&log).unwrap();
+        assert!(calls
+            .lines()
+            .last()
+            .unwrap()
+            .ends_with("--size 5G vg0/root"));
+    }
+
+    #[test]
+    fn test_snapshots_of_one_group_get_distinct_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        fs::write(&log, "").unwrap();
+        // Like lvcreate, refuse a name that is already taken.
+        write_stub(
+            dir.path(),
+            "lvcreate",
+            &format!(
+                "grep -qw -- \"$3\" {log} && exit 5; echo \"$@\" >> {log}",
+                log = log.display()
+            ),
+        );
+        let runner = CommandRunner::with_search_path(dir.path());
+        let volumes = parse_lvs(LVS_JSON).unwrap();
+        let mounts = parse_mountinfo(MOUNTINFO);
+
+        let mut names = Vec::new();
+        for path in ["/home/alice/.cache", "/var/cache/dnf"] {
+            let volume = volume_for(&volumes, &mounts, Path::new(path)).unwrap();
+            let outcome =
+                create(&runner, &volume.full_name(), volume.is_thin(), Some("1G")).unwrap();
+            names.push(outcome.name.unwrap());
+        }
+        assert!(names[0].starts_with("vg0/vole-clean-home-"));
+        assert!(names[1].starts_with("vg0/vole-clean-root-"));
+
+        let snapshot = |lv_name: &str| LogicalVolume {
+            lv_name: lv_name.to_string(),
+            origin: "root".to_string(),
+            ..volumes[2].clone()
+        };
+        let created = snapshot(names[1].trim_start_matches("vg0/"));
+        assert!(created.is_vole_snapshot());
+        assert!(created
+            .snapshot_created()
+            .is_some_and(|ts| ts > 1_700_000_000));
+        assert_eq!(
+            snapshot("vole-clean-my-data-1700000000-42").snapshot_created(),
+            Some(1_700_000_000)
+        );
+        assert_eq!(
+            snapshot("vole-clean-1700000000").snapshot_created(),
+            Some(1_700_000_000)
+        );
+    }
+}
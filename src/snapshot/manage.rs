@@ -0,0 +1,696 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{lchown, symlink, MetadataExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use walkdir::WalkDir;
+
+use crate::command::CommandRunner;
+use crate::exit::refuse;
+use crate::mounts::MountEntry;
+use crate::units::timestamp_from_civil;
+
+use super::{archive, lvm, snapper, zfs, SNAPSHOT_PREFIX, TIMESHIFT_COMMENT};
+
+/// A snapshot Vole created, from any provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoleSnapshot {
+    /// What `vole snapshot restore` takes to identify the snapshot.
+    pub id: String,
+    pub provider: &'static str,
+    /// Creation time as a unix timestamp, when known.
+    pub created: Option<u64>,
+    /// What was snapshotted; `--keep` applies per source.
+    pub source: String,
+    kind: SnapshotKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SnapshotKind {
+    Btrfs {
+        path: PathBuf,
+        source: Option<PathBuf>,
+    },
+    Zfs {
+        name: String,
+    },
+    Lvm {
+        volume: String,
+        origin: String,
+    },
+    Snapper {
+        config: String,
+        subvolume: PathBuf,
+        pair: snapper::SnapperPair,
+    },
+    Timeshift {
+        name: String,
+    },
+    Archive {
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    pub restored: usize,
+    /// Entries that already exist and were left alone.
+    pub skipped: usize,
+}
+
+/// Sidecar file recording which subvolume a Btrfs snapshot was taken of.
+pub fn source_marker(snapshot: &Path) -> PathBuf {
+    let mut name = snapshot.as_os_str().to_owned();
+    name.push(".source");
+    PathBuf::from(name)
+}
+
+/// Every Vole snapshot the installed providers know about, oldest first.
+/// Providers that fail to list are skipped.
+pub fn list_snapshots(
+    runner: &CommandRunner,
+    mounts: &[MountEntry],
+    home: &Path,
+    archive_dir: Option<&Path>,
+) -> Vec<VoleSnapshot> {
+    let mut snapshots = list_btrfs(mounts, home);
+    if let Some(dir) = archive_dir {
+        snapshots.extend(list_archives(dir));
+    }
+    if runner.has("zfs") {
+        snapshots.extend(list_zfs(runner).unwrap_or_default());
+    }
+    if runner.has("lvs") {
+        snapshots.extend(list_lvm(runner).unwrap_or_default());
+    }
+    if runner.has("snapper") {
+        snapshots.extend(list_snapper(runner).unwrap_or_default());
+    }
+    if runner.has("timeshift") {
+        snapshots.extend(list_timeshift(runner).unwrap_or_default());
+    }
+    snapshots.sort_by_key(|snapshot| snapshot.created);
+    snapshots
+}
+
+fn list_btrfs(mounts: &[MountEntry], home: &Path) -> Vec<VoleSnapshot> {
+    // Snapshots live in `<parent of subvolume>/.snapshots/vole`.
+    let mut bases = Vec::new();
+    let candidates = mounts
+        .iter()
+        .filter(|entry| entry.fs_type == "btrfs")
+        .flat_map(|entry| {
+            [
+                Some(entry.mount_point.as_path()),
+                entry.mount_point.parent(),
+            ]
+        })
+        .chain([home.parent()])
+        .flatten();
+    for parent in candidates {
+        let base = parent.join(".snapshots/vole");
+        if !bases.contains(&base) {
+            bases.push(base);
+        }
+    }
+
+    let mut snapshots = Vec::new();
+    for base in bases {
+        let Ok(entries) = fs::read_dir(&base) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(rest) = name.strip_prefix(SNAPSHOT_PREFIX) else {
+                continue;
+            };
+            if !path.is_dir() {
+                continue;
+            }
+            let created = rest.split('-').next().and_then(|ts| ts.parse().ok());
+            let source = fs::read(source_marker(&path))
+                .ok()
+                .map(|bytes| PathBuf::from(std::ffi::OsStr::from_bytes(&bytes)));
+            let source_label = source
+                .as_ref()
+                .map(|source| source.display().to_string())
+                .unwrap_or_else(|| base.display().to_string());
+            snapshots.push(VoleSnapshot {
+                id: path.display().to_string(),
+                provider: "Btrfs",
+                created,
+                source: source_label,
+                kind: SnapshotKind::Btrfs { path, source },
+            });
+        }
+    }
+    snapshots
+}
+
+fn list_archives(dir: &Path) -> Vec<VoleSnapshot> {
+    archive::list(dir)
+        .into_iter()
+        .map(|(path, created)| VoleSnapshot {
+            id: path.display().to_string(),
+            provider: "Archive",
+            created,
+            source: dir.display().to_string(),
+            kind: SnapshotKind::Archive { path },
+        })
+        .collect()
+}
+
+fn list_zfs(runner: &CommandRunner) -> Result<Vec<VoleSnapshot>> {
+    Ok(zfs::list_snapshots(runner)?
+        .into_iter()
+        .map(|(name, created)| VoleSnapshot {
+            id: name.clone(),
+            provider: "ZFS",
+            created: Some(created),
+            source: name.split('@').next().unwrap_or_default().to_string(),
+            kind: SnapshotKind::Zfs { name },
+        })
+        .collect())
+}
+
+fn list_lvm(runner: &CommandRunner) -> Result<Vec<VoleSnapshot>> {
+    Ok(lvm::list_volumes(runner)?
+        .into_iter()
+        .filter(|volume| volume.is_vole_snapshot())
+        .map(|volume| {
+            let origin = format!("{}/{}", volume.vg_name, volume.origin);
+            let created = volume.snapshot_created();
+            VoleSnapshot {
+                id: volume.full_name(),
+                provider: "LVM",
+                created,
+                source: origin.clone(),
+                kind: SnapshotKind::Lvm {
+                    volume: volume.full_name(),
+                    origin,
+                },
+            }
+        })
+        .collect())
+}
+
+fn list_snapper(runner: &CommandRunner) -> Result<Vec<VoleSnapshot>> {
+    let mut snapshots = Vec::new();
+    for config in snapper::list_configs(runner)? {
+        for pair in snapper::list_pairs(runner, &config.name)? {
+            snapshots.push(VoleSnapshot {
+                id: format!("{}#{}", config.name, pair.pre),
+                provider: "Snapper",
+                created: parse_iso_datetime(&pair.date),
+                source: config.name.clone(),
+                kind: SnapshotKind::Snapper {
+                    config: config.name.clone(),
+                    subvolume: config.subvolume.clone(),
+                    pair,
+                },
+            });
+        }
+    }
+    Ok(snapshots)
+}
+
+fn list_timeshift(runner: &CommandRunner) -> Result<Vec<VoleSnapshot>> {
+    let output = runner.stdout("timeshift", ["--list", "--scripted"])?;
+    Ok(parse_timeshift_list(&output)
+        .into_iter()
+        .map(|name| VoleSnapshot {
+            id: name.clone(),
+            provider: "Timeshift",
+            created: parse_timeshift_name(&name),
+            source: "timeshift".to_string(),
+            kind: SnapshotKind::Timeshift { name },
+        })
+        .collect())
+}
+
+/// Names of the snapshots in `timeshift --list` output that Vole created.
+fn parse_timeshift_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.contains(TIMESHIFT_COMMENT))
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find(|token| parse_timeshift_name(token).is_some())
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Timeshift names snapshots `YYYY-MM-DD_HH-MM-SS`.
+fn parse_timeshift_name(name: &str) -> Option<u64> {
+    let (date, time) = name.split_once('_')?;
+    parse_civil(date, time.split('-'))
+}
+
+/// Parses `YYYY-MM-DD HH:MM:SS`.
+fn parse_iso_datetime(value: &str) -> Option<u64> {
+    let (date, time) = value.split_once(' ')?;
+    parse_civil(date, time.split(':'))
+}
+
+fn parse_civil<'a>(date: &str, time: impl Iterator<Item = &'a str>) -> Option<u64> {
+    let date = date
+        .split('-')
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let time = time
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let ([year, month, day], [hour, minute, second]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+    if !(1..=12).contains(month) || !(1..=31).contains(day) {
+        return None;
+    }
+    Some(timestamp_from_civil(
+        *year as i64,
+        *month,
+        *day,
+        (*hour, *minute, *second),
+    ))
+}
+
+/// Snapshots to delete: per source, everything beyond the newest `keep` that
+/// is also older than `older_than`. Either limit may be left out.
+pub fn select_prune(
+    snapshots: &[VoleSnapshot],
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+    now: u64,
+) -> Vec<VoleSnapshot> {
+    let mut by_source: HashMap<&str, Vec<&VoleSnapshot>> = HashMap::new();
+    for snapshot in snapshots {
+        by_source
+            .entry(&snapshot.source)
+            .or_default()
+            .push(snapshot);
+    }
+    let mut selected = Vec::new();
+    for snapshot in snapshots {
+        let mut newest_first = by_source[snapshot.source.as_str()].clone();
+        newest_first.sort_by_key(|other| std::cmp::Reverse(other.created));
+        let rank = newest_first
+            .iter()
+            .position(|other| other.id == snapshot.id)
+            .unwrap_or(0);
+        let beyond_keep = keep.is_none_or(|keep| rank >= keep);
+        // Snapshots of unknown age are never considered old.
+        let old = older_than.is_none_or(|age| {
+            snapshot
+                .created
+                .is_some_and(|created| now.saturating_sub(created) > age.as_secs())
+        });
+        if beyond_keep && old {
+            selected.push(snapshot.clone());
+        }
+    }
+    selected
+}
+
+pub fn delete(runner: &CommandRunner, snapshot: &VoleSnapshot) -> Result<()> {
+    match &snapshot.kind {
+        SnapshotKind::Btrfs { path, .. } => {
+            runner.output(
+                "btrfs",
+                [
+                    std::ffi::OsStr::new("subvolume"),
+                    std::ffi::OsStr::new("delete"),
+                    path.as_os_str(),
+                ],
+            )?;
+            fs::remove_file(source_marker(path)).ok();
+        }
+        SnapshotKind::Zfs { name } => zfs::destroy(runner, name)?,
+        SnapshotKind::Lvm { volume, .. } => lvm::remove(runner, volume)?,
+        SnapshotKind::Snapper { config, pair, .. } => snapper::delete(runner, config, pair)?,
+        SnapshotKind::Timeshift { name } => {
+            runner.output("timeshift", ["--delete", "--snapshot", name, "--scripted"])?;
+        }
+        SnapshotKind::Archive { path } => {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?
+        }
+    }
+    Ok(())
+}
+
+/// Copies `paths` (or the whole snapshot when empty) back out of `snapshot`,
+/// leaving anything that still exists untouched.
+pub fn restore(
+    runner: &CommandRunner,
+    mounts: &[MountEntry],
+    snapshot: &VoleSnapshot,
+    paths: &[PathBuf],
+) -> Result<RestoreReport> {
+    match &snapshot.kind {
+        SnapshotKind::Btrfs { path, source } => {
+            let source = match source {
+                Some(source) => source.clone(),
+                None => legacy_btrfs_source(path, paths)?,
+            };
+            restore_from(path, &source, paths)
+        }
+        SnapshotKind::Zfs { name } => {
+            let Some((mount_point, view)) = zfs::snapshot_view(mounts, name) else {
+                bail!("Dataset of {name} is not mounted");
+            };
+            restore_from(&view, &mount_point, paths)
+        }
+        SnapshotKind::Snapper {
+            subvolume, pair, ..
+        } => {
+            let view = subvolume
+                .join(".snapshots")
+                .join(pair.pre.to_string())
+                .join("snapshot");
+            restore_from(&view, subvolume, paths)
+        }
+        SnapshotKind::Lvm { volume, origin } => restore_lvm(runner, mounts, volume, origin, paths),
+        SnapshotKind::Archive { path } => archive::restore(path, paths),
+        SnapshotKind::Timeshift { name } => {
+            refuse!("Timeshift snapshots are restored with Timeshift itself (timeshift --restore --snapshot {name})")
+        }
+    }
+}
+
+/// Btrfs snapshots from before the source marker existed: the source is the
+/// child of the snapshot directory's parent that each path lives under.
+fn legacy_btrfs_source(snapshot: &Path, paths: &[PathBuf]) -> Result<PathBuf> {
+    let parent = snapshot
+        .ancestors()
+        .nth(3)
+        .context("Unexpected Btrfs snapshot location")?;
+    let Some(first) = paths.first() else {
+        bail!("This snapshot doesn't record its source; pass --path to choose what to restore");
+    };
+    let Some(Component::Normal(child)) = first
+        .strip_prefix(parent)
+        .ok()
+        .and_then(|rest| rest.components().next())
+    else {
+        bail!(
+            "{} is not covered by {}",
+            first.display(),
+            snapshot.display()
+        );
+    };
+    Ok(parent.join(child))
+}
+
+fn restore_lvm(
+    runner: &CommandRunner,
+    mounts: &[MountEntry],
+    volume: &str,
+    origin: &str,
+    paths: &[PathBuf],
+) -> Result<RestoreReport> {
+    let volumes = lvm::list_volumes(runner)?;
+    let find = |name: &str| volumes.iter().find(|lv| lv.full_name() == name);
+    let (Some(snapshot), Some(origin_lv)) = (find(volume), find(origin)) else {
+        bail!("Logical volume {volume} or its origin {origin} no longer exists");
+    };
+    let Some(origin_mount) = mounts
+        .iter()
+        .find(|entry| origin_lv.matches_device(&entry.source))
+    else {
+        bail!("Origin volume {origin} is not mounted");
+    };
+
+    let target = mount_dir()?;
+    let deactivate = lvm::mount_read_only(runner, snapshot, &origin_mount.fs_type, &target)?;
+    let report = restore_from(&target, &origin_mount.mount_point, paths);
+    let unmounted = lvm::unmount(runner, snapshot, &target, deactivate);
+    fs::remove_dir(&target).ok();
+    let report = report?;
+    unmounted?;
+    Ok(report)
+}
+
+/// A new `vole-restore-XXXXXX` directory in the temp dir, created with a
+/// random name and mode 0700 so nobody else can get at the mounted files.
+fn mount_dir() -> Result<PathBuf> {
+    let template = std::env::temp_dir().join("vole-restore-XXXXXX");
+    let mut template = CString::new(template.into_os_string().into_vec())
+        .context("Invalid temp directory")?
+        .into_bytes_with_nul();
+    if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+        return Err(io::Error::last_os_error()).context("Failed to create a mount directory");
+    }
+    template.pop();
+    Ok(PathBuf::from(OsString::from_vec(template)))
+}
+
+fn restore_from(view: &Path, source: &Path, paths: &[PathBuf]) -> Result<RestoreReport> {
+    if !view.is_dir() {
+        bail!("Snapshot contents are not accessible at {}", view.display());
+    }
+    let mut report = RestoreReport::default();
+    if paths.is_empty() {
+        copy_missing(view, source, source, &mut report)?;
+        return Ok(report);
+    }
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(source) else {
+            bail!(
+                "{} is outside the snapshotted {}",
+                path.display(),
+                source.display()
+            );
+        };
+        let from = view.join(relative);
+        if fs::symlink_metadata(&from).is_err() {
+            bail!("{} is not in the snapshot", path.display());
+        }
+        refuse_symlinked_parents(source, path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        copy_missing(&from, path, source, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Refuses `dest` when a directory between `source` and it is a symlink:
+/// restoring as root would write wherever the link points.
+fn refuse_symlinked_parents(source: &Path, dest: &Path) -> Result<()> {
+    if let Some(link) = dest
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| *ancestor != source && ancestor.starts_with(source))
+        .find(|ancestor| ancestor.is_symlink())
+    {
+        bail!(
+            "Refusing to restore {} through the symlink {}",
+            dest.display(),
+            link.display()
+        );
+    }
+    Ok(())
+}
+
+/// Recursively copies `from` to `to` (inside `source`), skipping entries that
+/// already exist and keeping ownership, permissions and modification times.
+/// Existing destinations are only descended into when they are real
+/// directories, never through symlinks.
+fn copy_missing(from: &Path, to: &Path, source: &Path, report: &mut RestoreReport) -> Result<()> {
+    let mut walk = WalkDir::new(from).into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(from).unwrap_or(Path::new(""));
+        let dest = if relative.as_os_str().is_empty() {
+            to.to_path_buf()
+        } else {
+            to.join(relative)
+        };
+        if let Ok(existing) = fs::symlink_metadata(&dest) {
+            if entry.file_type().is_dir() && existing.is_dir() {
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                walk.skip_current_dir();
+            }
+            report.skipped += 1;
+            continue;
+        }
+        refuse_symlinked_parents(source, &dest)?;
+        let meta = entry.path().symlink_metadata()?;
+        if entry.file_type().is_dir() {
+            fs::create_dir(&dest)
+                .with_context(|| format!("Failed to create {}", dest.display()))?;
+            fs::set_permissions(&dest, meta.permissions())?;
+        } else if entry.file_type().is_symlink() {
+            symlink(fs::read_link(entry.path())?, &dest)
+                .with_context(|| format!("Failed to restore {}", dest.display()))?;
+        } else {
+            fs::copy(entry.path(), &dest)
+                .with_context(|| format!("Failed to restore {}", dest.display()))?;
+            if let Ok(modified) = meta.modified() {
+                fs::File::options()
+                    .write(true)
+                    .open(&dest)
+                    .and_then(|file| file.set_modified(modified))
+                    .ok();
+            }
+        }
+        // Only root can hand files back to their owner; otherwise keep ours.
+        lchown(&dest, Some(meta.uid()), Some(meta.gid())).ok();
+        report.restored += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(id: &str, source: &str, created: u64) -> VoleSnapshot {
+        VoleSnapshot {
+            id: id.to_string(),
+            provider: "ZFS",
+            created: Some(created),
+            source: source.to_string(),
+            kind: SnapshotKind::Zfs {
+                name: id.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_select_prune() {
+        let day = 86_400;
+        let now = 100 * day;
+        let snapshots = vec![
+            snapshot("home@1", "home", now - 50 * day),
+            snapshot("home@2", "home", now - 40 * day),
+            snapshot("home@3", "home", now - 10 * day),
+            snapshot("root@1", "root", now - 60 * day),
+        ];
+        let ids = |selected: Vec<VoleSnapshot>| {
+            selected
+                .into_iter()
+                .map(|snapshot| snapshot.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(select_prune(&snapshots, Some(1), None, now)),
+            ["home@1", "home@2"]
+        );
+        assert_eq!(
+            ids(select_prune(
+                &snapshots,
+                None,
+                Some(Duration::from_secs(45 * day)),
+                now
+            )),
+            ["home@1", "root@1"]
+        );
+        assert_eq!(
+            ids(select_prune(
+                &snapshots,
+                Some(2),
+                Some(Duration::from_secs(30 * day)),
+                now
+            )),
+            ["home@1"]
+        );
+    }
+
+    #[test]
+    fn test_timeshift_and_snapper_dates() {
+        let output = "\
+Num     Name                 Tags  Description
+------------------------------------------------------------------------------
+0    >  2024-02-29_13-05-09  O     Vole clean
+1    >  2024-03-01_08-00-00  D     daily
+";
+        assert_eq!(parse_timeshift_list(output), ["2024-02-29_13-05-09"]);
+        assert_eq!(
+            parse_timeshift_name("2024-02-29_13-05-09"),
+            Some(1_709_211_909)
+        );
+        assert_eq!(
+            parse_iso_datetime("2024-02-29 13:05:09"),
+            Some(1_709_211_909)
+        );
+        assert_eq!(parse_iso_datetime("yesterday"), None);
+    }
+
+    #[test]
+    fn test_mount_dir_is_private() {
+        let first = mount_dir().unwrap();
+        let second = mount_dir().unwrap();
+        assert_ne!(first, second);
+        let meta = fs::symlink_metadata(&first).unwrap();
+        assert!(meta.is_dir());
+        assert_eq!(meta.mode() & 0o777, 0o700);
+        fs::remove_dir(first).unwrap();
+        fs::remove_dir(second).unwrap();
+    }
+
+    #[test]
+    fn test_restore_copies_only_missing() {
+        let snapshot_dir = tempfile::tempdir().unwrap();
+        let source_dir = tempfile::tempdir().unwrap();
+        let view = snapshot_dir.path();
+        let source = source_dir.path();
+        fs::create_dir_all(view.join("docs/old")).unwrap();
+        fs::write(view.join("docs/old/a.txt"), "a").unwrap();
+        fs::write(view.join("docs/b.txt"), "snapshot b").unwrap();
+        fs::write(view.join("other.txt"), "other").unwrap();
+        fs::create_dir_all(source.join("docs")).unwrap();
+        fs::write(source.join("docs/b.txt"), "current b").unwrap();
+
+        let report = restore_from(view, source, &[source.join("docs")]).unwrap();
+        assert_eq!(report.restored, 2);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(
+            fs::read_to_string(source.join("docs/old/a.txt")).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            fs::read_to_string(source.join("docs/b.txt")).unwrap(),
+            "current b"
+        );
+        assert!(!source.join("other.txt").exists());
+
+        assert!(restore_from(view, source, &[PathBuf::from("/elsewhere")]).is_err());
+        let report = restore_from(view, source, &[]).unwrap();
+        assert_eq!(report.restored, 1);
+    }
+
+    #[test]
+    fn test_restore_never_writes_through_symlinks() {
+        let snapshot_dir = tempfile::tempdir().unwrap();
+        let source_dir = tempfile::tempdir().unwrap();
+        let outside_dir = tempfile::tempdir().unwrap();
+        let view = snapshot_dir.path();
+        let source = source_dir.path();
+        let outside = outside_dir.path();
+        fs::create_dir_all(view.join("home/alice/config")).unwrap();
+        fs::write(view.join("home/alice/config/passwd"), "planted").unwrap();
+        // The user replaced a directory in their home with a link elsewhere.
+        fs::create_dir_all(source.join("home/alice")).unwrap();
+        symlink(outside, source.join("home/alice/config")).unwrap();
+
+        let report = restore_from(view, source, &[]).unwrap();
+        assert_eq!((report.restored, report.skipped), (0, 1));
+        assert!(!outside.join("passwd").exists());
+
+        let err =
+            restore_from(view, source, &[source.join("home/alice/config/passwd")]).unwrap_err();
+        assert!(format!("{err:#}").contains("through the symlink"));
+        assert!(!outside.join("passwd").exists());
+    }
+}
@@ -0,0 +1,245 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::command::CommandRunner;
+
+use super::{PostSnapshot, SnapshotOutcome};
+
+const DESCRIPTION: &str = "vole clean";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapperConfig {
+    pub name: String,
+    pub subvolume: PathBuf,
+}
+
+/// Parses `snapper --csvout list-configs --columns config,subvolume`.
+pub fn parse_configs(csv: &str) -> Vec<SnapperConfig> {
+    let mut lines = csv.lines();
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let columns = header.split(',').map(str::trim).collect::<Vec<_>>();
+    let Some(config_col) = columns.iter().position(|col| *col == "config") else {
+        return Vec::new();
+    };
+    let Some(subvolume_col) = columns.iter().position(|col| *col == "subvolume") else {
+        return Vec::new();
+    };
+    lines
+        .filter_map(|line| {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            Some(SnapperConfig {
+                name: fields.get(config_col)?.to_string(),
+                subvolume: PathBuf::from(fields.get(subvolume_col)?),
+            })
+        })
+        .filter(|config| !config.name.is_empty())
+        .collect()
+}
+
+pub fn list_configs(runner: &CommandRunner) -> Result<Vec<SnapperConfig>> {
+    let csv = runner.stdout(
+        "snapper",
+        ["--csvout", "list-configs", "--columns", "config,subvolume"],
+    )?;
+    Ok(parse_configs(&csv))
+}
+
+/// Creates the "pre" half of a pre/post pair; the outcome carries what is
+/// needed to create the matching "post" snapshot after apply.
+pub fn create_pre(runner: &CommandRunner, config: &str) -> Result<SnapshotOutcome> {
+    let number = create(runner, config, &["--type", "pre"])?;
+    Ok(SnapshotOutcome {
+        provider: "Snapper".to_string(),
+        location: None,
+        name: Some(format!("{config} #{number}")),
+        post: Some(PostSnapshot::Snapper {
+            config: config.to_string(),
+            pre_number: number,
+        }),
+    })
+}
+
+pub fn create_post(
+    runner: &CommandRunner,
+    config: &str,
+    pre_number: u32,
+) -> Result<SnapshotOutcome> {
+    let pre_number = pre_number.to_string();
+    let number = create(
+        runner,
+        config,
+        &["--type", "post", "--pre-number", pre_number.as_str()],
+    )?;
+    Ok(SnapshotOutcome {
+        provider: "Snapper".to_string(),
+        location: None,
+        name: Some(format!("{config} #{number}")),
+        post: None,
+    })
+}
+
+fn create(runner: &CommandRunner, config: &str, kind: &[&str]) -> Result<u32> {
+    let mut args = vec!["-c", config, "create"];
+    args.extend(kind);
+    args.extend([
+        "--cleanup-algorithm",
+        "number",
+        "--print-number",
+        "--description",
+        DESCRIPTION,
+    ]);
+    let stdout = runner
+        .stdout("snapper", &args)
+        .context("Failed to create snapper snapshot")?;
+    stdout
+        .trim()
+        .parse()
+        .with_context(|| format!("Unexpected snapper output: {}", stdout.trim()))
+}
+
+/// A Vole pre/post pair in one snapper config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapperPair {
+    pub pre: u32,
+    pub post: Option<u32>,
+    /// `YYYY-MM-DD HH:MM:SS` as printed with `--iso`.
+    pub date: String,
+}
+
+/// Parses `snapper --csvout --iso list --columns number,type,pre-number,date,description`
+/// into Vole's pre/post pairs.
+pub fn parse_pairs(csv: &str) -> Vec<SnapperPair> {
+    let rows = csv
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, ',');
+            let number = fields.next()?.trim().parse::<u32>().ok()?;
+            let kind = fields.next()?.trim().to_string();
+            let pre_number = fields.next()?.trim().parse::<u32>().ok();
+            let date = fields.next()?.trim().to_string();
+            let description = fields.next()?.trim().trim_matches('"').to_string();
+            Some((number, kind, pre_number, date, description))
+        })
+        .collect::<Vec<_>>();
+    rows.iter()
+        .filter(|(_, kind, _, _, description)| kind == "pre" && description == DESCRIPTION)
+        .map(|(number, _, _, date, _)| SnapperPair {
+            pre: *number,
+            post: rows
+                .iter()
+                .find(|(_, kind, pre_number, _, _)| kind == "post" && *pre_number == Some(*number))
+                .map(|(post, ..)| *post),
+            date: date.clone(),
+        })
+        .collect()
+}
+
+pub fn list_pairs(runner: &CommandRunner, config: &str) -> Result<Vec<SnapperPair>> {
+    let csv = runner.stdout(
+        "snapper",
+        [
+            "--csvout",
+            "--iso",
+            "-c",
+            config,
+            "list",
+            "--columns",
+            "number,type,pre-number,date,description",
+        ],
+    )?;
+    Ok(parse_pairs(&csv))
+}
+
+pub fn delete(runner: &CommandRunner, config: &str, pair: &SnapperPair) -> Result<()> {
+    let mut numbers = vec![pair.pre.to_string()];
+    numbers.extend(pair.post.map(|post| post.to_string()));
+    let mut args = vec!["-c", config, "delete"];
+    args.extend(numbers.iter().map(String::as_str));
+    runner.output("snapper", &args)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::command::testing::write_stub;
+
+    #[test]
+    fn test_parse_configs() {
+        let configs = parse_configs("config,subvolume\nroot,/\nhome,/home\n");
+        assert_eq!(
+            configs[1],
+            SnapperConfig {
+                name: "home".to_string(),
+                subvolume: PathBuf::from("/home"),
+            }
+        );
+        assert_eq!(configs.len(), 2);
+        assert!(parse_configs("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_pairs() {
+        let csv = "\
+number,type,pre-number,date,description
+0,single,,,current
+7,pre,,2024-02-29 13:05:09,vole clean
+8,post,7,2024-02-29 13:06:10,vole clean
+9,pre,,2024-03-01 10:00:00,zypp(zypper)
+11,pre,,2024-03-02 10:00:00,vole clean
+";
+        let pairs = parse_pairs(csv);
+        assert_eq!(
+            pairs,
+            vec![
+                SnapperPair {
+                    pre: 7,
+                    post: Some(8),
+                    date: "2024-02-29 13:05:09".to_string(),
+                },
+                SnapperPair {
+                    pre: 11,
+                    post: None,
+                    date: "2024-03-02 10:00:00".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snapper_pre_post_with_stub() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        write_stub(
+            dir.path(),
+            "snapper",
+            &format!(
+                "echo \"$@\" >> {}\ncase \"$*\" in *pre-number*) echo 8;; *) echo 7;; esac",
+                log.display()
+            ),
+        );
+        let runner = CommandRunner::with_search_path(dir.path());
+
+        let pre = create_pre(&runner, "home").unwrap();
+        assert_eq!(pre.name.as_deref(), Some("home #7"));
+        let Some(PostSnapshot::Snapper { config, pre_number }) = pre.post else {
+            panic!("expected a pending post snapshot");
+        };
+        let post = create_post(&runner, &config, pre_number).unwrap();
+        assert_eq!(post.name.as_deref(), Some("home #8"));
+
+        let calls = fs::read_to_string(log).unwrap();
+        let calls = calls.lines().collect::<Vec<_>>();
+        assert_eq!(
+            calls[0],
+            "-c home create --type pre --cleanup-algorithm number --print-number --description vole clean"
+        );
+        assert!(calls[1].contains("--type post --pre-number 7"));
+    }
+}
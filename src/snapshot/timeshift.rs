@@ -0,0 +1,341 @@
+use std::fs;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use humansize::{format_size, BINARY};
+use serde_json::Value;
+
+use crate::command::CommandRunner;
+use crate::exit::refuse;
+use crate::mounts::MountEntry;
+use crate::space;
+
+use super::{SnapshotOutcome, TIMESHIFT_COMMENT};
+
+const CONFIG_PATHS: [&str; 2] = ["/etc/timeshift/timeshift.json", "/etc/timeshift.json"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeshiftMode {
+    Btrfs,
+    Rsync,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeshiftSettings {
+    pub mode: TimeshiftMode,
+    pub backup_device_uuid: Option<String>,
+    excludes: GlobSet,
+}
+
+impl TimeshiftSettings {
+    /// Whether rsync mode copies `path` (it isn't in the exclude list).
+    pub fn covers(&self, path: &Path) -> bool {
+        !self.excludes.is_match(path)
+    }
+}
+
+pub fn parse_settings(data: &str) -> TimeshiftSettings {
+    let Ok(json) = serde_json::from_str::<Value>(data) else {
+        let mode = if data.to_lowercase().contains("btrfs") {
+            TimeshiftMode::Btrfs
+        } else {
+            TimeshiftMode::Rsync
+        };
+        return TimeshiftSettings {
+            mode,
+            backup_device_uuid: None,
+            excludes: GlobSet::empty(),
+        };
+    };
+
+    let text = |key: &str| match json.get(key) {
+        Some(Value::String(value)) => Some(value.clone()),
+        Some(Value::Bool(value)) => Some(value.to_string()),
+        _ => None,
+    };
+    // Timeshift itself writes `"btrfs_mode": "true"` / `"false"`.
+    let btrfs = match text("btrfs_mode") {
+        Some(value) => value.eq_ignore_ascii_case("true"),
+        None => ["snapshot_type", "backup_type", "mode"]
+            .iter()
+            .filter_map(|key| text(key))
+            .any(|value| value.to_lowercase().contains("btrfs")),
+    };
+
+    let mut excludes = GlobSetBuilder::new();
+    let patterns = json
+        .get("exclude")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        // `+ pattern` entries are includes.
+        .filter(|pattern| !pattern.starts_with('+'));
+    for pattern in patterns {
+        let pattern = pattern.trim_start_matches("- ").trim_end_matches('/');
+        let pattern = pattern.strip_suffix("/**").unwrap_or(pattern);
+        for glob in [pattern.to_string(), format!("{pattern}/**")] {
+            if let Ok(glob) = Glob::new(&glob) {
+                excludes.add(glob);
+            }
+        }
+    }
+
+    TimeshiftSettings {
+        mode: if btrfs {
+            TimeshiftMode::Btrfs
+        } else {
+            TimeshiftMode::Rsync
+        },
+        backup_device_uuid: text("backup_device_uuid").filter(|uuid| !uuid.is_empty()),
+        excludes: excludes.build().unwrap_or_else(|_| GlobSet::empty()),
+    }
+}
+
+pub fn read_settings(runner: &CommandRunner) -> Option<TimeshiftSettings> {
+    if !runner.has("timeshift") {
+        return None;
+    }
+    CONFIG_PATHS
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|data| parse_settings(&data))
+}
+
+/// Rough size of a new rsync snapshot: the first one copies everything in
+/// use on the root filesystem, later ones hard-link unchanged files and at
+/// most need room for what cleanup is about to delete.
+pub fn estimate_snapshot_bytes(has_previous: bool, root_used: u64, cleanup_bytes: u64) -> u64 {
+    if has_previous {
+        cleanup_bytes
+    } else {
+        root_used
+    }
+}
+
+pub fn create_btrfs(runner: &CommandRunner) -> Result<SnapshotOutcome> {
+    create_with_progress(runner)?;
+    Ok(SnapshotOutcome {
+        provider: "Timeshift".to_string(),
+        location: None,
+        name: None,
+        post: None,
+    })
+}
+
+/// Checks the backup device has room for the snapshot, then runs
+/// `timeshift --create`.
+pub fn create_rsync(
+    runner: &CommandRunner,
+    mounts: &[MountEntry],
+    backup_device_uuid: Option<&str>,
+    cleanup_bytes: u64,
+) -> Result<SnapshotOutcome> {
+    let backup = backup_device_uuid
+        .map(|uuid| Path::new("/dev/disk/by-uuid").join(uuid))
+        .and_then(|device| backup_mount(mounts, &device));
+    match backup {
+        Some(mount_point) => {
+            let has_previous = fs::read_dir(mount_point.join("timeshift/snapshots"))
+                .map(|mut entries| entries.next().is_some())
+                .unwrap_or(false);
+            let root = space::usage(Path::new("/")).context("Failed to check root usage")?;
+            let estimate = estimate_snapshot_bytes(
+                has_previous,
+                root.total.saturating_sub(root.available),
+                cleanup_bytes,
+            );
+            let backup = space::usage(&mount_point).context("Failed to check backup device")?;
+            ensure_room(backup.available, estimate, &mount_point)?;
+            eprintln!(
+                "Timeshift snapshot needs about {} ({} free on {}).",
+                format_size(estimate, BINARY),
+                format_size(backup.available, BINARY),
+                mount_point.display()
+            );
+        }
+        None => eprintln!("Timeshift backup device is not mounted; skipping the free-space check."),
+    }
+
+    create_with_progress(runner)?;
+    Ok(SnapshotOutcome {
+        provider: "Timeshift (rsync)".to_string(),
+        location: None,
+        name: None,
+        post: None,
+    })
+}
+
+fn ensure_room(available: u64, estimate: u64, mount_point: &Path) -> Result<()> {
+    if available < estimate {
+        refuse!(
+            "Timeshift backup device {} has {} free but the snapshot needs about {}",
+            mount_point.display(),
+            format_size(available, BINARY),
+            format_size(estimate, BINARY)
+        );
+    }
+    Ok(())
+}
+
+/// Where the backup `device` is mounted, if it is.
+fn backup_mount(mounts: &[MountEntry], device: &Path) -> Option<PathBuf> {
+    let device = fs::canonicalize(device).ok()?;
+    mounts
+        .iter()
+        .find(|entry| fs::canonicalize(&entry.source).ok().as_deref() == Some(device.as_path()))
+        .map(|entry| entry.mount_point.clone())
+}
+
+/// Runs `timeshift --create`, keeping a one-line status with the elapsed time
+/// and Timeshift's latest message on stderr. When stderr isn't a terminal
+/// (logs, systemd units) only the start and the end are printed.
+fn create_with_progress(runner: &CommandRunner) -> Result<()> {
+    let mut child = runner
+        .command("timeshift")?
+        .args([
+            "--create",
+            "--scripted",
+            "--comments",
+            TIMESHIFT_COMMENT,
+            "--tags",
+            "O",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run timeshift")?;
+
+    let latest = Arc::new(Mutex::new(String::new()));
+    let stdout = child
+        .stdout
+        .take()
+        .context("timeshift stdout unavailable")?;
+    let reader = {
+        let latest = Arc::clone(&latest);
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let line = line.trim().to_string();
+                if !line.is_empty() {
+                    *latest.lock().unwrap() = line;
+                }
+            }
+        })
+    };
+    let mut stderr = child
+        .stderr
+        .take()
+        .context("timeshift stderr unavailable")?;
+    let errors = thread::spawn(move || {
+        let mut text = String::new();
+        stderr.read_to_string(&mut text).ok();
+        text
+    });
+
+    let started = Instant::now();
+    let animate = std::io::stderr().is_terminal();
+    if !animate {
+        eprintln!("Creating Timeshift snapshot...");
+    }
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if animate {
+            let message = latest.lock().unwrap().clone();
+            eprint!(
+                "\r\x1b[KCreating Timeshift snapshot ({}s) {}",
+                started.elapsed().as_secs(),
+                message
+            );
+            std::io::stderr().flush().ok();
+        }
+        thread::sleep(Duration::from_millis(500));
+    };
+    let clear = if animate { "\r\x1b[K" } else { "" };
+    eprintln!(
+        "{clear}Timeshift finished in {}s",
+        started.elapsed().as_secs()
+    );
+    reader.join().ok();
+    let errors = errors.join().unwrap_or_default();
+
+    if !status.success() {
+        bail!("timeshift snapshot command failed: {}", errors.trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::exit::{code, error_code};
+
+    const RSYNC_CONFIG: &str = r#"{
+  "backup_device_uuid" : "0b5f0c31-7b44-4c55-9d8a-2f7b1f9e9e11",
+  "btrfs_mode" : "false",
+  "include_btrfs_home_for_backup" : "false",
+  "exclude" : [
+    "/home/alice/**",
+    "/root/**",
+    "+ /home/alice/.config/**"
+  ]
+}"#;
+
+    #[test]
+    fn test_parse_settings() {
+        let settings = parse_settings(RSYNC_CONFIG);
+        assert_eq!(settings.mode, TimeshiftMode::Rsync);
+        assert_eq!(
+            settings.backup_device_uuid.as_deref(),
+            Some("0b5f0c31-7b44-4c55-9d8a-2f7b1f9e9e11")
+        );
+        assert!(settings.covers(Path::new("/var/cache/apt")));
+        assert!(!settings.covers(Path::new("/home/alice/.cache")));
+        assert!(!settings.covers(Path::new("/root")));
+
+        let btrfs = parse_settings(r#"{"btrfs_mode": "true"}"#);
+        assert_eq!(btrfs.mode, TimeshiftMode::Btrfs);
+        let legacy = parse_settings(r#"{"snapshot_type": "BTRFS"}"#);
+        assert_eq!(legacy.mode, TimeshiftMode::Btrfs);
+    }
+
+    #[test]
+    fn test_estimate_and_room() {
+        assert_eq!(estimate_snapshot_bytes(false, 40 << 30, 1 << 30), 40 << 30);
+        assert_eq!(estimate_snapshot_bytes(true, 40 << 30, 1 << 30), 1 << 30);
+        let err = ensure_room(10, 20, Path::new("/mnt/backup")).unwrap_err();
+        assert_eq!(error_code(&err), code::SAFETY_REFUSAL);
+        assert!(ensure_room(20, 20, Path::new("/mnt/backup")).is_ok());
+    }
+
+    #[test]
+    fn test_create_rsync_with_stub() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        write_stub(
+            dir.path(),
+            "timeshift",
+            &format!(
+                "echo \"$@\" >> {}\necho 'Synching files with rsync...'",
+                log.display()
+            ),
+        );
+        let runner = CommandRunner::with_search_path(dir.path());
+
+        let outcome = create_rsync(&runner, &[], None, 0).unwrap();
+        assert_eq!(outcome.provider, "Timeshift (rsync)");
+        let calls = fs::read_to_string(log).unwrap();
+        assert_eq!(
+            calls.trim(),
+            "--create --scripted --comments Vole clean --tags O"
+        );
+    }
+}
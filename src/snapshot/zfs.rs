@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::command::CommandRunner;
+use crate::mounts::{self, MountEntry};
+
+use super::{snapshot_name, SnapshotOutcome, SNAPSHOT_PREFIX};
+
+/// The ZFS dataset `path` lives on, if it is mounted from one.
+pub fn dataset_for(mounts: &[MountEntry], path: &Path) -> Option<String> {
+    mounts::mount_for(mounts, path)
+        .filter(|entry| entry.fs_type == "zfs")
+        .map(|entry| entry.source.clone())
+}
+
+pub fn create(runner: &CommandRunner, dataset: &str) -> Result<SnapshotOutcome> {
+    let name = format!("{dataset}@{}", snapshot_name());
+    runner
+        .output("zfs", ["snapshot", name.as_str()])
+        .context("Failed to create ZFS snapshot")?;
+    Ok(SnapshotOutcome {
+        provider: "ZFS".to_string(),
+        location: None,
+        name: Some(name),
+        post: None,
+    })
+}
+
+/// Vole's snapshots from `zfs list -H -p -t snapshot -o name,creation`, as
+/// `(name, creation)` pairs.
+pub fn parse_snapshots(output: &str) -> Vec<(String, u64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, creation) = line.split_once('\t')?;
+            let (_, snapshot) = name.split_once('@')?;
+            if !snapshot.starts_with(SNAPSHOT_PREFIX) {
+                return None;
+            }
+            Some((name.to_string(), creation.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+pub fn list_snapshots(runner: &CommandRunner) -> Result<Vec<(String, u64)>> {
+    let output = runner.stdout(
+        "zfs",
+        ["list", "-H", "-p", "-t", "snapshot", "-o", "name,creation"],
+    )?;
+    Ok(parse_snapshots(&output))
+}
+
+pub fn destroy(runner: &CommandRunner, name: &str) -> Result<()> {
+    runner.output("zfs", ["destroy", name])?;
+    Ok(())
+}
+
+/// Where the dataset is mounted and where the snapshot's read-only view of it
+/// lives (`<mount point>/.zfs/snapshot/<snapshot>`).
+pub fn snapshot_view(mounts: &[MountEntry], name: &str) -> Option<(PathBuf, PathBuf)> {
+    let (dataset, snapshot) = name.split_once('@')?;
+    let entry = mounts
+        .iter()
+        .find(|entry| entry.fs_type == "zfs" && entry.source == dataset)?;
+    let view = entry.mount_point.join(".zfs/snapshot").join(snapshot);
+    Some((entry.mount_point.clone(), view))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::command::testing::write_stub;
+    use crate::mounts::parse_mountinfo;
+
+    const MOUNTINFO: &str = "\
+22 1 0:21 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+41 22 0:35 / /home rw,relatime shared:20 - zfs tank/home rw,xattr
+";
+
+    #[test]
+    fn test_zfs_snapshot_with_stub() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        write_stub(
+            dir.path(),
+            "zfs",
+            &format!("echo \"$@\" >> {}", log.display()),
+        );
+        let runner = CommandRunner::with_search_path(dir.path());
+        let mounts = parse_mountinfo(MOUNTINFO);
+
+        let dataset = dataset_for(&mounts, Path::new("/home/alice")).unwrap();
+        assert_eq!(dataset, "tank/home");
+        assert!(dataset_for(&mounts, Path::new("/var/cache")).is_none());
+
+        let outcome = create(&runner, &dataset).unwrap();
+        let name = outcome.name.unwrap();
+        assert!(name.starts_with("tank/home@vole-clean-"));
+        let calls = fs::read_to_string(log).unwrap();
+        assert_eq!(calls.trim(), format!("snapshot {name}"));
+    }
+
+    #[test]
+    fn test_parse_snapshots_and_view() {
+        let output = "tank/home@vole-clean-1700000000\t1700000000\n\
+tank/home@autosnap_2024\t1700000100\n";
+        assert_eq!(
+            parse_snapshots(output),
+            vec![("tank/home@vole-clean-1700000000".to_string(), 1_700_000_000)]
+        );
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let (mount_point, view) = snapshot_view(&mounts, "tank/home@vole-clean-1").unwrap();
+        assert_eq!(mount_point, PathBuf::from("/home"));
+        assert_eq!(view, PathBuf::from("/home/.zfs/snapshot/vole-clean-1"));
+    }
+}
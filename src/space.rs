@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use humansize::{format_size, BINARY};
+
+use crate::units::parse_size;
+
+/// A free-space amount: either absolute bytes or a percentage of the filesystem.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpaceAmount {
+    Bytes(u64),
+    Percent(f64),
+}
+
+impl SpaceAmount {
+    pub fn bytes_of(self, total: u64) -> u64 {
+        match self {
+            SpaceAmount::Bytes(bytes) => bytes,
+            SpaceAmount::Percent(percent) => (total as f64 * percent / 100.0).ceil() as u64,
+        }
+    }
+
+    /// Exact command-line form, for passing the amount on to another process.
+    pub fn to_arg(self) -> String {
+        match self {
+            SpaceAmount::Bytes(bytes) => bytes.to_string(),
+            SpaceAmount::Percent(percent) => format!("{percent}%"),
+        }
+    }
+}
+
+impl FromStr for SpaceAmount {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        if let Some(percent) = input.trim().strip_suffix('%') {
+            let Ok(value) = percent.trim().parse::<f64>() else {
+                bail!("Invalid percentage '{input}'");
+            };
+            if !(0.0..=100.0).contains(&value) {
+                bail!("Percentage must be between 0 and 100");
+            }
+            return Ok(SpaceAmount::Percent(value));
+        }
+        parse_size(input).map(SpaceAmount::Bytes)
+    }
+}
+
+impl fmt::Display for SpaceAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpaceAmount::Bytes(bytes) => f.write_str(&format_size(*bytes, BINARY)),
+            SpaceAmount::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+/// Free space on one filesystem, identified by its device id.
+#[derive(Debug, Clone)]
+pub struct FsUsage {
+    pub device: u64,
+    /// An existing path on the filesystem, used for display and re-checks.
+    pub path: PathBuf,
+    pub total: u64,
+    pub available: u64,
+}
+
+impl FsUsage {
+    pub fn below(&self, amount: SpaceAmount) -> bool {
+        self.available < amount.bytes_of(self.total)
+    }
+
+    pub fn refresh(&self) -> io::Result<FsUsage> {
+        usage(&self.path)
+    }
+}
+
+pub fn usage(path: &Path) -> io::Result<FsUsage> {
+    let path = nearest_existing(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no existing ancestor"))?;
+    let device = std::fs::metadata(&path)?.dev();
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fragment = stat.f_frsize as u64;
+    Ok(FsUsage {
+        device,
+        path,
+        total: stat.f_blocks as u64 * fragment,
+        available: stat.f_bavail as u64 * fragment,
+    })
+}
+
+/// Device ids of the filesystems `paths` live on.
+pub fn devices(paths: &[PathBuf]) -> Vec<u64> {
+    let mut devices = Vec::new();
+    for path in paths {
+        if let Some(existing) = nearest_existing(path) {
+            if let Ok(meta) = std::fs::metadata(existing) {
+                if !devices.contains(&meta.dev()) {
+                    devices.push(meta.dev());
+                }
+            }
+        }
+    }
+    devices
+}
+
+/// Distinct filesystems `paths` live on, in first-seen order.
+pub fn filesystems(paths: &[PathBuf]) -> Vec<FsUsage> {
+    let mut usages: Vec<FsUsage> = Vec::new();
+    for path in paths {
+        let Ok(fs_usage) = usage(path) else {
+            continue;
+        };
+        if !usages.iter().any(|known| known.device == fs_usage.device) {
+            usages.push(fs_usage);
+        }
+    }
+    usages
+}
+
+/// Estimates how many leading entries of `rule_bytes` must be applied before
+/// every filesystem in `usages` reaches `target`. Each entry pairs the devices
+/// a rule touches with the bytes it would free.
+pub fn estimate_until_free(
+    usages: &[FsUsage],
+    rule_bytes: &[(Vec<u64>, u64)],
+    target: SpaceAmount,
+) -> usize {
+    let mut available = usages
+        .iter()
+        .map(|usage| (usage.device, (usage.available, usage.total)))
+        .collect::<HashMap<_, _>>();
+    let reached = |available: &HashMap<u64, (u64, u64)>| {
+        available
+            .values()
+            .all(|(free, total)| *free >= target.bytes_of(*total))
+    };
+    for (index, (devices, bytes)) in rule_bytes.iter().enumerate() {
+        if reached(&available) {
+            return index;
+        }
+        for device in devices {
+            if let Some((free, _)) = available.get_mut(device) {
+                *free = free.saturating_add(*bytes);
+            }
+        }
+    }
+    rule_bytes.len()
+}
+
+fn nearest_existing(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_space_amount() {
+        assert_eq!(
+            "10%".parse::<SpaceAmount>().unwrap(),
+            SpaceAmount::Percent(10.0)
+        );
+        assert_eq!(
+            "20GiB".parse::<SpaceAmount>().unwrap(),
+            SpaceAmount::Bytes(20 << 30)
+        );
+        assert!("120%".parse::<SpaceAmount>().is_err());
+        assert_eq!(SpaceAmount::Percent(25.0).bytes_of(400), 100);
+        assert_eq!(SpaceAmount::Bytes(1536).to_arg(), "1536");
+    }
+
+    #[test]
+    fn test_estimate_until_free() {
+        let usages = vec![FsUsage {
+            device: 1,
+            path: PathBuf::from("/"),
+            total: 1000,
+            available: 100,
+        }];
+        let rules = vec![(vec![1], 50), (vec![2], 500), (vec![1], 100), (vec![1], 10)];
+        assert_eq!(
+            estimate_until_free(&usages, &rules, SpaceAmount::Bytes(250)),
+            3
+        );
+        assert_eq!(
+            estimate_until_free(&usages, &rules, SpaceAmount::Percent(5.0)),
+            0
+        );
+        assert_eq!(
+            estimate_until_free(&usages, &rules, SpaceAmount::Bytes(900)),
+            4
+        );
+    }
+
+    #[test]
+    fn test_usage_of_missing_path_uses_ancestor() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = usage(&dir.path().join("missing/child")).unwrap();
+        assert_eq!(usage.path, dir.path());
+        assert!(usage.total >= usage.available);
+    }
+}
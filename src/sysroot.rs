@@ -0,0 +1,204 @@
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// Root directory of the system being cleaned: `/` for the live system, or a
+/// chroot, container image or fixture tree given with `--root`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysRoot {
+    path: PathBuf,
+}
+
+impl Default for SysRoot {
+    fn default() -> Self {
+        Self::host()
+    }
+}
+
+impl SysRoot {
+    pub fn host() -> Self {
+        Self {
+            path: PathBuf::from("/"),
+        }
+    }
+
+    pub fn new(path: &Path) -> Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve root {}", path.display()))?;
+        if !path.is_dir() {
+            bail!("Root {} is not a directory", path.display());
+        }
+        Ok(Self { path })
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.path == Path::new("/")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where `path`, as seen from inside the root, lives on this machine.
+    /// Symlinks are resolved the way a chroot would: absolute targets start
+    /// over at the root. Paths already under the root (like homes that were
+    /// re-based before) are resolved the same way.
+    pub fn join(&self, path: &Path) -> Result<PathBuf> {
+        self.resolve(path, true)
+    }
+
+    /// Like [`SysRoot::join`] for a directory vole walks and deletes from,
+    /// which must not be a symlink itself under `--root`.
+    pub fn join_scan_root(&self, path: &Path) -> Result<PathBuf> {
+        let resolved = self.resolve(path, false)?;
+        if !self.is_host() && resolved.is_symlink() {
+            bail!(
+                "Refusing to scan {}: it is a symlink inside {}",
+                resolved.display(),
+                self.path.display()
+            );
+        }
+        Ok(resolved)
+    }
+
+    fn resolve(&self, path: &Path, follow_last: bool) -> Result<PathBuf> {
+        if self.is_host() {
+            return Ok(path.to_path_buf());
+        }
+        let relative = path.strip_prefix(&self.path).unwrap_or(path);
+        // Components still to resolve, last first; `true` marks those read
+        // from a symlink target.
+        let mut pending = parts(relative, false);
+        let mut resolved = self.path.clone();
+        let mut depth = 0usize;
+        let mut links = 0;
+        while let Some((part, from_link)) = pending.pop() {
+            let name = match part {
+                Part::Name(name) => name,
+                Part::Parent if depth > 0 => {
+                    resolved.pop();
+                    depth -= 1;
+                    continue;
+                }
+                // `/..` is `/` for paths given to us, but a link climbing
+                // out of the root is refused.
+                Part::Parent if from_link => bail!(
+                    "{} escapes {} through a symlink",
+                    path.display(),
+                    self.path.display()
+                ),
+                Part::Parent => continue,
+            };
+            let candidate = resolved.join(name);
+            let is_link = (follow_last || !pending.is_empty()) && candidate.is_symlink();
+            if !is_link {
+                resolved = candidate;
+                depth += 1;
+                continue;
+            }
+            links += 1;
+            if links > MAX_SYMLINKS {
+                bail!("Too many levels of symlinks in {}", path.display());
+            }
+            let target = candidate
+                .read_link()
+                .with_context(|| format!("Failed to read symlink {}", candidate.display()))?;
+            if target.is_absolute() {
+                resolved = self.path.clone();
+                depth = 0;
+            }
+            pending.extend(parts(&target, true));
+        }
+        Ok(resolved)
+    }
+}
+
+/// Matches the kernel's limit on symlinks followed in one lookup.
+const MAX_SYMLINKS: usize = 40;
+
+enum Part {
+    Parent,
+    Name(OsString),
+}
+
+/// The `..` and named components of `path`, last first.
+fn parts(path: &Path, from_link: bool) -> Vec<(Part, bool)> {
+    path.components()
+        .rev()
+        .filter_map(|component| match component {
+            Component::ParentDir => Some(Part::Parent),
+            Component::Normal(name) => Some(Part::Name(name.to_os_string())),
+            _ => None,
+        })
+        .map(|part| (part, from_link))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_join_rebases_absolute_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = SysRoot::new(dir.path()).unwrap();
+        let base = root.path().to_path_buf();
+        let join = |path: &Path| root.join(path).unwrap();
+        assert_eq!(join(Path::new("/etc/passwd")), base.join("etc/passwd"));
+        assert_eq!(
+            join(Path::new("/../etc/os-release")),
+            base.join("etc/os-release")
+        );
+        assert_eq!(join(&base.join("home/alice")), base.join("home/alice"));
+        assert_eq!(
+            SysRoot::host().join(Path::new("/etc/passwd")).unwrap(),
+            Path::new("/etc/passwd")
+        );
+        assert!(SysRoot::new(&base.join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_join_resolves_symlinks_inside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let host = dir.path().join("host");
+        let image = dir.path().join("image");
+        std::fs::create_dir_all(host.join("cache")).unwrap();
+        std::fs::create_dir_all(image.join("var")).unwrap();
+        std::fs::create_dir_all(image.join("usr/lib")).unwrap();
+        // Absolute targets point at the host outside a chroot.
+        symlink(&host, image.join("var/cache")).unwrap();
+        symlink("/usr/lib", image.join("lib")).unwrap();
+        symlink("../../../..", image.join("var/up")).unwrap();
+        symlink("loop", image.join("loop")).unwrap();
+        let root = SysRoot::new(&image).unwrap();
+        let base = root.path().to_path_buf();
+
+        assert_eq!(
+            root.join(Path::new("/var/cache/cache")).unwrap(),
+            base.join(host.strip_prefix("/").unwrap()).join("cache")
+        );
+        assert_eq!(
+            root.join(Path::new("/lib/os-release")).unwrap(),
+            base.join("usr/lib/os-release")
+        );
+        assert_eq!(
+            root.join(&base.join("lib/../etc")).unwrap(),
+            base.join("usr/etc")
+        );
+        assert!(root.join(Path::new("/var/up/etc")).is_err());
+        assert!(root.join(Path::new("/loop/x")).is_err());
+
+        assert!(root.join_scan_root(Path::new("/var/cache")).is_err());
+        assert_eq!(
+            root.join_scan_root(Path::new("/usr/lib")).unwrap(),
+            base.join("usr/lib")
+        );
+        // The host itself is never re-based.
+        assert!(SysRoot::host()
+            .join_scan_root(&image.join("var/cache"))
+            .is_ok());
+    }
+}
@@ -0,0 +1,249 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::clean::{CleanReport, RuleScan};
+use crate::mounts::{self, MountEntry};
+
+/// Moves the files and directories of `scans` to the freedesktop.org Trash
+/// instead of deleting them, so they can be restored from a file manager.
+pub fn apply(scans: &[RuleScan], home: &Path) -> CleanReport {
+    let home_trash = home_trash(home);
+    let mounts = mounts::read_mountinfo().unwrap_or_default();
+    let mut report = CleanReport::default();
+    for scan in scans {
+        for path in scan.files.iter().chain(&scan.dirs) {
+            let meta = match fs::symlink_metadata(path) {
+                Ok(meta) => meta,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(_) => {
+                    report.errors += 1;
+                    continue;
+                }
+            };
+            if scan.owner.is_some_and(|uid| meta.uid() != uid) {
+                report.errors += 1;
+                continue;
+            }
+            let result = trash_dir(path, home, &home_trash, &mounts)
+                .and_then(|trash| move_to_trash(path, &trash));
+            match result {
+                Ok(()) => {
+                    report.files_trashed += 1;
+                    if meta.is_file() {
+                        report.bytes_trashed += meta.len();
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Failed to move {} to the Trash: {err}", path.display());
+                    report.errors += 1;
+                }
+            }
+        }
+    }
+    report
+}
+
+/// A trash directory. For one at a filesystem's top directory, paths are
+/// recorded relative to `top`.
+struct Trash {
+    dir: PathBuf,
+    top: Option<PathBuf>,
+}
+
+/// `$XDG_DATA_HOME/Trash`.
+fn home_trash(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home.join(".local/share"))
+        .join("Trash")
+}
+
+/// The trash for `path`: the home trash on the home filesystem, otherwise
+/// `$topdir/.Trash/$uid` when an administrator set that up, else
+/// `$topdir/.Trash-$uid`.
+fn trash_dir(
+    path: &Path,
+    home: &Path,
+    home_trash: &Path,
+    mounts: &[MountEntry],
+) -> io::Result<Trash> {
+    let device = fs::symlink_metadata(path)?.dev();
+    if fs::metadata(home)?.dev() == device {
+        return Ok(Trash {
+            dir: home_trash.to_path_buf(),
+            top: None,
+        });
+    }
+    let Some(mount) = mounts::mount_for(mounts, path) else {
+        return Err(io::Error::other("no mount point found"));
+    };
+    let top = mount.mount_point.clone();
+    let uid = unsafe { libc::geteuid() };
+    let admin = top.join(".Trash");
+    let sticky = fs::symlink_metadata(&admin)
+        .is_ok_and(|meta| meta.is_dir() && meta.mode() & libc::S_ISVTX != 0);
+    let dir = if sticky {
+        admin.join(uid.to_string())
+    } else {
+        top.join(format!(".Trash-{uid}"))
+    };
+    Ok(Trash {
+        dir,
+        top: Some(top),
+    })
+}
+
+/// Moves `path` into the trash's `files`, recording where it came from in
+/// `info`. Name clashes get a `.2`, `.3`, ... before the extension.
+fn move_to_trash(path: &Path, trash: &Trash) -> io::Result<()> {
+    let files = trash.dir.join("files");
+    let info = trash.dir.join("info");
+    for dir in [&files, &info] {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::other("path has no file name"));
+    };
+    let original = match &trash.top {
+        Some(top) => path.strip_prefix(top).unwrap_or(path),
+        None => path,
+    };
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(original),
+        deletion_date(SystemTime::now())
+    );
+
+    for attempt in 1.. {
+        let candidate = candidate_name(name, attempt);
+        if fs::symlink_metadata(files.join(&candidate)).is_ok() {
+            continue;
+        }
+        let mut info_name = candidate.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(info_name);
+        // Creating the info file reserves the name.
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+        let moved = file
+            .write_all(contents.as_bytes())
+            .and_then(|_| fs::rename(path, files.join(&candidate)));
+        if let Err(err) = moved {
+            let _ = fs::remove_file(&info_path);
+            return Err(err);
+        }
+        return Ok(());
+    }
+    unreachable!()
+}
+
+fn candidate_name(name: &OsStr, attempt: usize) -> OsString {
+    if attempt == 1 {
+        return name.to_os_string();
+    }
+    let path = Path::new(name);
+    let mut candidate = path.file_stem().unwrap_or(name).to_os_string();
+    candidate.push(format!(".{attempt}"));
+    if let Some(extension) = path.extension() {
+        candidate.push(".");
+        candidate.push(extension);
+    }
+    candidate
+}
+
+/// Percent-encodes `path` as the trash spec asks (RFC 2396 URI escaping).
+fn encode_path(path: &Path) -> String {
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect()
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in local time.
+fn deletion_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_to_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = Trash {
+            dir: dir.path().join("share/Trash"),
+            top: None,
+        };
+        let first = dir.path().join("My Videos/talk.mkv");
+        let second = dir.path().join("other/talk.mkv");
+        for path in [&first, &second] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"video").unwrap();
+        }
+
+        move_to_trash(&first, &trash).unwrap();
+        move_to_trash(&second, &trash).unwrap();
+        assert!(!first.exists() && !second.exists());
+        assert_eq!(
+            fs::read(trash.dir.join("files/talk.mkv")).unwrap(),
+            b"video"
+        );
+        assert!(trash.dir.join("files/talk.2.mkv").exists());
+        let info = fs::read_to_string(trash.dir.join("info/talk.mkv.trashinfo")).unwrap();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("Path={}/My%20Videos/talk.mkv", dir.path().display())
+        );
+        assert!(lines.next().unwrap().starts_with("DeletionDate="));
+        assert!(trash.dir.join("info/talk.2.mkv.trashinfo").exists());
+
+        let top = dir.path().join("disk");
+        let external = top.join("backups/old.iso");
+        fs::create_dir_all(external.parent().unwrap()).unwrap();
+        fs::write(&external, b"iso").unwrap();
+        let trash = Trash {
+            dir: top.join(".Trash-1000"),
+            top: Some(top.clone()),
+        };
+        move_to_trash(&external, &trash).unwrap();
+        let info = fs::read_to_string(trash.dir.join("info/old.iso.trashinfo")).unwrap();
+        assert!(info.contains("\nPath=backups/old.iso\n"));
+    }
+}
@@ -1,3 +1,7 @@
+pub mod dupes;
+pub mod large;
+
+use std::collections::BTreeMap;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -64,10 +68,11 @@ mod symbols {
 use ratatui::Terminal;
 use serde::{Deserialize, Serialize};
 
-use crate::clean::{dry_run_output, scan_rule, write_dry_run_report};
-use crate::config::{Rule, RuleKind};
+use crate::clean::{dry_run_output, scan_rule, write_dry_run_report, Package};
+use crate::config::{tier_label, Rule, RuleKind};
 use crate::options::{DownloadsChoice, ScanOptions};
 use crate::snapshot::SnapshotSupport;
+use crate::sysroot::SysRoot;
 
 const OUTPUT_SCROLL_STEP: isize = 3;
 
@@ -80,6 +85,9 @@ pub struct PersistedState {
     pub include_sudo: bool,
     #[serde(default)]
     pub downloads_choice: Option<DownloadsChoice>,
+    /// Packages unchecked in the package picker, by rule id.
+    #[serde(default)]
+    pub deselected: BTreeMap<String, Vec<Package>>,
 }
 
 pub struct RunConfig {
@@ -91,6 +99,7 @@ pub struct RunConfig {
     pub sudo_reexec: Option<Vec<String>>,
     pub initial_state: Option<PersistedState>,
     pub home: PathBuf,
+    pub root: SysRoot,
 }
 
 pub fn run(config: RunConfig) -> Result<TuiExit> {
@@ -103,6 +112,7 @@ pub fn run(config: RunConfig) -> Result<TuiExit> {
         sudo_reexec,
         initial_state,
         home,
+        root,
     } = config;
     let mut terminal = setup_terminal()?;
     let mut app = AppState::new(
@@ -114,6 +124,7 @@ pub fn run(config: RunConfig) -> Result<TuiExit> {
         sudo_reexec,
         home,
     );
+    app.root = root;
     if let Some(state) = initial_state {
         app.apply_state(&state);
     }
@@ -131,7 +142,7 @@ pub enum TuiExit {
     Quit,
     Apply {
         rules: Vec<Rule>,
-        snapshot: Option<SnapshotSupport>,
+        snapshot: bool,
         downloads_choice: Option<DownloadsChoice>,
     },
     ReexecSudo {
@@ -143,6 +154,47 @@ struct RuleState {
     rule: Rule,
     enabled: bool,
     scan: Option<crate::clean::RuleScan>,
+    /// Packages the user unchecked in the package picker.
+    deselected: Vec<Package>,
+}
+
+impl RuleState {
+    /// The rule as scanned and applied: deselected packages become
+    /// exclusions.
+    fn effective_rule(&self) -> Rule {
+        let mut rule = self.rule.clone();
+        rule.exclude_globs.extend(
+            self.deselected
+                .iter()
+                .map(|package| globset::escape(&package.name)),
+        );
+        rule
+    }
+}
+
+/// Per-package selection for a rule that removes packages, shown in place of
+/// the rule list.
+struct PackagePicker {
+    rule_index: usize,
+    packages: Vec<(Package, bool)>,
+    list_state: ListState,
+}
+
+impl PackagePicker {
+    fn move_selection(&mut self, delta: isize) {
+        if self.packages.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.packages.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    fn toggle_at(&mut self, index: usize) {
+        if let Some((_, checked)) = self.packages.get_mut(index) {
+            *checked = !*checked;
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -183,9 +235,11 @@ struct AppState {
     sudo_reexec_args: Option<Vec<String>>,
     layout: UiLayout,
     home: PathBuf,
+    root: SysRoot,
     output_lines: Vec<String>,
     output_scroll: usize,
     downloads_choice: Option<DownloadsChoice>,
+    package_picker: Option<PackagePicker>,
 }
 
 impl AppState {
@@ -214,6 +268,7 @@ impl AppState {
                     },
                     rule,
                     scan: None,
+                    deselected: Vec::new(),
                 })
                 .collect(),
             list_state,
@@ -234,9 +289,11 @@ impl AppState {
             sudo_reexec_args,
             layout: UiLayout::default(),
             home,
+            root: SysRoot::host(),
             output_lines: Vec::new(),
             output_scroll: 0,
             downloads_choice: None,
+            package_picker: None,
         }
     }
 
@@ -247,14 +304,73 @@ impl AppState {
                 state.scan = None;
                 continue;
             }
-            state.scan = Some(scan_rule(&state.rule, &options));
+            state.scan = Some(scan_rule(&state.effective_rule(), &options));
         }
         self.message = message;
     }
 
+    fn open_package_picker(&mut self) {
+        let Some(index) = self.list_state.selected() else {
+            return;
+        };
+        let Some(state) = self.rules.get(index) else {
+            return;
+        };
+        let mut packages = state
+            .scan
+            .iter()
+            .flat_map(|scan| scan.packages.iter())
+            .map(|package| (package.clone(), true))
+            .collect::<Vec<_>>();
+        packages.extend(
+            state
+                .deselected
+                .iter()
+                .map(|package| (package.clone(), false)),
+        );
+        if packages.is_empty() {
+            self.message = Some("This rule has no packages to choose from".to_string());
+            return;
+        }
+        packages.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        self.package_picker = Some(PackagePicker {
+            rule_index: index,
+            packages,
+            list_state,
+        });
+    }
+
+    fn close_package_picker(&mut self) {
+        let Some(picker) = self.package_picker.take() else {
+            return;
+        };
+        let options = self.scan_options();
+        let Some(state) = self.rules.get_mut(picker.rule_index) else {
+            return;
+        };
+        state.deselected = picker
+            .packages
+            .into_iter()
+            .filter(|(_, checked)| !checked)
+            .map(|(package, _)| package)
+            .collect();
+        if state.scan.is_some() {
+            state.scan = Some(scan_rule(&state.effective_rule(), &options));
+        }
+        self.message = Some(format!(
+            "{} packages deselected for {}",
+            state.deselected.len(),
+            state.rule.label
+        ));
+    }
+
     fn scan_options(&self) -> ScanOptions {
         ScanOptions {
             downloads_choice: self.downloads_choice,
+            root: self.root.clone(),
+            ..ScanOptions::default()
         }
     }
 
@@ -303,17 +419,40 @@ impl AppState {
         self.ensure_visible(clamped);
     }
 
+    /// Whether the rule at `index` starts a tier group, under a header row.
+    fn starts_tier(&self, index: usize) -> bool {
+        index == 0 || self.rules[index - 1].rule.tier != self.rules[index].rule.tier
+    }
+
+    /// Row of the rule at `index` in the list, counting the tier headers.
+    /// The list state's offset is in rows; its selection is a rule index.
+    fn row_of(&self, index: usize) -> usize {
+        index + (0..=index).filter(|&i| self.starts_tier(i)).count()
+    }
+
+    fn rule_at_row(&self, row: usize) -> Option<usize> {
+        (0..self.rules.len()).find(|&index| self.row_of(index) == row)
+    }
+
     fn ensure_visible(&mut self, index: usize) {
         let height = self.list_height();
         if height == 0 {
             return;
         }
+        let row = self.row_of(index);
+        // Scrolling up to the first rule of a tier shows its header too.
+        let top = if self.starts_tier(index) {
+            row - 1
+        } else {
+            row
+        };
         let offset = self.list_state.offset();
-        let max_offset = self.rules.len().saturating_sub(height);
-        if index < offset {
-            *self.list_state.offset_mut() = index;
-        } else if index >= offset + height {
-            *self.list_state.offset_mut() = (index + 1 - height).min(max_offset);
+        let rows = self.row_of(self.rules.len() - 1) + 1;
+        let max_offset = rows.saturating_sub(height);
+        if top < offset {
+            *self.list_state.offset_mut() = top;
+        } else if row >= offset + height {
+            *self.list_state.offset_mut() = (row + 1 - height).min(max_offset);
         }
     }
 
@@ -418,7 +557,9 @@ impl AppState {
             self.rescan_with_message(Some("Sudo mode enabled: dry-run forced ON".to_string()));
         } else {
             let message = if !self.include_sudo {
-                self.snapshot_enabled = false;
+                if self.snapshot_requires_root() {
+                    self.snapshot_enabled = false;
+                }
                 Some("Sudo rules disabled (still running as root)".to_string())
             } else {
                 Some("Scan complete".to_string())
@@ -427,6 +568,12 @@ impl AppState {
         }
     }
 
+    fn snapshot_requires_root(&self) -> bool {
+        self.snapshot_support
+            .as_ref()
+            .is_some_and(|support| support.provider.requires_root())
+    }
+
     fn toggle_snapshot(&mut self) {
         if self.snapshot_support.is_none() {
             return;
@@ -435,7 +582,7 @@ impl AppState {
             self.message = Some("Disable dry-run to use snapshots".to_string());
             return;
         }
-        if !self.include_sudo {
+        if !self.include_sudo && self.snapshot_requires_root() {
             self.message = Some("Enable sudo to use snapshots".to_string());
             return;
         }
@@ -469,7 +616,7 @@ impl AppState {
         self.rules
             .iter()
             .filter(|rule| rule.enabled)
-            .map(|rule| rule.rule.clone())
+            .map(RuleState::effective_rule)
             .collect()
     }
 
@@ -489,9 +636,17 @@ impl AppState {
             state.dry_run
         };
         self.downloads_choice = state.downloads_choice;
-        self.snapshot_enabled =
-            state.snapshot_enabled && self.snapshot_support.is_some() && self.include_sudo;
+        self.snapshot_enabled = state.snapshot_enabled
+            && self.snapshot_support.is_some()
+            && (self.include_sudo || !self.snapshot_requires_root());
         self.apply_enabled_rules(&state.enabled_rules, state.selected_rule.as_deref());
+        for rule in &mut self.rules {
+            rule.deselected = state
+                .deselected
+                .get(&rule.rule.id)
+                .cloned()
+                .unwrap_or_default();
+        }
     }
 
     fn export_state(&self) -> PersistedState {
@@ -511,6 +666,12 @@ impl AppState {
             snapshot_enabled: self.snapshot_enabled,
             include_sudo: self.include_sudo,
             downloads_choice: self.downloads_choice,
+            deselected: self
+                .rules
+                .iter()
+                .filter(|rule| !rule.deselected.is_empty())
+                .map(|rule| (rule.rule.id.clone(), rule.deselected.clone()))
+                .collect(),
         }
     }
 
@@ -586,6 +747,12 @@ impl AppState {
             "Dry-run listed {} files and {} directories",
             report.files_listed, report.dirs_listed
         ));
+        if report.commands_listed > 0 {
+            lines.push(format!(
+                "Would run {} cleanup commands",
+                report.commands_listed
+            ));
+        }
         lines.push(format!(
             "Would free {}",
             format_size(report.bytes_listed, BINARY)
@@ -648,6 +815,21 @@ fn handle_key(app: &mut AppState, key: KeyEvent) -> Result<Option<TuiExit>> {
         return Ok(None);
     }
 
+    if let Some(picker) = &mut app.package_picker {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => picker.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => picker.move_selection(-1),
+            KeyCode::Char(' ') => {
+                if let Some(index) = picker.list_state.selected() {
+                    picker.toggle_at(index);
+                }
+            }
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Char('o') => app.close_package_picker(),
+            _ => {}
+        }
+        return Ok(None);
+    }
+
     if app.confirm_apply {
         if app.confirm_requires_delete {
             match key.code {
@@ -659,11 +841,7 @@ fn handle_key(app: &mut AppState, key: KeyEvent) -> Result<Option<TuiExit>> {
                 KeyCode::Enter => {
                     if app.confirm_buffer.eq_ignore_ascii_case("delete") {
                         let rules = app.selected_rules();
-                        let snapshot = if app.snapshot_enabled {
-                            app.snapshot_support.clone()
-                        } else {
-                            None
-                        };
+                        let snapshot = app.snapshot_enabled && app.snapshot_support.is_some();
                         return Ok(Some(TuiExit::Apply {
                             rules,
                             snapshot,
@@ -676,6 +854,7 @@ fn handle_key(app: &mut AppState, key: KeyEvent) -> Result<Option<TuiExit>> {
                 KeyCode::Backspace => {
                     app.confirm_buffer.pop();
                 }
+                #[allow(clippy::collapsible_match)]
                 KeyCode::Char(c) => {
                     if c.is_ascii_alphabetic() && app.confirm_buffer.len() < 6 {
                         app.confirm_buffer.push(c.to_ascii_uppercase());
@@ -698,11 +877,7 @@ fn handle_key(app: &mut AppState, key: KeyEvent) -> Result<Option<TuiExit>> {
                         return Ok(None);
                     }
                     let rules = app.selected_rules();
-                    let snapshot = if app.snapshot_enabled {
-                        app.snapshot_support.clone()
-                    } else {
-                        None
-                    };
+                    let snapshot = app.snapshot_enabled && app.snapshot_support.is_some();
                     return Ok(Some(TuiExit::Apply {
                         rules,
                         snapshot,
@@ -750,6 +925,9 @@ fn handle_key(app: &mut AppState, key: KeyEvent) -> Result<Option<TuiExit>> {
         KeyCode::Char('p') => {
             app.toggle_snapshot();
         }
+        KeyCode::Char('o') => {
+            app.open_package_picker();
+        }
         KeyCode::Char('a') => {
             begin_apply(app);
         }
@@ -782,6 +960,22 @@ fn handle_mouse(app: &mut AppState, mouse: MouseEvent) -> Result<Option<TuiExit>
         return Ok(None);
     }
 
+    if let Some(picker) = &mut app.package_picker {
+        if let (MouseEventKind::Down(MouseButton::Left), Some(list_area)) =
+            (mouse.kind, app.layout.list_area)
+        {
+            if contains(list_area, mouse.column, mouse.row) {
+                let index = picker.list_state.offset()
+                    + (mouse.row.saturating_sub(list_area.y) as usize);
+                if index < picker.packages.len() {
+                    picker.list_state.select(Some(index));
+                    picker.toggle_at(index);
+                }
+            }
+        }
+        return Ok(None);
+    }
+
     let row = mouse.row;
     let col = mouse.column;
 
@@ -815,14 +1009,15 @@ fn handle_mouse(app: &mut AppState, mouse: MouseEvent) -> Result<Option<TuiExit>
             if let Some(list_area) = app.layout.list_area {
                 if contains(list_area, col, row) {
                     let offset = app.list_state.offset();
-                    let index = offset + (row.saturating_sub(list_area.y) as usize);
-                    if index < app.rules.len() {
+                    let clicked = offset + (row.saturating_sub(list_area.y) as usize);
+                    if let Some(index) = app.rule_at_row(clicked) {
                         app.select_index(index);
                         app.toggle_at(index);
                     }
                 }
             }
         }
+        #[allow(clippy::collapsible_match)]
         MouseEventKind::Drag(MouseButton::Left) => {
             if in_scrollbar_area(app, col, row) || in_output_area(app, col, row) {
                 app.jump_output_to_row(row);
@@ -845,7 +1040,7 @@ fn begin_apply(app: &mut AppState) {
         app.confirm_buffer.clear();
     } else if app.snapshot_enabled && app.dry_run {
         app.message = Some("Disable dry-run to create snapshots".to_string());
-    } else if app.snapshot_enabled && !app.include_sudo {
+    } else if app.snapshot_enabled && !app.include_sudo && app.snapshot_requires_root() {
         app.message = Some("Enable sudo to use snapshots".to_string());
     } else {
         app.confirm_apply = true;
@@ -1113,11 +1308,26 @@ fn draw_ui(frame: &mut ratatui::Frame<'_>, app: &mut AppState) {
 }
 
 fn draw_list(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
-    let items = app
-        .rules
-        .iter()
-        .enumerate()
-        .map(|(idx, state)| {
+    if app.package_picker.is_some() {
+        draw_package_picker(frame, app, area);
+        return;
+    }
+    let mut items = Vec::new();
+    for (idx, state) in app.rules.iter().enumerate() {
+        if app.starts_tier(idx) {
+            let tier = state.rule.tier;
+            items.push(ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!(" Tier {tier} "),
+                    Style::default().fg(theme::ACCENT).bold(),
+                ),
+                Span::styled(
+                    format!("· {}", tier_label(tier)),
+                    Style::default().fg(theme::TEXT_DIM).italic(),
+                ),
+            ])));
+        }
+        items.push({
             let is_selected = app.list_state.selected() == Some(idx);
             let checkbox = if state.enabled {
                 Span::styled(
@@ -1182,12 +1392,13 @@ fn draw_list(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
                 sudo_span,
                 size,
             ]))
-        })
-        .collect::<Vec<_>>();
+        });
+    }
 
     let list_title = Title::from(Line::from(vec![
         Span::styled(" 🗑 ", Style::default().fg(theme::ACCENT)),
         Span::styled("Cleanup Rules ", Style::default().fg(theme::ACCENT).bold()),
+        Span::styled("by tier ", Style::default().fg(theme::TEXT_DIM)),
     ]));
     let list_block = Block::default()
         .title(list_title)
@@ -1202,7 +1413,78 @@ fn draw_list(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
             .add_modifier(Modifier::BOLD),
     );
 
-    frame.render_stateful_widget(list, area, &mut app.list_state);
+    // Render with the selection as a row; the tier headers shift rules down.
+    let mut rows = ListState::default()
+        .with_offset(app.list_state.offset())
+        .with_selected(app.list_state.selected().map(|index| app.row_of(index)));
+    frame.render_stateful_widget(list, area, &mut rows);
+    *app.list_state.offset_mut() = rows.offset();
+}
+
+fn draw_package_picker(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
+    let Some(picker) = &mut app.package_picker else {
+        return;
+    };
+    let label = app
+        .rules
+        .get(picker.rule_index)
+        .map(|state| state.rule.label.clone())
+        .unwrap_or_default();
+    let selected = picker.list_state.selected();
+    let items = picker
+        .packages
+        .iter()
+        .enumerate()
+        .map(|(idx, (package, checked))| {
+            let arrow = if selected == Some(idx) {
+                Span::styled(
+                    format!("{} ", symbols::ARROW_RIGHT),
+                    Style::default().fg(theme::HIGHLIGHT).bold(),
+                )
+            } else {
+                Span::raw("  ")
+            };
+            let checkbox = if *checked {
+                Span::styled(
+                    format!(" {} ", symbols::CHECK),
+                    Style::default().fg(theme::ENABLED).bold(),
+                )
+            } else {
+                Span::styled(
+                    format!(" {} ", symbols::BULLET_EMPTY),
+                    Style::default().fg(theme::DISABLED),
+                )
+            };
+            let name_style = if *checked {
+                Style::default().fg(theme::TEXT)
+            } else {
+                Style::default().fg(theme::TEXT_DIM)
+            };
+            ListItem::new(Line::from(vec![
+                arrow,
+                checkbox,
+                Span::styled(package.name.clone(), name_style),
+                Span::styled(
+                    format!("  {}", format_size(package.bytes, BINARY)),
+                    Style::default().fg(theme::ACCENT_DIM),
+                ),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 📦 ", Style::default().fg(theme::ACCENT)),
+        Span::styled(format!("{label} "), Style::default().fg(theme::ACCENT).bold()),
+        Span::styled("packages ", Style::default().fg(theme::TEXT_DIM)),
+    ]));
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER_FOCUSED));
+    app.layout.list_area = Some(block.inner(area));
+    let list = List::new(items).block(block);
+    frame.render_stateful_widget(list, area, &mut picker.list_state);
 }
 
 fn draw_status(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
@@ -1313,6 +1595,16 @@ fn draw_status(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
             Style::default().fg(theme::TEXT_MUTED),
         ));
     }
+    if app.rules.iter().any(|rule| {
+        !rule.deselected.is_empty() || rule.scan.as_ref().is_some_and(|scan| !scan.packages.is_empty())
+    }) {
+        help_spans.push(Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)));
+        help_spans.push(Span::styled("o", Style::default().fg(theme::TEXT_DIM)));
+        help_spans.push(Span::styled(
+            " packages",
+            Style::default().fg(theme::TEXT_MUTED),
+        ));
+    }
     help_spans.push(Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)));
     help_spans.push(Span::styled("enter", Style::default().fg(theme::DANGER)));
     help_spans.push(Span::styled(
@@ -1423,7 +1715,12 @@ fn draw_output(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
 }
 
 fn draw_message(frame: &mut ratatui::Frame<'_>, app: &mut AppState, area: Rect) {
-    let (message_text, message_style) = if app.confirm_downloads_choice {
+    let (message_text, message_style) = if app.package_picker.is_some() {
+        (
+            "  📦 Space toggles a package; Enter or Esc returns to the rules".to_string(),
+            Style::default().fg(theme::INFO),
+        )
+    } else if app.confirm_downloads_choice {
         (
             "  📁 Downloads cleanup: remove (a)rchives or (f)olders?".to_string(),
             Style::default().fg(theme::WARNING),
@@ -1505,3 +1802,60 @@ fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Re
     terminal.show_cursor()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_keeps_deselected_packages() {
+        let rule: Rule = serde_json::from_str(
+            r#"{ "id": "orphans", "label": "Orphans", "kind": "orphans", "enabled_by_default": true }"#,
+        )
+        .unwrap();
+        let new_app = || {
+            AppState::new(
+                vec![rule.clone()],
+                None,
+                true,
+                false,
+                false,
+                None,
+                PathBuf::from("/home/alice"),
+            )
+        };
+        let package = Package {
+            name: "libfoo".to_string(),
+            bytes: 2048,
+        };
+        let mut app = new_app();
+        app.rules[0].deselected.push(package.clone());
+
+        let data = serde_json::to_vec(&app.export_state_for_sudo()).unwrap();
+        let state: PersistedState = serde_json::from_slice(&data).unwrap();
+        let mut restored = new_app();
+        restored.apply_state(&state);
+        assert_eq!(restored.rules[0].deselected, [package]);
+        assert_eq!(restored.rules[0].effective_rule().exclude_globs, ["libfoo"]);
+    }
+
+    #[test]
+    fn test_tier_headers_shift_rows() {
+        let rules = [1, 1, 2, 3]
+            .iter()
+            .enumerate()
+            .map(|(index, tier)| {
+                serde_json::from_str(&format!(
+                    r#"{{ "id": "r{index}", "label": "R", "kind": "paths", "tier": {tier} }}"#
+                ))
+                .unwrap()
+            })
+            .collect();
+        let app = AppState::new(rules, None, false, false, false, None, PathBuf::new());
+        let rows = (0..4).map(|index| app.row_of(index)).collect::<Vec<_>>();
+        assert_eq!(rows, [1, 2, 4, 6]);
+        assert_eq!(app.rule_at_row(0), None);
+        assert_eq!(app.rule_at_row(4), Some(2));
+        assert_eq!(app.rule_at_row(5), None);
+    }
+}
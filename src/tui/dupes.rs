@@ -0,0 +1,526 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use humansize::{format_size, BINARY};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    block::Title, Block, BorderType, Borders, List, ListItem, ListState, Paragraph,
+};
+use ratatui::Terminal;
+
+use super::{contains, restore_terminal, setup_terminal, symbols, theme};
+use crate::clean::write_dry_run_report;
+use crate::dupes::{dry_run_details, DupeAction, DupeSet, Keep, Replacement};
+
+pub struct RunConfig {
+    pub sets: Vec<DupeSet>,
+    /// Picks the copy left unmarked in each set at start.
+    pub keep: Keep,
+    pub action: DupeAction,
+    pub dry_run: bool,
+    pub home: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum DupesExit {
+    Quit,
+    Apply {
+        replacements: Vec<Replacement>,
+        action: DupeAction,
+    },
+}
+
+/// Shows duplicate sets with a checkbox per copy; checked copies are removed
+/// with the chosen action.
+pub fn run(config: RunConfig) -> Result<DupesExit> {
+    let mut terminal = setup_terminal()?;
+    let mut app = DupesApp::new(config);
+    let exit = run_app(&mut terminal, &mut app);
+    restore_terminal(&mut terminal)?;
+    exit
+}
+
+/// A line in the list: a set header or one of its copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Set(usize),
+    File(usize, usize),
+}
+
+struct DupesApp {
+    sets: Vec<DupeSet>,
+    /// Copies marked for removal, per set.
+    marked: Vec<Vec<bool>>,
+    rows: Vec<Row>,
+    list_state: ListState,
+    list_area: Option<Rect>,
+    action: DupeAction,
+    dry_run: bool,
+    confirm_apply: bool,
+    message: Option<String>,
+    output_lines: Vec<String>,
+    home: PathBuf,
+}
+
+impl DupesApp {
+    fn new(config: RunConfig) -> Self {
+        let RunConfig {
+            sets,
+            keep,
+            action,
+            dry_run,
+            home,
+        } = config;
+        let marked = sets
+            .iter()
+            .map(|set| {
+                let keeper = set.keeper(keep);
+                (0..set.files.len()).map(|index| index != keeper).collect()
+            })
+            .collect();
+        let rows = sets
+            .iter()
+            .enumerate()
+            .flat_map(|(set_index, set)| {
+                std::iter::once(Row::Set(set_index))
+                    .chain((0..set.files.len()).map(move |index| Row::File(set_index, index)))
+            })
+            .collect::<Vec<_>>();
+        let message = format!(
+            "{} duplicate sets; checked copies will be {}",
+            sets.len(),
+            action_done(action)
+        );
+        let mut list_state = ListState::default();
+        if !rows.is_empty() {
+            list_state.select(Some(0));
+        }
+        Self {
+            sets,
+            marked,
+            rows,
+            list_state,
+            list_area: None,
+            action,
+            dry_run,
+            confirm_apply: false,
+            message: Some(message),
+            output_lines: Vec::new(),
+            home,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.rows.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    /// Toggles a copy, or a whole set between "all but one" and "none".
+    fn toggle_at(&mut self, index: usize) {
+        match self.rows.get(index) {
+            Some(Row::File(set, file)) => {
+                let marked = &mut self.marked[*set];
+                if !marked[*file] && marked.iter().filter(|marked| !**marked).count() == 1 {
+                    self.message = Some("Keep at least one copy of each set".to_string());
+                    return;
+                }
+                marked[*file] = !marked[*file];
+            }
+            Some(Row::Set(set)) => {
+                let marked = &mut self.marked[*set];
+                let any = marked.iter().any(|marked| *marked);
+                for (index, mark) in marked.iter_mut().enumerate() {
+                    *mark = !any && index != 0;
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn replacements(&self) -> Vec<Replacement> {
+        self.sets
+            .iter()
+            .zip(&self.marked)
+            .flat_map(|(set, marked)| set.replacements(marked))
+            .collect()
+    }
+
+    fn run_dry_run(&mut self) {
+        let replacements = self.replacements();
+        let details = dry_run_details(&self.sets, &replacements, self.action);
+        let mut lines = details.lines().map(str::to_string).collect::<Vec<_>>();
+        match write_dry_run_report(&self.home, &details) {
+            Ok(path) => lines.push(format!("Dry-run report saved to {}", path.display())),
+            Err(err) => lines.push(format!("Failed to write dry-run report: {err}")),
+        }
+        lines.push(format!(
+            "Would free {}",
+            format_size(
+                replacements.iter().map(|item| item.bytes).sum::<u64>(),
+                BINARY
+            )
+        ));
+        self.output_lines = lines;
+        self.message = Some("Dry-run complete (see Output panel)".to_string());
+    }
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut DupesApp,
+) -> Result<DupesExit> {
+    loop {
+        terminal.draw(|frame| draw_ui(frame, app))?;
+
+        if event::poll(Duration::from_millis(200))? {
+            let exit = match event::read()? {
+                Event::Key(key) => handle_key(app, key),
+                Event::Mouse(mouse) => {
+                    handle_mouse(app, mouse);
+                    None
+                }
+                _ => None,
+            };
+            if let Some(exit) = exit {
+                return Ok(exit);
+            }
+        }
+    }
+}
+
+fn handle_key(app: &mut DupesApp, key: KeyEvent) -> Option<DupesExit> {
+    if app.confirm_apply {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                app.confirm_apply = false;
+                if app.dry_run {
+                    app.run_dry_run();
+                    return None;
+                }
+                return Some(DupesExit::Apply {
+                    replacements: app.replacements(),
+                    action: app.action,
+                });
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                app.confirm_apply = false;
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return Some(DupesExit::Quit),
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return Some(DupesExit::Quit);
+        }
+        KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+        KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+        KeyCode::PageDown => app.move_selection(10),
+        KeyCode::PageUp => app.move_selection(-10),
+        KeyCode::Char(' ') => {
+            if let Some(index) = app.list_state.selected() {
+                app.toggle_at(index);
+            }
+        }
+        KeyCode::Char('m') => {
+            app.action = app.action.next();
+            app.message = Some(format!(
+                "Checked copies will be {}",
+                action_done(app.action)
+            ));
+        }
+        KeyCode::Char('d') => app.dry_run = !app.dry_run,
+        KeyCode::Char('a') | KeyCode::Enter => {
+            if app.replacements().is_empty() {
+                app.message = Some("No copies checked".to_string());
+            } else {
+                app.confirm_apply = true;
+            }
+        }
+        _ => {}
+    }
+    None
+}
+
+fn handle_mouse(app: &mut DupesApp, mouse: MouseEvent) {
+    if app.confirm_apply {
+        return;
+    }
+    let Some(list_area) = app.list_area else {
+        return;
+    };
+    if !contains(list_area, mouse.column, mouse.row) {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollDown => app.move_selection(1),
+        MouseEventKind::ScrollUp => app.move_selection(-1),
+        MouseEventKind::Down(MouseButton::Left) => {
+            let index = app.list_state.offset() + (mouse.row.saturating_sub(list_area.y) as usize);
+            if index < app.rows.len() {
+                app.list_state.select(Some(index));
+                app.toggle_at(index);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn action_done(action: DupeAction) -> &'static str {
+    match action {
+        DupeAction::Delete => "deleted",
+        DupeAction::Hardlink => "replaced by hardlinks",
+        DupeAction::Reflink => "replaced by reflinks",
+    }
+}
+
+fn draw_ui(frame: &mut ratatui::Frame<'_>, app: &mut DupesApp) {
+    let output_height = if app.output_lines.is_empty() { 0 } else { 10 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(5),
+            Constraint::Length(output_height),
+            Constraint::Length(3),
+        ])
+        .split(frame.size());
+
+    draw_list(frame, app, chunks[0]);
+    draw_status(frame, app, chunks[1]);
+    if !app.output_lines.is_empty() {
+        draw_output(frame, app, chunks[2]);
+    }
+    draw_message(frame, app, chunks[3]);
+}
+
+fn draw_list(frame: &mut ratatui::Frame<'_>, app: &mut DupesApp, area: Rect) {
+    let selected = app.list_state.selected();
+    let items = app
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(idx, row)| {
+            let arrow = if selected == Some(idx) {
+                Span::styled(
+                    format!("{} ", symbols::ARROW_RIGHT),
+                    Style::default().fg(theme::HIGHLIGHT).bold(),
+                )
+            } else {
+                Span::raw("  ")
+            };
+            match *row {
+                Row::Set(set) => {
+                    let set = &app.sets[set];
+                    ListItem::new(Line::from(vec![
+                        arrow,
+                        Span::styled(
+                            format!(
+                                "{} copies × {}",
+                                set.files.len(),
+                                format_size(set.size, BINARY)
+                            ),
+                            Style::default().fg(theme::ACCENT).bold(),
+                        ),
+                        Span::styled(
+                            format!("  wastes {}", format_size(set.wasted(), BINARY)),
+                            Style::default().fg(theme::ACCENT_DIM),
+                        ),
+                    ]))
+                }
+                Row::File(set, file) => {
+                    let marked = app.marked[set][file];
+                    let file = &app.sets[set].files[file];
+                    let (checkbox, label, style) = if marked {
+                        (
+                            Span::styled(
+                                format!("   {} ", symbols::CHECK),
+                                Style::default().fg(theme::DANGER).bold(),
+                            ),
+                            app.action.as_str(),
+                            Style::default().fg(theme::TEXT),
+                        )
+                    } else {
+                        (
+                            Span::styled(
+                                format!("   {} ", symbols::BULLET_EMPTY),
+                                Style::default().fg(theme::ENABLED),
+                            ),
+                            "keep",
+                            Style::default().fg(theme::TEXT_DIM),
+                        )
+                    };
+                    ListItem::new(Line::from(vec![
+                        arrow,
+                        checkbox,
+                        Span::styled(format!("{label:<8} "), style),
+                        Span::styled(
+                            format!("{}  ", file.modified_label()),
+                            Style::default().fg(theme::TEXT_DIM),
+                        ),
+                        Span::styled(file.path.display().to_string(), style),
+                    ]))
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 🗂 ", Style::default().fg(theme::ACCENT)),
+        Span::styled(
+            "Duplicate Files ",
+            Style::default().fg(theme::ACCENT).bold(),
+        ),
+        Span::styled("by wasted space ", Style::default().fg(theme::TEXT_DIM)),
+    ]));
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER_FOCUSED));
+    app.list_area = Some(block.inner(area));
+    let list = List::new(items).block(block);
+    frame.render_stateful_widget(list, area, &mut app.list_state);
+}
+
+fn draw_status(frame: &mut ratatui::Frame<'_>, app: &mut DupesApp, area: Rect) {
+    let replacements = app.replacements();
+    let bytes = replacements.iter().map(|item| item.bytes).sum::<u64>();
+    let summary = Line::from(vec![
+        Span::styled("  ✨ Checked: ", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(
+            replacements.len().to_string(),
+            Style::default().fg(theme::ACCENT).bold(),
+        ),
+        Span::styled(" copies │ ", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(
+            format_size(bytes, BINARY),
+            Style::default().fg(theme::SUCCESS).bold(),
+        ),
+        Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("Action: ", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(app.action.as_str(), Style::default().fg(theme::INFO).bold()),
+        Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("⚡ Dry-run: ", Style::default().fg(theme::TEXT_DIM)),
+        if app.dry_run {
+            Span::styled("ON", Style::default().fg(theme::DRY_RUN).bold())
+        } else {
+            Span::styled("OFF", Style::default().fg(theme::TEXT_DIM))
+        },
+    ]);
+    let help = Line::from(vec![
+        Span::styled("  ↑↓", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(" move │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("space", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(" toggle │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("m", Style::default().fg(theme::INFO)),
+        Span::styled(" action │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("d", Style::default().fg(theme::DRY_RUN)),
+        Span::styled(" dry │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("enter", Style::default().fg(theme::DANGER)),
+        Span::styled(" apply │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("q", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(" quit", Style::default().fg(theme::TEXT_MUTED)),
+    ]);
+    let title = Title::from(Line::from(vec![
+        Span::styled(" ⚙ ", Style::default().fg(theme::INFO)),
+        Span::styled("Status ", Style::default().fg(theme::INFO).bold()),
+    ]));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(title);
+    frame.render_widget(
+        Paragraph::new(vec![summary, Line::raw(""), help]).block(block),
+        area,
+    );
+}
+
+fn draw_output(frame: &mut ratatui::Frame<'_>, app: &mut DupesApp, area: Rect) {
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 📋 ", Style::default().fg(theme::ACCENT)),
+        Span::styled("Output ", Style::default().fg(theme::ACCENT).bold()),
+    ]));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(title);
+    let height = block.inner(area).height as usize;
+    // The totals are at the end.
+    let start = app.output_lines.len().saturating_sub(height);
+    let lines = app.output_lines[start..]
+        .iter()
+        .map(|line| {
+            let style = if line.starts_with("Set:") {
+                Style::default().fg(theme::ACCENT).bold()
+            } else if line.starts_with("  keep:") {
+                Style::default().fg(theme::TEXT_DIM)
+            } else if line.contains("Would free") {
+                Style::default().fg(theme::SUCCESS).bold()
+            } else {
+                Style::default().fg(theme::TEXT)
+            };
+            Line::styled(line.as_str(), style)
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_message(frame: &mut ratatui::Frame<'_>, app: &mut DupesApp, area: Rect) {
+    let (text, style) = if app.confirm_apply {
+        if app.dry_run {
+            (
+                "  ⚡ Run dry-run preview? (y/n)".to_string(),
+                Style::default().fg(theme::DRY_RUN),
+            )
+        } else {
+            (
+                format!(
+                    "  🗑  {} the checked copies? (y/n)",
+                    capitalize(app.action.as_str())
+                ),
+                Style::default().fg(theme::WARNING),
+            )
+        }
+    } else {
+        (
+            format!("  {}", app.message.clone().unwrap_or_default()),
+            Style::default().fg(theme::TEXT),
+        )
+    };
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 💬 ", Style::default().fg(theme::WARNING)),
+        Span::styled("Message ", Style::default().fg(theme::WARNING).bold()),
+    ]));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(title);
+    frame.render_widget(Paragraph::new(Line::styled(text, style)).block(block), area);
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
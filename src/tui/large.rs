@@ -0,0 +1,514 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use humansize::{format_size, BINARY};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    block::Title, Block, BorderType, Borders, List, ListItem, ListState, Paragraph,
+};
+use ratatui::Terminal;
+
+use super::{contains, restore_terminal, setup_terminal, symbols, theme};
+use crate::clean::{dry_run_output, write_dry_run_report};
+use crate::large::{selection_scan, LargeFile};
+use crate::snapshot::SnapshotSupport;
+
+pub struct RunConfig {
+    /// Largest first.
+    pub files: Vec<LargeFile>,
+    /// Move to the Trash instead of deleting.
+    pub trash: bool,
+    /// Only set when the provider works with the current privileges.
+    pub snapshot_support: Option<SnapshotSupport>,
+    pub dry_run: bool,
+    pub home: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum LargeExit {
+    Quit,
+    Apply {
+        files: Vec<LargeFile>,
+        trash: bool,
+        snapshot: bool,
+    },
+}
+
+/// Lists large files with a checkbox each; checked files are deleted or
+/// moved to the Trash.
+pub fn run(config: RunConfig) -> Result<LargeExit> {
+    let mut terminal = setup_terminal()?;
+    let mut app = LargeApp::new(config);
+    let exit = run_app(&mut terminal, &mut app);
+    restore_terminal(&mut terminal)?;
+    exit
+}
+
+struct LargeApp {
+    files: Vec<LargeFile>,
+    checked: Vec<bool>,
+    list_state: ListState,
+    list_area: Option<Rect>,
+    trash: bool,
+    snapshot_support: Option<SnapshotSupport>,
+    snapshot_enabled: bool,
+    dry_run: bool,
+    confirm_apply: bool,
+    message: Option<String>,
+    output_lines: Vec<String>,
+    home: PathBuf,
+}
+
+impl LargeApp {
+    fn new(config: RunConfig) -> Self {
+        let RunConfig {
+            files,
+            trash,
+            snapshot_support,
+            dry_run,
+            home,
+        } = config;
+        let mut list_state = ListState::default();
+        if !files.is_empty() {
+            list_state.select(Some(0));
+        }
+        Self {
+            checked: vec![false; files.len()],
+            message: Some(format!(
+                "{} large files; check the ones to remove",
+                files.len()
+            )),
+            files,
+            list_state,
+            list_area: None,
+            trash,
+            snapshot_support,
+            snapshot_enabled: false,
+            dry_run,
+            confirm_apply: false,
+            output_lines: Vec::new(),
+            home,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.files.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, self.files.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    fn toggle_at(&mut self, index: usize) {
+        if let Some(checked) = self.checked.get_mut(index) {
+            *checked = !*checked;
+        }
+    }
+
+    fn selected_files(&self) -> Vec<LargeFile> {
+        self.files
+            .iter()
+            .zip(&self.checked)
+            .filter(|(_, checked)| **checked)
+            .map(|(file, _)| file.clone())
+            .collect()
+    }
+
+    fn toggle_snapshot(&mut self) {
+        if self.snapshot_support.is_none() {
+            self.message = Some("No snapshot provider available here".to_string());
+            return;
+        }
+        if self.dry_run {
+            self.message = Some("Disable dry-run to use snapshots".to_string());
+            return;
+        }
+        self.snapshot_enabled = !self.snapshot_enabled;
+    }
+
+    fn toggle_dry_run(&mut self) {
+        self.dry_run = !self.dry_run;
+        if self.dry_run && self.snapshot_enabled {
+            self.snapshot_enabled = false;
+            self.message = Some("Dry-run enabled: snapshot disabled".to_string());
+        }
+    }
+
+    fn run_dry_run(&mut self) {
+        let scan = selection_scan(&self.selected_files());
+        let output = dry_run_output(std::slice::from_ref(&scan));
+        let mut lines = output
+            .details
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        match write_dry_run_report(&self.home, &output.details) {
+            Ok(path) => lines.push(format!("Dry-run report saved to {}", path.display())),
+            Err(err) => lines.push(format!("Failed to write dry-run report: {err}")),
+        }
+        if self.trash {
+            lines.push(format!(
+                "Would move {} to the Trash",
+                format_size(scan.bytes, BINARY)
+            ));
+        } else {
+            lines.push(format!("Would free {}", format_size(scan.bytes, BINARY)));
+        }
+        self.output_lines = lines;
+        self.message = Some("Dry-run complete (see Output panel)".to_string());
+    }
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut LargeApp,
+) -> Result<LargeExit> {
+    loop {
+        terminal.draw(|frame| draw_ui(frame, app))?;
+
+        if event::poll(Duration::from_millis(200))? {
+            let exit = match event::read()? {
+                Event::Key(key) => handle_key(app, key),
+                Event::Mouse(mouse) => {
+                    handle_mouse(app, mouse);
+                    None
+                }
+                _ => None,
+            };
+            if let Some(exit) = exit {
+                return Ok(exit);
+            }
+        }
+    }
+}
+
+fn handle_key(app: &mut LargeApp, key: KeyEvent) -> Option<LargeExit> {
+    if app.confirm_apply {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                app.confirm_apply = false;
+                if app.dry_run {
+                    app.run_dry_run();
+                    return None;
+                }
+                return Some(LargeExit::Apply {
+                    files: app.selected_files(),
+                    trash: app.trash,
+                    snapshot: app.snapshot_enabled,
+                });
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                app.confirm_apply = false;
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return Some(LargeExit::Quit),
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return Some(LargeExit::Quit);
+        }
+        KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+        KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+        KeyCode::PageDown => app.move_selection(10),
+        KeyCode::PageUp => app.move_selection(-10),
+        KeyCode::Char(' ') => {
+            if let Some(index) = app.list_state.selected() {
+                app.toggle_at(index);
+            }
+        }
+        KeyCode::Char('t') => {
+            app.trash = !app.trash;
+            app.message = Some(if app.trash {
+                "Checked files will be moved to the Trash".to_string()
+            } else {
+                "Checked files will be deleted".to_string()
+            });
+        }
+        KeyCode::Char('s') => app.toggle_snapshot(),
+        KeyCode::Char('d') => app.toggle_dry_run(),
+        KeyCode::Char('a') | KeyCode::Enter => {
+            if app.checked.iter().any(|checked| *checked) {
+                app.confirm_apply = true;
+            } else {
+                app.message = Some("No files checked".to_string());
+            }
+        }
+        _ => {}
+    }
+    None
+}
+
+fn handle_mouse(app: &mut LargeApp, mouse: MouseEvent) {
+    if app.confirm_apply {
+        return;
+    }
+    let Some(list_area) = app.list_area else {
+        return;
+    };
+    if !contains(list_area, mouse.column, mouse.row) {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollDown => app.move_selection(1),
+        MouseEventKind::ScrollUp => app.move_selection(-1),
+        MouseEventKind::Down(MouseButton::Left) => {
+            let index = app.list_state.offset() + (mouse.row.saturating_sub(list_area.y) as usize);
+            if index < app.files.len() {
+                app.list_state.select(Some(index));
+                app.toggle_at(index);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn draw_ui(frame: &mut ratatui::Frame<'_>, app: &mut LargeApp) {
+    let output_height = if app.output_lines.is_empty() { 0 } else { 10 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(5),
+            Constraint::Length(output_height),
+            Constraint::Length(3),
+        ])
+        .split(frame.size());
+
+    draw_list(frame, app, chunks[0]);
+    draw_status(frame, app, chunks[1]);
+    if !app.output_lines.is_empty() {
+        draw_output(frame, app, chunks[2]);
+    }
+    draw_message(frame, app, chunks[3]);
+}
+
+fn draw_list(frame: &mut ratatui::Frame<'_>, app: &mut LargeApp, area: Rect) {
+    let selected = app.list_state.selected();
+    let items = app
+        .files
+        .iter()
+        .zip(&app.checked)
+        .enumerate()
+        .map(|(idx, (file, checked))| {
+            let arrow = if selected == Some(idx) {
+                Span::styled(
+                    format!("{} ", symbols::ARROW_RIGHT),
+                    Style::default().fg(theme::HIGHLIGHT).bold(),
+                )
+            } else {
+                Span::raw("  ")
+            };
+            let (checkbox, style) = if *checked {
+                (
+                    Span::styled(
+                        format!("{} ", symbols::CHECK),
+                        Style::default().fg(theme::DANGER).bold(),
+                    ),
+                    Style::default().fg(theme::TEXT),
+                )
+            } else {
+                (
+                    Span::styled(
+                        format!("{} ", symbols::BULLET_EMPTY),
+                        Style::default().fg(theme::ENABLED),
+                    ),
+                    Style::default().fg(theme::TEXT_DIM),
+                )
+            };
+            ListItem::new(Line::from(vec![
+                arrow,
+                checkbox,
+                Span::styled(
+                    format!("{:>10}  ", format_size(file.size, BINARY)),
+                    Style::default().fg(theme::ACCENT).bold(),
+                ),
+                Span::styled(
+                    format!("{:<8}  ", file.type_label()),
+                    Style::default().fg(theme::INFO),
+                ),
+                Span::styled(
+                    format!("{}  ", file.last_used_label()),
+                    Style::default().fg(theme::TEXT_DIM),
+                ),
+                Span::styled(file.path.display().to_string(), style),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 📦 ", Style::default().fg(theme::ACCENT)),
+        Span::styled("Large Files ", Style::default().fg(theme::ACCENT).bold()),
+        Span::styled("by size ", Style::default().fg(theme::TEXT_DIM)),
+    ]));
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER_FOCUSED));
+    app.list_area = Some(block.inner(area));
+    let list = List::new(items).block(block);
+    frame.render_stateful_widget(list, area, &mut app.list_state);
+}
+
+fn draw_status(frame: &mut ratatui::Frame<'_>, app: &mut LargeApp, area: Rect) {
+    let (count, bytes) = app
+        .files
+        .iter()
+        .zip(&app.checked)
+        .filter(|(_, checked)| **checked)
+        .fold((0, 0), |(count, bytes), (file, _)| {
+            (count + 1, bytes + file.size)
+        });
+    let mut summary = vec![
+        Span::styled("  ✨ Checked: ", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(count.to_string(), Style::default().fg(theme::ACCENT).bold()),
+        Span::styled(" files │ ", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(
+            format_size(bytes, BINARY),
+            Style::default().fg(theme::SUCCESS).bold(),
+        ),
+        Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("Action: ", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(
+            if app.trash { "trash" } else { "delete" },
+            Style::default().fg(theme::INFO).bold(),
+        ),
+        Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("⚡ Dry-run: ", Style::default().fg(theme::TEXT_DIM)),
+        if app.dry_run {
+            Span::styled("ON", Style::default().fg(theme::DRY_RUN).bold())
+        } else {
+            Span::styled("OFF", Style::default().fg(theme::TEXT_DIM))
+        },
+    ];
+    if let Some(support) = &app.snapshot_support {
+        summary.push(Span::styled(" │ ", Style::default().fg(theme::TEXT_MUTED)));
+        summary.push(Span::styled(
+            "🛡 Snapshot: ",
+            Style::default().fg(theme::TEXT_DIM),
+        ));
+        summary.push(if app.snapshot_enabled {
+            Span::styled("ON", Style::default().fg(theme::SUCCESS).bold())
+        } else {
+            Span::styled("OFF", Style::default().fg(theme::TEXT_DIM))
+        });
+        summary.push(Span::styled(
+            format!(" ({})", support.label),
+            Style::default().fg(theme::TEXT_DIM).italic(),
+        ));
+    }
+    let mut help = vec![
+        Span::styled("  ↑↓", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(" move │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("space", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(" toggle │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("t", Style::default().fg(theme::INFO)),
+        Span::styled(" trash │ ", Style::default().fg(theme::TEXT_MUTED)),
+    ];
+    if app.snapshot_support.is_some() {
+        help.push(Span::styled("s", Style::default().fg(theme::SUCCESS)));
+        help.push(Span::styled(
+            " snapshot │ ",
+            Style::default().fg(theme::TEXT_MUTED),
+        ));
+    }
+    help.extend([
+        Span::styled("d", Style::default().fg(theme::DRY_RUN)),
+        Span::styled(" dry │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("enter", Style::default().fg(theme::DANGER)),
+        Span::styled(" apply │ ", Style::default().fg(theme::TEXT_MUTED)),
+        Span::styled("q", Style::default().fg(theme::TEXT_DIM)),
+        Span::styled(" quit", Style::default().fg(theme::TEXT_MUTED)),
+    ]);
+    let title = Title::from(Line::from(vec![
+        Span::styled(" ⚙ ", Style::default().fg(theme::INFO)),
+        Span::styled("Status ", Style::default().fg(theme::INFO).bold()),
+    ]));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(title);
+    frame.render_widget(
+        Paragraph::new(vec![Line::from(summary), Line::raw(""), Line::from(help)]).block(block),
+        area,
+    );
+}
+
+fn draw_output(frame: &mut ratatui::Frame<'_>, app: &mut LargeApp, area: Rect) {
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 📋 ", Style::default().fg(theme::ACCENT)),
+        Span::styled("Output ", Style::default().fg(theme::ACCENT).bold()),
+    ]));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(title);
+    let height = block.inner(area).height as usize;
+    // The totals are at the end.
+    let start = app.output_lines.len().saturating_sub(height);
+    let lines = app.output_lines[start..]
+        .iter()
+        .map(|line| {
+            let style = if line.starts_with("Rule:") {
+                Style::default().fg(theme::ACCENT).bold()
+            } else if line.starts_with("Would ") {
+                Style::default().fg(theme::SUCCESS).bold()
+            } else {
+                Style::default().fg(theme::TEXT)
+            };
+            Line::styled(line.as_str(), style)
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_message(frame: &mut ratatui::Frame<'_>, app: &mut LargeApp, area: Rect) {
+    let (text, style) = if app.confirm_apply {
+        if app.dry_run {
+            (
+                "  ⚡ Run dry-run preview? (y/n)".to_string(),
+                Style::default().fg(theme::DRY_RUN),
+            )
+        } else if app.trash {
+            (
+                "  🗑  Move the checked files to the Trash? (y/n)".to_string(),
+                Style::default().fg(theme::WARNING),
+            )
+        } else {
+            (
+                "  🗑  Delete the checked files? (y/n)".to_string(),
+                Style::default().fg(theme::WARNING),
+            )
+        }
+    } else {
+        (
+            format!("  {}", app.message.clone().unwrap_or_default()),
+            Style::default().fg(theme::TEXT),
+        )
+    };
+    let title = Title::from(Line::from(vec![
+        Span::styled(" 💬 ", Style::default().fg(theme::WARNING)),
+        Span::styled("Message ", Style::default().fg(theme::WARNING).bold()),
+    ]));
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(theme::BORDER))
+        .title(title);
+    frame.render_widget(Paragraph::new(Line::styled(text, style)).block(block), area);
+}
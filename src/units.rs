@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+/// Parses a human-readable size such as `500M`, `20GiB`, `1.5 TB` or `4096`.
+///
+/// Single-letter suffixes and `*iB` are binary (like `du`), `*B` suffixes are
+/// decimal.
+pub fn parse_size(input: &str) -> Result<u64> {
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(split);
+    let Ok(value) = number.parse::<f64>() else {
+        bail!("Invalid size '{input}'");
+    };
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => bail!("Invalid size '{input}' (use a suffix like K, MiB, GB)"),
+    };
+    Ok((value * multiplier as f64).round() as u64)
+}
+
+/// Parses a duration such as `30d`, `12h`, `2w` or `90m`.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(split);
+    let Ok(value) = number.parse::<u64>() else {
+        bail!("Invalid duration '{input}'");
+    };
+    let seconds: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("Invalid duration '{input}' (use a suffix like 12h, 30d, 2w)"),
+    };
+    let Some(total) = value.checked_mul(seconds) else {
+        bail!("Duration '{input}' is too long");
+    };
+    Ok(Duration::from_secs(total))
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM` (UTC).
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        rem / 3600,
+        (rem % 3600) / 60
+    )
+}
+
+/// Unix timestamp of a civil date and time (UTC).
+pub fn timestamp_from_civil(year: i64, month: u32, day: u32, hms: (u32, u32, u32)) -> u64 {
+    let days = days_from_civil(year, month, day);
+    let (hour, minute, second) = hms;
+    (days * 86_400) as u64 + (hour * 3600 + minute * 60 + second) as u64
+}
+
+// Howard Hinnant's algorithms for the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("500M").unwrap(), 500 << 20);
+        assert_eq!(parse_size("20GiB").unwrap(), 20 << 30);
+        assert_eq!(parse_size("1.5 gb").unwrap(), 1_500_000_000);
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("5 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86_400)
+        );
+        assert_eq!(
+            parse_duration("12h").unwrap(),
+            Duration::from_secs(12 * 3600)
+        );
+        assert_eq!(
+            parse_duration("2w").unwrap(),
+            Duration::from_secs(14 * 86_400)
+        );
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        let ts = timestamp_from_civil(2024, 2, 29, (13, 5, 9));
+        assert_eq!(ts, 1_709_211_909);
+        assert_eq!(format_timestamp(ts), "2024-02-29 13:05");
+    }
+}
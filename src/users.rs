@@ -0,0 +1,190 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::sysroot::SysRoot;
+
+const PASSWD_PATH: &str = "/etc/passwd";
+const LOGIN_DEFS_PATH: &str = "/etc/login.defs";
+const DEFAULT_UID_MIN: u32 = 1000;
+const DEFAULT_UID_MAX: u32 = 60000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserAccount {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+    pub shell: String,
+}
+
+impl UserAccount {
+    /// Accounts with a nologin/false shell are service accounts, not people.
+    pub fn has_login_shell(&self) -> bool {
+        !(self.shell.ends_with("/nologin") || self.shell.ends_with("/false"))
+    }
+}
+
+pub fn parse_passwd(content: &str) -> Vec<UserAccount> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.split(':');
+            let name = parts.next()?;
+            let _password = parts.next()?;
+            let uid = parts.next()?.parse().ok()?;
+            let gid = parts.next()?.parse().ok()?;
+            let _gecos = parts.next()?;
+            let home = parts.next()?;
+            let shell = parts.next().unwrap_or("");
+            Some(UserAccount {
+                name: name.to_string(),
+                uid,
+                gid,
+                home: PathBuf::from(home),
+                shell: shell.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Accounts of the system at `root`, with homes re-based onto it.
+pub fn read_passwd(root: &SysRoot) -> Result<Vec<UserAccount>> {
+    let path = root.join(Path::new(PASSWD_PATH))?;
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    // Homes that can't be resolved inside the root are left out.
+    Ok(parse_passwd(&content)
+        .into_iter()
+        .filter_map(|mut account| {
+            account.home = root.join(&account.home).ok()?;
+            Some(account)
+        })
+        .collect())
+}
+
+pub fn find_user<'a>(accounts: &'a [UserAccount], name: &str) -> Option<&'a UserAccount> {
+    accounts.iter().find(|account| account.name == name)
+}
+
+/// Regular (human) users: uid within `range`, a login shell and an existing home.
+pub fn real_users(accounts: &[UserAccount], range: &RangeInclusive<u32>) -> Vec<UserAccount> {
+    accounts
+        .iter()
+        .filter(|account| range.contains(&account.uid))
+        .filter(|account| account.has_login_shell())
+        .filter(|account| account.home.is_dir())
+        .cloned()
+        .collect()
+}
+
+/// The regular-user uid range from `/etc/login.defs`, falling back to 1000-60000.
+pub fn default_uid_range(root: &SysRoot) -> RangeInclusive<u32> {
+    let content = root
+        .join(Path::new(LOGIN_DEFS_PATH))
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .unwrap_or_default();
+    uid_range_from_login_defs(&content)
+}
+
+fn uid_range_from_login_defs(content: &str) -> RangeInclusive<u32> {
+    let mut min = DEFAULT_UID_MIN;
+    let mut max = DEFAULT_UID_MAX;
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(value) = value.parse() else {
+            continue;
+        };
+        match key {
+            "UID_MIN" => min = value,
+            "UID_MAX" => max = value,
+            _ => {}
+        }
+    }
+    min..=max
+}
+
+/// Parses `MIN-MAX` (either side may be omitted, e.g. `1000-`).
+pub fn parse_uid_range(input: &str) -> Result<RangeInclusive<u32>> {
+    let Some((min, max)) = input.split_once('-') else {
+        bail!("Invalid uid range '{input}' (expected MIN-MAX)");
+    };
+    let min = if min.trim().is_empty() {
+        0
+    } else {
+        min.trim().parse().context("Invalid minimum uid")?
+    };
+    let max = if max.trim().is_empty() {
+        u32::MAX
+    } else {
+        max.trim().parse().context("Invalid maximum uid")?
+    };
+    if min > max {
+        bail!("Invalid uid range '{input}' (minimum is above maximum)");
+    }
+    Ok(min..=max)
+}
+
+pub fn home_for(accounts: &[UserAccount], name: &str) -> Option<PathBuf> {
+    find_user(accounts, name).map(|account| account.home.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str = "\
+root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+# comment
+alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh
+bob:x:1001:1001::/home/bob:/bin/bash
+svc:x:1002:1002::/var/lib/svc:/bin/false
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+";
+
+    #[test]
+    fn test_parse_passwd() {
+        let accounts = parse_passwd(PASSWD);
+        assert_eq!(accounts.len(), 6);
+        let alice = find_user(&accounts, "alice").unwrap();
+        assert_eq!(alice.uid, 1000);
+        assert_eq!(alice.home, PathBuf::from("/home/alice"));
+        assert!(alice.has_login_shell());
+        assert!(!find_user(&accounts, "svc").unwrap().has_login_shell());
+        assert_eq!(home_for(&accounts, "bob"), Some(PathBuf::from("/home/bob")));
+    }
+
+    #[test]
+    fn test_read_passwd_under_root() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("etc")).unwrap();
+        fs::write(dir.path().join("etc/passwd"), PASSWD).unwrap();
+        fs::write(dir.path().join("etc/login.defs"), "UID_MIN 2000\n").unwrap();
+        let root = SysRoot::new(dir.path()).unwrap();
+
+        let accounts = read_passwd(&root).unwrap();
+        assert_eq!(
+            home_for(&accounts, "alice"),
+            Some(root.path().join("home/alice"))
+        );
+        assert_eq!(default_uid_range(&root), 2000..=60000);
+    }
+
+    #[test]
+    fn test_uid_ranges() {
+        let defs = "# comment\nUID_MIN\t\t 500\nUID_MAX 29999\nGID_MIN 500\n";
+        assert_eq!(uid_range_from_login_defs(defs), 500..=29999);
+        assert_eq!(uid_range_from_login_defs(""), 1000..=60000);
+        assert_eq!(parse_uid_range("1000-2000").unwrap(), 1000..=2000);
+        assert_eq!(parse_uid_range("1000-").unwrap(), 1000..=u32::MAX);
+        assert!(parse_uid_range("2000-1000").is_err());
+        assert!(parse_uid_range("1000").is_err());
+    }
+}